@@ -1,7 +1,7 @@
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
-use ungoliant::identifiers::FastText;
+use ungoliant::identifiers::{FastText, FastTextBuilder, Predict};
 pub fn fasttext_benchmark(c: &mut Criterion) {
-    let cls = FastText::new_lid().unwrap();
+    let cls: FastText = FastTextBuilder::default().build().unwrap();
     let dummy = "This is a completely innocent phrase 
     This is a completely innocent phrase 
     This is a completely innocent phrase 
@@ -20,6 +20,17 @@ pub fn fasttext_benchmark(c: &mut Criterion) {
     This is a completely innocent phrase 
     This is a completely innocent phrase ";
     c.bench_function("fasttext", |b| b.iter(|| cls.predict(black_box(dummy))));
+    c.bench_function("fasttext lines", |b| {
+        b.iter(|| {
+            black_box(dummy)
+                .lines()
+                .map(|line| cls.predict_one(&line.replace(char::from(0), "")))
+                .collect::<Vec<_>>()
+        })
+    });
+    c.bench_function("fasttext weighted_ids", |b| {
+        b.iter(|| cls.weighted_ids(black_box(dummy).lines()))
+    });
 }
 
 criterion_group!(benches, fasttext_benchmark);
@@ -1,7 +1,7 @@
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
 use itertools::Itertools;
 use rayon::prelude::*;
-use ungoliant::identifiers::FastText;
+use ungoliant::identifiers::{FastTextBuilder, Predict};
 use ungoliant::sources::commoncrawl::Wet;
 use warc::{BufferedBody, Record, WarcHeader};
 
@@ -24,7 +24,7 @@ use warc::{BufferedBody, Record, WarcHeader};
 pub fn pipeline_full_sequential_benchmark(c: &mut Criterion) {
     fn parse_headers() {
         let _lang_tag = WarcHeader::Unknown("warc-identified-content-language".to_string());
-        let cls = FastText::new_lid().unwrap();
+        let cls = FastTextBuilder::default().build().unwrap();
         let results = std::fs::read_dir("results/")
             .unwrap()
             .map(|d| Wet::from_path_gzip(d.unwrap().path()).unwrap());
@@ -3,6 +3,10 @@ use std::path::PathBuf;
 
 use structopt::StructOpt;
 
+use crate::filtering::record::FilterKind;
+use crate::identifiers::MultilingualKind;
+use crate::transformers::{BoilerplateMode, PiiMode, TrimmingKind};
+
 #[derive(Debug, StructOpt)]
 #[structopt(name = "ungoliant", about = "corpus generation tool.")]
 /// Holds every command that is callable by the `oscar-tools` command.
@@ -10,7 +14,7 @@ pub enum Ungoliant {
     #[structopt(about = "Download a CommonCrawl release")]
     Download(Download),
     #[structopt(about = "Run pipeline")]
-    Pipeline(Pipeline),
+    Pipeline(Box<Pipeline>),
     // #[structopt(about = "Deduplicate a generated, not split corpus.")]
     // Dedup(Dedup),
     // #[structopt(about = "Split a not split corpus")]
@@ -21,6 +25,14 @@ pub enum Ungoliant {
     // Package(Package),
     #[structopt(about = "Rebuild the corpus for a given language.")]
     Rebuild(Rebuild),
+    #[structopt(about = "Evaluate a language identification model on labelled data.")]
+    EvalLid(EvalLid),
+    #[structopt(about = "Learn per-language sentence length distributions on a sample of shards.")]
+    CalibrateLengths(CalibrateLengths),
+    #[structopt(about = "Count line frequencies on shards, for boilerplate removal.")]
+    CountLines(CountLines),
+    #[structopt(about = "Compute per-language perplexity cutoffs on a sample of a scored corpus.")]
+    CalibratePerplexity(CalibratePerplexity),
     //#[structopt(about = "check for corpus validity. This is under construction and shouldn't be used. ")]
     //Check(Check),
 }
@@ -44,6 +56,126 @@ pub struct Rebuild {
     pub lang: String,
 }
 #[derive(Debug, StructOpt)]
+/// Language identification evaluation command and parameters.
+pub struct EvalLid {
+    #[structopt(parse(from_os_str), help = "Path to the fastText model")]
+    pub lid_path: PathBuf,
+    #[structopt(
+        parse(from_os_str),
+        help = "Labelled TSV file (one `<gold label>\\t<text>` pair per line)"
+    )]
+    pub src: PathBuf,
+    #[structopt(
+        long = "documents",
+        help = "Evaluate documents (newlines escaped as `\\n`) rather than lines."
+    )]
+    pub documents: bool,
+    #[structopt(
+        long = "threshold",
+        help = "Line-level confidence threshold of the model. Defaults to the pipeline one (lower it to sweep lower thresholds).",
+        default_value = "0.8"
+    )]
+    pub threshold: f32,
+    #[structopt(
+        long = "trimming",
+        help = "Head/foot trimming strategy of documents (see pipeline --trimming).",
+        default_value = "short_sentences"
+    )]
+    pub trimming: TrimmingKind,
+    #[structopt(
+        long = "multilingual",
+        help = "Multilingual detector of documents (see pipeline --multilingual).",
+        default_value = "strict"
+    )]
+    pub multilingual: MultilingualKind,
+    #[structopt(
+        long = "bins",
+        help = "Number of bins of the reliability curve.",
+        default_value = "10"
+    )]
+    pub bins: usize,
+    #[structopt(
+        parse(from_os_str),
+        long = "lid-labels-map",
+        help = "Optional path to a label mapping file (see pipeline --lid-labels-map)."
+    )]
+    pub lid_labels_map: Option<PathBuf>,
+}
+#[derive(Debug, StructOpt)]
+/// Sentence length calibration command and parameters.
+pub struct CalibrateLengths {
+    #[structopt(parse(from_os_str), help = "source (contains n.txt.gz)")]
+    pub src: PathBuf,
+    #[structopt(parse(from_os_str), help = "length distributions destination (json)")]
+    pub dst: PathBuf,
+    #[structopt(
+        parse(from_os_str),
+        long = "lid-path",
+        help = "Path to 176.lid.bin",
+        default_value = "lid.176.bin"
+    )]
+    pub lid_path: PathBuf,
+    #[structopt(
+        parse(from_os_str),
+        long = "lid-labels-map",
+        help = "Optional path to a label mapping file (see pipeline --lid-labels-map)."
+    )]
+    pub lid_labels_map: Option<PathBuf>,
+    #[structopt(
+        long = "shards",
+        help = "Number of shards to calibrate on.",
+        default_value = "10"
+    )]
+    pub nb_shards: usize,
+}
+#[derive(Debug, StructOpt)]
+/// Perplexity cutoffs calibration command and parameters.
+pub struct CalibratePerplexity {
+    #[structopt(
+        parse(from_os_str),
+        help = "source corpus (contains <lang>_meta.jsonl files with perplexity scores, see pipeline --quality-kenlms-path)"
+    )]
+    pub src: PathBuf,
+    #[structopt(parse(from_os_str), help = "perplexity cutoffs destination (json)")]
+    pub dst: PathBuf,
+    #[structopt(
+        long = "sample",
+        help = "Maximum number of documents per language to compute cutoffs on (reservoir-sampled with a fixed seed).",
+        default_value = "10000"
+    )]
+    pub sample: usize,
+}
+#[derive(Debug, StructOpt)]
+/// Line frequency counting command and parameters.
+pub struct CountLines {
+    #[structopt(parse(from_os_str), help = "source (contains n.txt.gz)")]
+    pub src: PathBuf,
+    #[structopt(parse(from_os_str), help = "line frequencies destination (json)")]
+    pub dst: PathBuf,
+    #[structopt(
+        long = "shards",
+        help = "Number of shards to count lines on (all of them if unset)."
+    )]
+    pub nb_shards: Option<usize>,
+    #[structopt(
+        long = "per-domain",
+        help = "Count lines per domain rather than globally."
+    )]
+    pub per_domain: bool,
+    #[structopt(
+        long = "min-count",
+        help = "Discard lines appearing in less documents.",
+        default_value = "10"
+    )]
+    pub min_count: u32,
+    #[structopt(
+        long = "max-lines",
+        help = "Maximum number of distinct lines kept in memory (about 20 bytes each). Least frequent ones are dropped beyond, which can make counts underestimated.",
+        default_value = "50000000"
+    )]
+    pub max_lines: usize,
+}
+#[derive(Debug, StructOpt)]
 /// Dedup command and parameters.
 pub struct Dedup {
     #[structopt(parse(from_os_str), help = "source corpus location")]
@@ -164,12 +296,29 @@ pub struct Pipeline {
         default_value = "lid.176.bin"
     )]
     pub lid_path: PathBuf,
+    #[structopt(
+        parse(from_os_str),
+        long = "lid-labels-map",
+        help = "Optional path to a label mapping file (one `<model label> <bcp47 tag>` pair per line). Replaces the default lid.176/NLLB mapping."
+    )]
+    pub lid_labels_map: Option<PathBuf>,
     #[structopt(
         parse(from_os_str),
         long = "blocklist-path",
         help = "Optional path to blocklist."
     )]
     pub blocklist: Option<PathBuf>,
+    #[structopt(
+        long = "blocklist-category",
+        help = "Only use this category of the blocklist (ex. adult) rather than all of them."
+    )]
+    pub blocklist_category: Option<String>,
+    #[structopt(
+        parse(from_os_str),
+        long = "allowlist",
+        help = "Optional path to an allowlist (one domain or URL per line). Allowed domains, their subdomains and allowed URLs are never flagged by the blocklist."
+    )]
+    pub allowlist: Option<PathBuf>,
 
     #[structopt(
         parse(from_os_str),
@@ -178,10 +327,172 @@ pub struct Pipeline {
     )]
     pub domain_blocklists: Option<Vec<PathBuf>>,
 
+    #[structopt(
+        parse(from_os_str),
+        long = "content-lexicons",
+        help = "Optional path to per-category, per-language lexicons (<category>/<lang>.txt). Scores content in `score:content:<category>` fields and adds `content:<category>` categories above --content-threshold."
+    )]
+    pub content_lexicons: Option<PathBuf>,
+    #[structopt(
+        long = "content-threshold",
+        help = "Minimum lexicon score (0 to 1, not a probability) for a category to be added.",
+        default_value = "0.5"
+    )]
+    pub content_threshold: f32,
+    #[structopt(
+        long = "content-midpoint",
+        help = "Weighted lexicon matches per word that score 0.5.",
+        default_value = "0.01"
+    )]
+    pub content_midpoint: f32,
+
     #[structopt(
         parse(from_os_str),
         long = "kenlms-path",
-        help = "Optional path to kenlm folder. for the language xx, you have to have a xx.binary file."
+        help = "Optional path to kenlm folder. for the language xx, you have to have a xx.binary (kenlm feature) or xx.arpa file."
     )]
     pub kenlms_path: Option<PathBuf>,
+    #[structopt(
+        parse(from_os_str),
+        long = "quality-kenlms-path",
+        help = "Optional path to quality kenlm folder (one xx.binary or xx.arpa file per language, see --kenlms-path). Records document perplexity in the `score:perplexity` field."
+    )]
+    pub quality_kenlms_path: Option<PathBuf>,
+    #[structopt(
+        long = "kenlms-max-loaded",
+        help = "Maximum number of KenLMs kept in memory (per KenLM folder). Least recently used ones are unloaded first."
+    )]
+    pub kenlms_max_loaded: Option<usize>,
+    #[structopt(
+        long = "kenlms-max-mb",
+        help = "Maximum size (in MB, estimated from loaded n-grams, or from model files for KenLM-backed models) of KenLMs kept in memory (per KenLM folder)."
+    )]
+    pub kenlms_max_mb: Option<u64>,
+    #[structopt(
+        long = "kenlms-appendable",
+        help = "Look for KenLMs of new languages in KenLM folders while running (models can be added without restarting)."
+    )]
+    pub kenlms_appendable: bool,
+    #[structopt(
+        parse(from_os_str),
+        long = "perplexity-cutoffs",
+        help = "Optional path to perplexity cutoffs (see calibrate-perplexity). Adds head/middle/tail buckets in the `pp_bucket` field."
+    )]
+    pub perplexity_cutoffs: Option<PathBuf>,
+
+    #[structopt(
+        long = "filter",
+        help = "Record filter expression. Filters: pfilter[=<threshold>], min_length=<n>, max_length=<n>, non_letter=<max ratio>, url=<pattern>, stop_words=<path>:<min ratio>. Combine with and(<filter>,...) / or(<filter>,...).",
+        default_value = "pfilter"
+    )]
+    pub filter: FilterKind,
+
+    #[structopt(
+        long = "trimming",
+        help = "Head/foot trimming strategy (short_sentences, conv or conv=<odd window size>).",
+        default_value = "short_sentences"
+    )]
+    pub trimming: TrimmingKind,
+
+    #[structopt(
+        parse(from_os_str),
+        long = "line-frequencies",
+        help = "Optional path to line frequencies (see count-lines). Removes or annotates lines that appear in many documents."
+    )]
+    pub line_frequencies: Option<PathBuf>,
+    #[structopt(
+        long = "boilerplate-threshold",
+        help = "Number of documents a line has to appear in to be considered boilerplate.",
+        default_value = "10"
+    )]
+    pub boilerplate_threshold: u32,
+    #[structopt(
+        long = "boilerplate-mode",
+        help = "What to do with boilerplate lines (remove or annotate).",
+        default_value = "remove"
+    )]
+    pub boilerplate_mode: BoilerplateMode,
+
+    #[structopt(
+        parse(from_os_str),
+        long = "length-stats",
+        help = "Optional path to sentence length distributions (see calibrate-lengths). Flags lines whose length is an outlier for their language."
+    )]
+    pub length_stats: Option<PathBuf>,
+    #[structopt(
+        long = "length-stats-std",
+        help = "Number of standard deviations from the mean length before a line is an outlier.",
+        default_value = "2.0"
+    )]
+    pub length_stats_std: f64,
+
+    #[structopt(
+        long = "repair-unicode",
+        help = "Repair mojibake and control characters and normalize content to NFC before identification. Repairs are counted in `unicode:<repair>` fields."
+    )]
+    pub repair_unicode: bool,
+
+    #[structopt(
+        long = "url-metadata",
+        help = "Add registered domain, TLD, ccTLD, path depth and listing page fields (`url:<field>`) from document URLs."
+    )]
+    pub url_metadata: bool,
+
+    #[structopt(
+        long = "pii",
+        help = "Count PII (emails, phone numbers, IP addresses, IBANs, credit card numbers, URL credentials) in `pii:<kind>` fields (annotate), and replace it by placeholders (redact)."
+    )]
+    pub pii: Option<PiiMode>,
+
+    #[structopt(
+        long = "quality-warnings",
+        help = "Add Gopher/C4-style heuristic quality warnings (punctuation, bullets, ellipsis, symbols, duplicates, n-gram repetition, boilerplate markers)."
+    )]
+    pub quality_warnings: bool,
+
+    #[structopt(
+        parse(from_os_str),
+        long = "quality-thresholds",
+        help = "Optional path to JSON thresholds of the quality warnings (implies --quality-warnings). Missing thresholds keep their default value."
+    )]
+    pub quality_thresholds: Option<PathBuf>,
+
+    #[structopt(
+        long = "scores",
+        help = "Record raw annotator scores (letter ratio, short lines fraction, header/footer short lines, line count) in `score:<name>` metadata fields."
+    )]
+    pub scores: bool,
+
+    #[structopt(
+        parse(from_os_str),
+        long = "removal-rules",
+        help = "Optional path to a JSON removal policy (keep/drop/divert rules over annotations, categories, harmful_pp, confidence and length). Defaults to dropping noisy+tiny documents."
+    )]
+    pub removal_rules: Option<PathBuf>,
+
+    #[structopt(
+        parse(from_os_str),
+        long = "rejected-dir",
+        help = "Optional folder where rejected records are written as JSONL, along with the rejection stage and reason."
+    )]
+    pub rejected_dir: Option<PathBuf>,
+    #[structopt(
+        long = "rejected-sample",
+        help = "Probability of a rejected record being written in the rejected folder.",
+        default_value = "1.0"
+    )]
+    pub rejected_sample: f64,
+
+    #[structopt(
+        long = "multilingual",
+        help = "Multilingual detector (strict or ratio).",
+        default_value = "strict"
+    )]
+    pub multilingual: MultilingualKind,
+
+    #[structopt(
+        long = "split-languages",
+        help = "Emit blocks of lines in another language that are at least <split-languages> bytes long as separate documents. Their lines are removed from the parent document."
+    )]
+    pub split_languages: Option<usize>,
 }
@@ -0,0 +1,262 @@
+//! Corpus-wide line frequencies.
+//!
+//! Boilerplate (cookie banners, navigation menus, footers) recurs across the pages of a same site.
+//! [LineFrequencies] counts, for each domain (or globally), the number of documents in which each line appears.
+//!
+//! Lines are normalized (see [normalize]) then hashed, so that only hashes are kept in memory and on disk.
+//! Memory can be bounded by dropping the least frequent lines (see [LineFrequencies::shrink]).
+//! Frequencies are learnt on a first pass (see [crate::processing::line_frequency]),
+//! saved as JSON and then used frozen in the pipeline (see [crate::transformers::Boilerplate]).
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::File;
+use std::hash::Hasher;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use twox_hash::XxHash64;
+
+use crate::error::Error;
+
+/// Key used when frequencies are not counted per domain.
+const GLOBAL_KEY: &str = "*";
+
+/// Estimated memory usage of a counted line: hash, count and hash map overhead.
+const LINE_SIZE: usize = std::mem::size_of::<(u64, u32)>() + 8;
+
+/// Normalize a line before hashing: lowercase, digits replaced by `0` and whitespace collapsed.
+///
+/// This way, lines only differing by dates or counters (ex. `© 2021 example.com`) are considered equal.
+pub fn normalize(line: &str) -> String {
+    line.split_whitespace()
+        .map(|word| {
+            word.chars()
+                .flat_map(char::to_lowercase)
+                .map(|c| if c.is_numeric() { '0' } else { c })
+                .collect::<String>()
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Hash a line after normalization. Returns [None] for blank lines.
+pub fn hash_line(line: &str) -> Option<u64> {
+    let normalized = normalize(line);
+    if normalized.is_empty() {
+        return None;
+    }
+
+    let mut hasher = XxHash64::with_seed(0);
+    hasher.write(normalized.as_bytes());
+    Some(hasher.finish())
+}
+
+/// Get the domain of a record from its URL.
+pub fn domain(url: &str) -> Option<String> {
+    url::Url::parse(url)
+        .ok()
+        .and_then(|url| url.host_str().map(|host| host.to_string()))
+}
+
+/// Number of documents in which each (hashed) line appears, per domain or globally.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LineFrequencies {
+    per_domain: bool,
+    counts: HashMap<String, HashMap<u64, u32>>,
+}
+
+impl LineFrequencies {
+    /// Create empty frequencies, counting per domain if `per_domain` is set.
+    pub fn new(per_domain: bool) -> Self {
+        Self {
+            per_domain,
+            counts: HashMap::new(),
+        }
+    }
+
+    /// Get the key under which lines of a document from `domain` are counted.
+    fn key<'a>(&self, domain: Option<&'a str>) -> &'a str {
+        if self.per_domain {
+            domain.unwrap_or(GLOBAL_KEY)
+        } else {
+            GLOBAL_KEY
+        }
+    }
+
+    /// Count the lines of a document. Lines appearing several times in the document are counted once.
+    pub fn count_mut<'a>(&mut self, domain: Option<&str>, lines: impl Iterator<Item = &'a str>) {
+        let hashes: HashSet<u64> = lines.filter_map(hash_line).collect();
+        let key = self.key(domain).to_string();
+        let counts = self.counts.entry(key).or_default();
+
+        for hash in hashes {
+            *counts.entry(hash).or_insert(0) += 1;
+        }
+    }
+
+    /// Merge other frequencies into these ones.
+    pub fn merge(&mut self, other: Self) {
+        for (key, other_counts) in other.counts {
+            let counts = self.counts.entry(key).or_default();
+            for (hash, count) in other_counts {
+                *counts.entry(hash).or_insert(0) += count;
+            }
+        }
+    }
+
+    /// Remove lines that appear in less than `min_count` documents, since they can't be used as boilerplate.
+    pub fn prune(&mut self, min_count: u32) {
+        for counts in self.counts.values_mut() {
+            counts.retain(|_, count| *count >= min_count);
+            counts.shrink_to_fit();
+        }
+        self.counts.retain(|_, counts| !counts.is_empty());
+    }
+
+    /// Drop the least frequent lines, so that at most `max_lines` lines are counted.
+    ///
+    /// Lines appearing in `n` documents or less are dropped, `n` being as small as possible.
+    /// Returns `n` (`0` if no line is dropped).
+    /// Dropped lines can be counted again afterwards, so their counts are underestimated by at most `n`
+    /// (by the sum of the returned values if shrinking several times).
+    pub fn shrink(&mut self, max_lines: usize) -> u32 {
+        if self.nb_lines() <= max_lines {
+            return 0;
+        }
+
+        // number of lines by count
+        let mut histogram: BTreeMap<u32, usize> = BTreeMap::new();
+        for count in self.counts.values().flat_map(HashMap::values) {
+            *histogram.entry(*count).or_default() += 1;
+        }
+
+        // keep the most frequent lines while there's room
+        let mut kept = 0;
+        let mut n = 0;
+        for (count, nb_lines) in histogram.iter().rev() {
+            if kept + nb_lines > max_lines {
+                n = *count;
+                break;
+            }
+            kept += nb_lines;
+        }
+
+        self.prune(n + 1);
+        n
+    }
+
+    /// Get the estimated memory usage of the counts, in bytes.
+    pub fn memory_size(&self) -> usize {
+        self.counts
+            .iter()
+            .map(|(key, counts)| key.capacity() + counts.capacity() * LINE_SIZE)
+            .sum()
+    }
+
+    /// Get the number of documents in which `line` appears.
+    pub fn frequency(&self, domain: Option<&str>, line: &str) -> u32 {
+        hash_line(line)
+            .and_then(|hash| {
+                self.counts
+                    .get(self.key(domain))
+                    .and_then(|counts| counts.get(&hash))
+            })
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Get the number of distinct lines that are counted.
+    pub fn nb_lines(&self) -> usize {
+        self.counts.values().map(|counts| counts.len()).sum()
+    }
+
+    /// Load frequencies from a JSON file.
+    pub fn from_path(path: &Path) -> Result<Self, Error> {
+        let f = File::open(path)?;
+        Ok(serde_json::from_reader(f)?)
+    }
+
+    /// Save frequencies into a JSON file.
+    pub fn to_path(&self, path: &Path) -> Result<(), Error> {
+        let f = File::create(path)?;
+        serde_json::to_writer(f, self)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize("  © 2021   Example.com "), "© 0000 example.com");
+        assert_eq!(hash_line("Accept cookies"), hash_line("accept  COOKIES"));
+        assert_eq!(hash_line("   "), None);
+    }
+
+    #[test]
+    fn test_count() {
+        let mut f = LineFrequencies::new(true);
+        for _ in 0..3 {
+            f.count_mut(Some("a.com"), "menu\nmenu\ncontent".lines());
+        }
+        f.count_mut(Some("b.com"), "menu\nother content".lines());
+
+        // once per document
+        assert_eq!(f.frequency(Some("a.com"), "menu"), 3);
+        assert_eq!(f.frequency(Some("b.com"), "menu"), 1);
+        assert_eq!(f.frequency(Some("c.com"), "menu"), 0);
+
+        f.prune(2);
+        assert_eq!(f.frequency(Some("b.com"), "menu"), 0);
+        assert_eq!(f.nb_lines(), 2);
+    }
+
+    #[test]
+    fn test_shrink() {
+        let mut f = LineFrequencies::new(false);
+        for _ in 0..4 {
+            f.count_mut(None, "menu\nfooter".lines());
+            f.count_mut(None, "footer\nsidebar".lines());
+        }
+        f.count_mut(None, "rare".lines());
+        assert_eq!(f.shrink(10), 0);
+        assert!(f.memory_size() > 0);
+
+        // footer: 8, menu: 4, sidebar: 4, rare: 1
+        assert_eq!(f.shrink(3), 1);
+        assert_eq!(f.nb_lines(), 3);
+        assert_eq!(f.shrink(2), 4);
+        assert_eq!(f.nb_lines(), 1);
+        assert_eq!(f.frequency(None, "footer"), 8);
+        assert_eq!(f.frequency(None, "menu"), 0);
+    }
+
+    #[test]
+    fn test_global_merge_ser() {
+        let mut f = LineFrequencies::new(false);
+        f.count_mut(Some("a.com"), "menu".lines());
+        let mut f2 = LineFrequencies::new(false);
+        f2.count_mut(Some("b.com"), "Menu".lines());
+        f.merge(f2);
+
+        assert_eq!(f.frequency(None, "menu"), 2);
+        assert_eq!(f.frequency(Some("c.com"), "menu"), 2);
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("frequencies.json");
+        f.to_path(&path).unwrap();
+        let f2 = LineFrequencies::from_path(&path).unwrap();
+        assert_eq!(f2.frequency(None, "menu"), 2);
+        assert!(!f2.per_domain);
+    }
+
+    #[test]
+    fn test_domain() {
+        assert_eq!(
+            domain("https://www.example.com/foo?bar"),
+            Some("www.example.com".to_string())
+        );
+        assert_eq!(domain("not a url"), None);
+    }
+}
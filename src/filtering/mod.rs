@@ -10,6 +10,8 @@ Both can be implemented for a given filter,
 in order to provide a mutable detection that could be used to "train" the filter, then an immutable one to effectively filter content.
 !*/
 mod filter;
+pub mod line_frequency;
+pub mod perplexity;
 pub mod record;
 pub mod sentence;
 
@@ -0,0 +1,200 @@
+//! Perplexity-based quality buckets.
+//!
+//! As in CCNet, documents are scored by a per-language "quality" language model (see `QualityModel`, behind the `kenlm` feature):
+//! each line is scored, and the document perplexity is computed from the line scores (see [doc_perplexity]).
+//!
+//! Documents are then split in head/middle/tail [Bucket]s, using per-language percentile cutoffs
+//! computed on a sample of scored documents (see [crate::processing::perplexity_calibration]).
+//! Cutoffs are saved as JSON and then used frozen in the pipeline.
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+use crate::pipelines::oscardoc::types::Document;
+
+/// Percentile of the perplexity under which documents are in the head bucket.
+pub const HEAD_PERCENTILE: f32 = 30.0;
+/// Percentile of the perplexity above which documents are in the tail bucket.
+pub const TAIL_PERCENTILE: f32 = 60.0;
+
+/// Name of the score holding the document perplexity (see [Document::add_score]).
+pub const PERPLEXITY_SCORE: &str = "perplexity";
+/// Key of the field holding the document bucket.
+pub const BUCKET_FIELD: &str = "pp_bucket";
+
+/// Get the perplexity from a `log10` probability over `nb_tokens` tokens.
+pub fn perplexity(log_score: f32, nb_tokens: usize) -> f32 {
+    10f32.powf(-log_score / nb_tokens.max(1) as f32)
+}
+
+/// Get the perplexity of a document from the `(log10 probability, number of tokens)` of its lines.
+///
+/// This is the perplexity of the whole document, with each line scored as a sentence.
+/// Returns [None] if there are no tokens.
+pub fn doc_perplexity(line_scores: &[(f32, usize)]) -> Option<f32> {
+    let (log_score, nb_tokens) = line_scores.iter().fold(
+        (0f32, 0usize),
+        |(score, tokens), (line_score, line_tokens)| (score + line_score, tokens + line_tokens),
+    );
+
+    (nb_tokens > 0).then(|| perplexity(log_score, nb_tokens))
+}
+
+/// Quality bucket of a document. Lower perplexity means closer to the model's training data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bucket {
+    Head,
+    Middle,
+    Tail,
+}
+
+impl fmt::Display for Bucket {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Head => "head",
+            Self::Middle => "middle",
+            Self::Tail => "tail",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// Get the `percentile`th percentile of sorted values, using the nearest rank.
+fn percentile(sorted: &[f32], percentile: f32) -> Option<f32> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let rank = (percentile / 100.0 * sorted.len() as f32).ceil() as usize;
+    Some(sorted[rank.clamp(1, sorted.len()) - 1])
+}
+
+/// Per-language perplexity cutoffs: `(head, tail)`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PerplexityCutoffs {
+    cutoffs: HashMap<String, (f32, f32)>,
+}
+
+impl PerplexityCutoffs {
+    /// Compute cutoffs from samples of document perplexities, by language.
+    pub fn from_samples(samples: HashMap<String, Vec<f32>>) -> Self {
+        let cutoffs = samples
+            .into_iter()
+            .filter_map(|(lang, mut perplexities)| {
+                perplexities.retain(|pp| pp.is_finite());
+                perplexities.sort_by(f32::total_cmp);
+                let head = percentile(&perplexities, HEAD_PERCENTILE)?;
+                let tail = percentile(&perplexities, TAIL_PERCENTILE)?;
+                Some((lang, (head, tail)))
+            })
+            .collect();
+
+        Self { cutoffs }
+    }
+
+    /// Get the `(head, tail)` cutoffs of a language.
+    pub fn get(&self, lang: &str) -> Option<(f32, f32)> {
+        self.cutoffs.get(lang).copied()
+    }
+
+    /// Iterate over languages and their cutoffs.
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &(f32, f32))> {
+        self.cutoffs.iter()
+    }
+
+    /// Get the bucket of a perplexity. Returns [None] if there are no cutoffs for `lang`.
+    pub fn bucket(&self, lang: &str, perplexity: f32) -> Option<Bucket> {
+        self.get(lang).map(|(head, tail)| {
+            if perplexity <= head {
+                Bucket::Head
+            } else if perplexity <= tail {
+                Bucket::Middle
+            } else {
+                Bucket::Tail
+            }
+        })
+    }
+
+    /// Add the `pp_bucket` field, using the `score:perplexity` field.
+    ///
+    /// Documents without perplexity or languages without cutoffs are left untouched.
+    pub fn add_to(&self, lang: &str, doc: &mut Document) {
+        let bucket = doc
+            .score(PERPLEXITY_SCORE)
+            .and_then(|perplexity| self.bucket(lang, perplexity as f32));
+
+        if let Some(bucket) = bucket {
+            doc.add_field(BUCKET_FIELD, bucket.to_string());
+        }
+    }
+
+    /// Load cutoffs from a JSON file.
+    pub fn from_path(path: &Path) -> Result<Self, Error> {
+        let f = File::open(path)?;
+        Ok(serde_json::from_reader(f)?)
+    }
+
+    /// Save cutoffs in a JSON file.
+    pub fn to_path(&self, path: &Path) -> Result<(), Error> {
+        let f = File::create(path)?;
+        serde_json::to_writer(f, self)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pipelines::oscardoc::types::Metadata;
+
+    use super::*;
+
+    #[test]
+    fn test_doc_perplexity() {
+        assert_eq!(perplexity(-2.0, 2), 10.0);
+        assert_eq!(doc_perplexity(&[]), None);
+
+        // lines are weighted by their number of tokens
+        let pp = doc_perplexity(&[(-1.0, 1), (-5.0, 5)]).unwrap();
+        assert!((pp - 10.0).abs() < 1e-4);
+        let pp = doc_perplexity(&[(-1.0, 2), (-4.0, 2)]).unwrap();
+        assert!((pp - 10f32.powf(1.25)).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_cutoffs() {
+        let samples = HashMap::from([
+            (
+                "en".to_string(),
+                (1..=10).rev().map(|pp| pp as f32).collect(),
+            ),
+            ("fr".to_string(), vec![f32::NAN]),
+        ]);
+        let cutoffs = PerplexityCutoffs::from_samples(samples);
+
+        assert_eq!(cutoffs.iter().count(), 1);
+        assert_eq!(cutoffs.get("en"), Some((3.0, 6.0)));
+        assert_eq!(cutoffs.bucket("en", 3.0), Some(Bucket::Head));
+        assert_eq!(cutoffs.bucket("en", 5.5), Some(Bucket::Middle));
+        assert_eq!(cutoffs.bucket("en", 100.0), Some(Bucket::Tail));
+        assert_eq!(cutoffs.bucket("fr", 1.0), None);
+    }
+
+    #[test]
+    fn test_add_to() {
+        let cutoffs = PerplexityCutoffs::from_samples(HashMap::from([(
+            "en".to_string(),
+            vec![100.0, 200.0, 300.0],
+        )]));
+
+        let mut doc = Document::new(String::new(), HashMap::new(), Metadata::default());
+        cutoffs.add_to("en", &mut doc);
+        assert_eq!(doc.field(BUCKET_FIELD), None);
+
+        doc.add_score(PERPLEXITY_SCORE, 150.0);
+        cutoffs.add_to("en", &mut doc);
+        assert_eq!(doc.field(BUCKET_FIELD), Some(&"middle".into()));
+    }
+}
@@ -1,15 +1,182 @@
 //! Document-level filtering.
 //!
 //! Those filters take a WARC [warc::Record] as a parameter.
+//!
+//! Filters are listed in [FilterKind], and can be combined using [FilterKind::And] and [FilterKind::Or].
+//! They can also be parsed from a string (see [FilterKind::from_str]), which is what the CLI uses:
+//!
+//! ```text
+//! and(pfilter,min_length=500,or(non_letter=0.3,stop_words=stopwords.txt:0.1))
+//! ```
+//!
+//! [RecordFilter] wraps a [FilterKind] and counts rejected records for each filter of the tree.
+use std::collections::HashSet;
 use std::convert::TryFrom;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
 
-use warc::{BufferedBody, Record};
+use log::info;
+use unicode_segmentation::UnicodeSegmentation;
+use warc::{BufferedBody, Record, WarcHeader};
+
+use crate::error::Error;
 
 use super::sentence::Length;
 use super::Filter;
 use std::cmp::Ordering;
+
+#[derive(Debug)]
 pub enum FilterKind {
     PFilter(PFilter),
+    Length(DocumentLength),
+    NonLetter(NonLetter),
+    Url(UrlPattern),
+    StopWords(StopWords),
+    /// Keeps records that pass every filter.
+    ///
+    /// Filters are evaluated in order and evaluation stops at the first rejection,
+    /// so rejection counts of a filter only include records that passed the previous ones.
+    And(Vec<RecordFilter>),
+    /// Keeps records that pass at least one filter.
+    ///
+    /// Filters are evaluated in order and evaluation stops at the first acceptance.
+    Or(Vec<RecordFilter>),
+}
+
+impl FilterKind {
+    /// Short description of the filter, used in reports.
+    fn name(&self) -> String {
+        match self {
+            Self::PFilter(p) => format!("pfilter={}", p.sentence_threshold),
+            Self::Length(l) => match (l.min, l.max) {
+                (Some(min), Some(max)) => format!("length={min}..={max}"),
+                (Some(min), None) => format!("min_length={min}"),
+                (None, Some(max)) => format!("max_length={max}"),
+                (None, None) => "length".to_string(),
+            },
+            Self::NonLetter(n) => format!("non_letter={}", n.max_ratio),
+            Self::Url(u) => format!("url={}", u.patterns.join("|")),
+            Self::StopWords(s) => format!("stop_words={}", s.min_ratio),
+            Self::And(_) => "and".to_string(),
+            Self::Or(_) => "or".to_string(),
+        }
+    }
+
+    /// Split `args` on commas that are not nested in parentheses.
+    fn split_args(args: &str) -> Result<Vec<&str>, String> {
+        let mut parts = Vec::new();
+        let mut depth = 0usize;
+        let mut start = 0;
+        for (idx, c) in args.char_indices() {
+            match c {
+                '(' => depth += 1,
+                ')' => {
+                    depth = depth
+                        .checked_sub(1)
+                        .ok_or_else(|| format!("unbalanced parentheses in {args}"))?
+                }
+                ',' if depth == 0 => {
+                    parts.push(args[start..idx].trim());
+                    start = idx + 1;
+                }
+                _ => (),
+            }
+        }
+        if depth != 0 {
+            return Err(format!("unbalanced parentheses in {args}"));
+        }
+        parts.push(args[start..].trim());
+        Ok(parts)
+    }
+
+    /// Parse a leaf filter value.
+    fn parse_value<T: FromStr>(name: &str, value: Option<&str>) -> Result<T, String> {
+        value
+            .ok_or_else(|| format!("filter {name} needs a value ({name}=<value>)"))?
+            .parse()
+            .map_err(|_| format!("invalid value for filter {name}: {value:?}"))
+    }
+}
+
+impl FromStr for FilterKind {
+    type Err = String;
+
+    /// Parse a filter expression.
+    ///
+    /// Available filters are:
+    /// - `pfilter` or `pfilter=<threshold>`: see [PFilter]
+    /// - `min_length=<n>`, `max_length=<n>`: see [DocumentLength]
+    /// - `non_letter=<max ratio>`: see [NonLetter]
+    /// - `url=<pattern>`: see [UrlPattern]
+    /// - `stop_words=<path>:<min ratio>`: see [StopWords]
+    /// - `and(<filter>,<filter>,...)` and `or(<filter>,<filter>,...)`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+
+        // combinators
+        for (prefix, is_and) in [("and(", true), ("or(", false)] {
+            if let Some(args) = s.strip_prefix(prefix) {
+                let args = args
+                    .strip_suffix(')')
+                    .ok_or_else(|| format!("missing closing parenthesis in {s}"))?;
+                let filters = Self::split_args(args)?
+                    .into_iter()
+                    .map(|arg| arg.parse().map(RecordFilter::new))
+                    .collect::<Result<Vec<_>, _>>()?;
+                return Ok(if is_and {
+                    Self::And(filters)
+                } else {
+                    Self::Or(filters)
+                });
+            }
+        }
+
+        let (name, value) = match s.split_once('=') {
+            Some((name, value)) => (name, Some(value)),
+            None => (s, None),
+        };
+
+        match name {
+            "pfilter" => match value {
+                Some(_) => Ok(Self::PFilter(PFilter::new(
+                    Self::parse_value(name, value)?,
+                    Length::default(),
+                ))),
+                None => Ok(Self::PFilter(PFilter::default())),
+            },
+            "min_length" => Ok(Self::Length(DocumentLength::new(
+                Some(Self::parse_value(name, value)?),
+                None,
+            ))),
+            "max_length" => Ok(Self::Length(DocumentLength::new(
+                None,
+                Some(Self::parse_value(name, value)?),
+            ))),
+            "non_letter" => Ok(Self::NonLetter(NonLetter::new(Self::parse_value(
+                name, value,
+            )?))),
+            "url" => Ok(Self::Url(UrlPattern::new(vec![Self::parse_value(
+                name, value,
+            )?]))),
+            "stop_words" => {
+                let (path, ratio) =
+                    value
+                        .and_then(|value| value.rsplit_once(':'))
+                        .ok_or_else(|| {
+                            "stop_words filter needs a value (stop_words=<path>:<min ratio>)"
+                                .to_string()
+                        })?;
+                let ratio = Self::parse_value(name, Some(ratio))?;
+                StopWords::from_path(Path::new(path), ratio)
+                    .map(Self::StopWords)
+                    .map_err(|e| format!("could not load stop words from {path}: {e:?}"))
+            }
+            _ => Err(format!("unknown filter: {s}")),
+        }
+    }
 }
 
 impl Default for FilterKind {
@@ -18,19 +185,249 @@ impl Default for FilterKind {
     }
 }
 
+impl FilterKind {
+    /// Get the reason why a record is rejected, or [None] if it is kept.
+    ///
+    /// The reason is the name of the rejecting filter rather than of its combination:
+    /// the first rejecting filter for [FilterKind::And], and every rejecting filter for [FilterKind::Or]
+    /// (ex. `or(min_length=500,non_letter=0.3)`).
+    pub fn detect_reason(&self, reader: &Record<BufferedBody>) -> Option<String> {
+        let kept = match self {
+            Self::PFilter(p) => p.detect(reader),
+            Self::Length(l) => l.detect(reader),
+            Self::NonLetter(n) => n.detect(reader),
+            Self::Url(u) => u.detect(reader),
+            Self::StopWords(s) => s.detect(reader),
+            Self::And(filters) => return filters.iter().find_map(|f| f.detect_reason(reader)),
+            Self::Or(filters) => {
+                let mut reasons = Vec::with_capacity(filters.len());
+                for filter in filters {
+                    reasons.push(filter.detect_reason(reader)?);
+                }
+                return Some(format!("or({})", reasons.join(",")));
+            }
+        };
+        (!kept).then(|| self.name())
+    }
+}
+
 impl Filter<&Record<BufferedBody>> for FilterKind {
     fn detect(&self, reader: &Record<BufferedBody>) -> bool {
-        match self {
-            Self::PFilter(p) => p.detect(reader),
+        self.detect_reason(reader).is_none()
+    }
+}
+
+/// [FilterKind] that counts the records it rejects.
+///
+/// Counting is thread-safe, so a single [RecordFilter] can be shared across shards.
+#[derive(Debug)]
+pub struct RecordFilter {
+    kind: FilterKind,
+    rejected: AtomicUsize,
+}
+
+impl RecordFilter {
+    pub fn new(kind: FilterKind) -> Self {
+        Self {
+            kind,
+            rejected: AtomicUsize::new(0),
+        }
+    }
+
+    /// Get the reason why a record is rejected (see [FilterKind::detect_reason]), counting rejections.
+    pub fn detect_reason(&self, reader: &Record<BufferedBody>) -> Option<String> {
+        let reason = self.kind.detect_reason(reader);
+        if reason.is_some() {
+            self.rejected.fetch_add(1, AtomicOrdering::Relaxed);
+        }
+        reason
+    }
+
+    /// Get the number of records rejected by this filter.
+    pub fn rejected(&self) -> usize {
+        self.rejected.load(AtomicOrdering::Relaxed)
+    }
+
+    /// Get the rejection counts of this filter and of its children (depth-first),
+    /// along with their depth in the filter tree.
+    pub fn rejections(&self) -> Vec<(usize, String, usize)> {
+        let mut rejections = Vec::new();
+        self.collect_rejections(0, &mut rejections);
+        rejections
+    }
+
+    fn collect_rejections(&self, depth: usize, rejections: &mut Vec<(usize, String, usize)>) {
+        rejections.push((depth, self.kind.name(), self.rejected()));
+        if let FilterKind::And(filters) | FilterKind::Or(filters) = &self.kind {
+            for filter in filters {
+                filter.collect_rejections(depth + 1, rejections);
+            }
+        }
+    }
+
+    /// Log rejection counts.
+    pub fn report(&self) {
+        for (depth, name, rejected) in self.rejections() {
+            info!(
+                "record filter {}{}: {} rejected",
+                "  ".repeat(depth),
+                name,
+                rejected
+            );
+        }
+    }
+}
+
+impl From<FilterKind> for RecordFilter {
+    fn from(kind: FilterKind) -> Self {
+        Self::new(kind)
+    }
+}
+
+impl Default for RecordFilter {
+    fn default() -> Self {
+        Self::new(FilterKind::default())
+    }
+}
+
+impl Filter<&Record<BufferedBody>> for RecordFilter {
+    fn detect(&self, reader: &Record<BufferedBody>) -> bool {
+        self.detect_reason(reader).is_none()
+    }
+}
+
+/// Keeps documents whose length (in unicode codepoints) is within bounds (inclusive).
+#[derive(Debug, Default)]
+pub struct DocumentLength {
+    min: Option<usize>,
+    max: Option<usize>,
+}
+
+impl DocumentLength {
+    pub fn new(min: Option<usize>, max: Option<usize>) -> Self {
+        Self { min, max }
+    }
+}
+
+impl Filter<&Record<BufferedBody>> for DocumentLength {
+    fn detect(&self, reader: &Record<BufferedBody>) -> bool {
+        let length = String::from_utf8_lossy(reader.body()).chars().count();
+        self.min.iter().all(|min| length >= *min) && self.max.iter().all(|max| length <= *max)
+    }
+}
+
+/// Rejects documents where the ratio of non-letter characters (whitespace excluded) exceeds `max_ratio`.
+///
+/// Documents without any non-whitespace character are rejected.
+#[derive(Debug)]
+pub struct NonLetter {
+    max_ratio: f32,
+}
+
+impl NonLetter {
+    pub fn new(max_ratio: f32) -> Self {
+        Self { max_ratio }
+    }
+}
+
+impl Filter<&Record<BufferedBody>> for NonLetter {
+    fn detect(&self, reader: &Record<BufferedBody>) -> bool {
+        let body = String::from_utf8_lossy(reader.body());
+        let (nb_chars, nb_non_letters) = body.chars().filter(|c| !c.is_whitespace()).fold(
+            (0, 0),
+            |(nb_chars, nb_non_letters), c| {
+                (
+                    nb_chars + 1,
+                    nb_non_letters + usize::from(!c.is_alphabetic()),
+                )
+            },
+        );
+
+        nb_chars > 0 && nb_non_letters as f32 / nb_chars as f32 <= self.max_ratio
+    }
+}
+
+impl Default for NonLetter {
+    /// Default maximum ratio is `0.5`.
+    fn default() -> Self {
+        Self { max_ratio: 0.5 }
+    }
+}
+
+/// Rejects documents whose URL contains one of the provided patterns.
+///
+/// Documents without URL are kept.
+#[derive(Debug, Default)]
+pub struct UrlPattern {
+    patterns: Vec<String>,
+}
+
+impl UrlPattern {
+    pub fn new(patterns: Vec<String>) -> Self {
+        Self { patterns }
+    }
+}
+
+impl Filter<&Record<BufferedBody>> for UrlPattern {
+    fn detect(&self, reader: &Record<BufferedBody>) -> bool {
+        match reader.header(WarcHeader::TargetURI) {
+            Some(url) => !self
+                .patterns
+                .iter()
+                .any(|pattern| url.contains(pattern.as_str())),
+            None => true,
         }
     }
 }
 
+/// Keeps documents where the ratio of stop words over words is at least `min_ratio`.
+///
+/// Since record filtering happens before identification, the stop word list is not language-specific.
+/// Words are lowercased before lookup.
+#[derive(Debug, Default)]
+pub struct StopWords {
+    words: HashSet<String>,
+    min_ratio: f32,
+}
+
+impl StopWords {
+    pub fn new(words: HashSet<String>, min_ratio: f32) -> Self {
+        Self { words, min_ratio }
+    }
+
+    /// Load stop words from a file holding one word per line.
+    pub fn from_path(path: &Path, min_ratio: f32) -> Result<Self, Error> {
+        let reader = BufReader::new(File::open(path)?);
+        let words = reader
+            .lines()
+            .map(|line| line.map(|word| word.trim().to_lowercase()))
+            .filter(|word| !matches!(word, Ok(w) if w.is_empty()))
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self::new(words, min_ratio))
+    }
+}
+
+impl Filter<&Record<BufferedBody>> for StopWords {
+    fn detect(&self, reader: &Record<BufferedBody>) -> bool {
+        let body = String::from_utf8_lossy(reader.body());
+        let (nb_words, nb_stop_words) =
+            body.unicode_words()
+                .fold((0, 0), |(nb_words, nb_stop_words), word| {
+                    let is_stop_word = self.words.contains(&word.to_lowercase());
+                    (nb_words + 1, nb_stop_words + usize::from(is_stop_word))
+                });
+
+        nb_words > 0 && nb_stop_words as f32 / nb_words as f32 >= self.min_ratio
+    }
+}
+
 /// Filters out documents that doesn't have its content enough in long newline-separated strings.
 ///
 /// For each document, we compute the size (in bytes) of newline-separated strings, that we bucket in two bins
-/// depending on their size. The threshold size is specified in the [PFilter::min_length].
+/// depending on their size. The threshold size is the one of the sentence filter (see [Length], which is script-aware).
 /// If the `>min_length` bin makes for at least sentence_threshold of the document, we keep it.
+#[derive(Debug)]
 pub struct PFilter {
     sentence_threshold: f64,
     sentence_filter: Length,
@@ -58,10 +455,11 @@ impl Filter<&Record<BufferedBody>> for PFilter {
 
         for line in lines {
             // we do not use sentence_filter since we'd compute sentence length two times.
-            let count = line.chars().count();
+            // thresholds are script-aware (see [Length::min_size_for]).
+            let count = self.sentence_filter.length(line);
 
             // if count is >= than minimum filter size, we add to upper bucket
-            match count.cmp(self.sentence_filter.min_size()) {
+            match count.cmp(&self.sentence_filter.min_size_for(line)) {
                 Ordering::Less => bucket_lower += u32::try_from(count).unwrap(),
                 Ordering::Equal | Ordering::Greater => {
                     bucket_upper += u32::try_from(count).unwrap()
@@ -98,11 +496,19 @@ impl Default for PFilter {
 
 #[cfg(test)]
 mod tests {
-    use warc::Record;
+    use std::collections::HashSet;
+
+    use warc::{BufferedBody, Record, WarcHeader};
 
     use crate::filtering::Filter;
 
-    use super::PFilter;
+    use super::{
+        DocumentLength, FilterKind, NonLetter, PFilter, RecordFilter, StopWords, UrlPattern,
+    };
+
+    fn gen_record(body: &str) -> Record<BufferedBody> {
+        Record::default().add_body(body)
+    }
 
     #[test]
     fn test_pfilter_fail() {
@@ -146,4 +552,104 @@ mod tests {
         let f = PFilter::default();
         assert_eq!(f.detect(&r), true);
     }
+
+    #[test]
+    fn test_document_length() {
+        let r = gen_record("0123456789");
+        assert!(DocumentLength::new(Some(10), Some(10)).detect(&r));
+        assert!(!DocumentLength::new(Some(11), None).detect(&r));
+        assert!(!DocumentLength::new(None, Some(9)).detect(&r));
+        assert!(DocumentLength::default().detect(&r));
+    }
+
+    #[test]
+    fn test_non_letter() {
+        let f = NonLetter::new(0.5);
+        assert!(f.detect(&gen_record("some text, with punctuation.")));
+        assert!(!f.detect(&gen_record("1234 5678 !!!! a")));
+        assert!(!f.detect(&gen_record("   ")));
+    }
+
+    #[test]
+    fn test_url() {
+        let f = UrlPattern::new(vec!["/tag/".to_string()]);
+        let mut r = gen_record("foo");
+        assert!(f.detect(&r));
+
+        r.set_header(WarcHeader::TargetURI, "https://example.com/tag/foo")
+            .unwrap();
+        assert!(!f.detect(&r));
+
+        r.set_header(WarcHeader::TargetURI, "https://example.com/post/foo")
+            .unwrap();
+        assert!(f.detect(&r));
+    }
+
+    #[test]
+    fn test_stop_words() {
+        let words: HashSet<String> = ["the", "a", "of"].iter().map(|w| w.to_string()).collect();
+        let f = StopWords::new(words, 0.25);
+        assert!(f.detect(&gen_record("The cat of the house")));
+        assert!(!f.detect(&gen_record("cat house garden tree")));
+        assert!(!f.detect(&gen_record("")));
+    }
+
+    #[test]
+    fn test_parse() {
+        let f: FilterKind = "and(pfilter, min_length=10, or(url=/tag/, non_letter=0.3))"
+            .parse()
+            .unwrap();
+        match f {
+            FilterKind::And(filters) => {
+                assert_eq!(filters.len(), 3);
+                assert!(matches!(filters[2].kind, FilterKind::Or(ref f) if f.len() == 2));
+            }
+            _ => panic!("expected and filter, got {f:?}"),
+        }
+
+        assert!("pfilter=0.5".parse::<FilterKind>().is_ok());
+        assert!("min_length".parse::<FilterKind>().is_err());
+        assert!("and(pfilter".parse::<FilterKind>().is_err());
+        assert!("foo=1".parse::<FilterKind>().is_err());
+        assert!("stop_words=/nonexistent:0.1".parse::<FilterKind>().is_err());
+    }
+
+    #[test]
+    fn test_rejection_counts() {
+        let f = RecordFilter::new("or(min_length=5,non_letter=0.1)".parse().unwrap());
+
+        // passes min_length
+        assert!(f.detect(&gen_record("long enough")));
+        // fails min_length, passes non_letter
+        assert!(f.detect(&gen_record("abc")));
+        // fails both
+        assert!(!f.detect(&gen_record("a!")));
+
+        let rejections: Vec<_> = f
+            .rejections()
+            .into_iter()
+            .map(|(depth, _, rejected)| (depth, rejected))
+            .collect();
+        assert_eq!(rejections, vec![(0, 1), (1, 2), (1, 1)]);
+    }
+
+    #[test]
+    fn test_detect_reason() {
+        let f = RecordFilter::new(
+            "and(min_length=3,or(min_length=10,non_letter=0.1))"
+                .parse()
+                .unwrap(),
+        );
+
+        assert_eq!(f.detect_reason(&gen_record("long enough")), None);
+        assert_eq!(
+            f.detect_reason(&gen_record("a")),
+            Some("min_length=3".to_string())
+        );
+        assert_eq!(
+            f.detect_reason(&gen_record("a!!")),
+            Some("or(min_length=10,non_letter=0.1)".to_string())
+        );
+        assert_eq!(f.rejected(), 2);
+    }
 }
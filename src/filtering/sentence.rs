@@ -1,52 +1,149 @@
 //! Sentence-level filtering
 use super::filter::FilterMut;
 use super::Filter;
+use std::collections::HashMap;
 use std::convert::TryInto;
+use std::fs::File;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use unicode_script::{Script, UnicodeScript};
+
+use crate::error::Error;
+
+/// Maximum number of distinct scripts counted when looking for the main script of a line.
+const MAX_SCRIPTS: usize = 4;
 
 /// Simple length filter.
 /// Returns `false` if provided sentence is less than [Length::min_size] unicode codepoints.
 ///
 /// [Length::min_size] is 100 by default.
+///
+/// Thresholds are script-aware: lines in dense scripts (Han, Kana, Thai...) carry more content per character,
+/// so their threshold is [Length::min_size] scaled by a per-script ratio (see [Length::set_script_ratio]).
+/// The script of a line is the most frequent one, ignoring common characters (punctuation, digits...).
+#[derive(Debug, Clone)]
 pub struct Length {
     min_size: usize,
+    script_ratios: HashMap<Script, f32>,
 }
 
 impl Length {
     /// specify a minimum length
     pub fn with_min_size(min_size: usize) -> Self {
-        Self { min_size }
+        Self {
+            min_size,
+            script_ratios: Self::default_script_ratios(),
+        }
+    }
+
+    /// Default script ratios.
+    ///
+    /// Scripts without spaces between words, or with syllabic/logographic characters, get lower thresholds.
+    fn default_script_ratios() -> HashMap<Script, f32> {
+        [
+            (Script::Han, 0.4),
+            (Script::Hiragana, 0.4),
+            (Script::Katakana, 0.4),
+            (Script::Thai, 0.4),
+            (Script::Lao, 0.4),
+            (Script::Khmer, 0.4),
+            (Script::Myanmar, 0.4),
+            (Script::Tibetan, 0.4),
+            (Script::Hangul, 0.5),
+        ]
+        .into_iter()
+        .collect()
     }
 
     /// Get a reference to the length's min size.
     pub fn min_size(&self) -> &usize {
         &self.min_size
     }
+
+    /// Set the threshold ratio of a script. The threshold of lines in this script is `min_size * ratio`.
+    pub fn set_script_ratio(&mut self, script: Script, ratio: f32) {
+        self.script_ratios.insert(script, ratio);
+    }
+
+    /// Remove script ratios, using [Length::min_size] for every script.
+    pub fn clear_script_ratios(&mut self) {
+        self.script_ratios.clear();
+    }
+
+    /// Get the length of a sentence, in unicode codepoints.
+    pub fn length(&self, sentence: &str) -> usize {
+        sentence.chars().count()
+    }
+
+    /// Get the most frequent script of a sentence, ignoring common and inherited characters.
+    ///
+    /// Only the first [MAX_SCRIPTS] scripts of the sentence are counted, which only matters for very mixed lines.
+    fn main_script(sentence: &str) -> Option<Script> {
+        let mut counts = [(Script::Unknown, 0usize); MAX_SCRIPTS];
+        for script in sentence.chars().map(|c| c.script()) {
+            if matches!(script, Script::Common | Script::Inherited | Script::Unknown) {
+                continue;
+            }
+            // slots are filled in order, so a counted script is always found before an empty slot
+            if let Some(slot) = counts
+                .iter_mut()
+                .find(|(counted, count)| *counted == script || *count == 0)
+            {
+                *slot = (script, slot.1 + 1);
+            }
+        }
+
+        counts
+            .into_iter()
+            .filter(|(_, count)| *count > 0)
+            .max_by_key(|(_, count)| *count)
+            .map(|(script, _)| script)
+    }
+
+    /// Get the minimum length of a sentence.
+    pub fn min_size_for(&self, sentence: &str) -> usize {
+        // avoid script detection when not needed
+        if self.script_ratios.is_empty() {
+            return self.min_size;
+        }
+
+        Self::main_script(sentence)
+            .and_then(|script| self.script_ratios.get(&script))
+            .map(|ratio| (self.min_size as f32 * ratio).round() as usize)
+            .unwrap_or(self.min_size)
+    }
 }
 
 impl Filter<&str> for Length {
     fn detect(&self, sentence: &str) -> bool {
-        sentence.chars().count() > self.min_size
+        self.length(sentence) > self.min_size_for(sentence)
     }
 }
 
 impl Default for Length {
     /// Default minimum length for sentences is 100 Unicode Codepoints
+    /// (scaled for dense scripts, see [Length::default_script_ratios]).
     fn default() -> Self {
-        Length { min_size: 100 }
+        Length::with_min_size(100)
     }
 }
 
 /// Mean filter: Keeps track of mean length of proposed sentences
 ///
-/// Detects sentences that are within the stdandard deviation.
+/// Detects sentences that are within the stdandard deviation
+/// (or within `nb_std` standard deviations, see [MeanLength::set_nb_std]).
 ///
 /// Implements both [super::Filter] and [super::FilterMut]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MeanLength {
     nb_measures: u32,
     sum_lenghts: f64,
     pow_lengths: f64,
     mean: f64,
     std: f64,
+    #[serde(skip, default = "MeanLength::default_nb_std")]
+    nb_std: f64,
 }
 
 /// mean/std update formula from
@@ -72,14 +169,33 @@ impl MeanLength {
     }
 
     /// Get a reference to the mean length's mean.
-    fn mean(&self) -> &f64 {
+    pub fn mean(&self) -> &f64 {
         &self.mean
     }
 
     /// Get a reference to the mean length's std.
-    fn std(&self) -> &f64 {
+    pub fn std(&self) -> &f64 {
         &self.std
     }
+
+    /// Get the number of sentences the distribution has been learnt on.
+    pub fn nb_measures(&self) -> u32 {
+        self.nb_measures
+    }
+
+    /// Set the number of standard deviations a sentence length can be away from the mean.
+    pub fn set_nb_std(&mut self, nb_std: f64) {
+        self.nb_std = nb_std;
+    }
+
+    fn default_nb_std() -> f64 {
+        1.0
+    }
+
+    /// Checks that `mu-k*sig < length < mu+k*sig`.
+    fn is_within(&self, length: u32) -> bool {
+        (f64::from(length) - self.mean).abs() < self.nb_std * self.std
+    }
 }
 impl Default for MeanLength {
     fn default() -> Self {
@@ -89,6 +205,7 @@ impl Default for MeanLength {
             pow_lengths: 0.0,
             mean: 0.0,
             std: 0.0,
+            nb_std: Self::default_nb_std(),
         }
     }
 }
@@ -100,14 +217,64 @@ impl FilterMut<&str> for MeanLength {
         self.update_mean(length);
 
         // ensure that mu-sig<length<mu+sig (eq.to 0<length-mu<sig)
-        (f64::from(length) - self.mean).abs() < self.std
+        self.is_within(length)
     }
 }
 
 impl Filter<&str> for MeanLength {
     fn detect(&self, sentence: &str) -> bool {
         let length: u32 = sentence.chars().count().try_into().unwrap_or_default();
-        (f64::from(length) - self.mean).abs() < self.std
+        self.is_within(length)
+    }
+}
+
+/// Per-language sentence length distributions.
+///
+/// Distributions are learnt in a calibration pass using [MeanLengths::detect_mut],
+/// saved with [MeanLengths::to_path] and then used frozen in the main pass.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MeanLengths {
+    lengths: HashMap<String, MeanLength>,
+}
+
+impl MeanLengths {
+    /// Update the distribution of `lang` with `sentence`,
+    /// returning whether the sentence is within the distribution.
+    pub fn detect_mut(&mut self, lang: &str, sentence: &str) -> bool {
+        self.lengths
+            .entry(lang.to_string())
+            .or_default()
+            .detect_mut(sentence)
+    }
+
+    /// Get the distribution of `lang`.
+    pub fn get(&self, lang: &str) -> Option<&MeanLength> {
+        self.lengths.get(lang)
+    }
+
+    /// Iterate over languages and their distributions.
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &MeanLength)> {
+        self.lengths.iter()
+    }
+
+    /// Set the number of standard deviations of every distribution (see [MeanLength::set_nb_std]).
+    pub fn set_nb_std(&mut self, nb_std: f64) {
+        for length in self.lengths.values_mut() {
+            length.set_nb_std(nb_std);
+        }
+    }
+
+    /// Load distributions from a JSON file.
+    pub fn from_path(path: &Path) -> Result<Self, Error> {
+        let f = File::open(path)?;
+        Ok(serde_json::from_reader(f)?)
+    }
+
+    /// Save distributions into a JSON file.
+    pub fn to_path(&self, path: &Path) -> Result<(), Error> {
+        let f = File::create(path)?;
+        serde_json::to_writer_pretty(f, self)?;
+        Ok(())
     }
 }
 
@@ -116,7 +283,9 @@ mod tests {
     use rand::thread_rng;
     use rand_distr::{Distribution, Normal};
 
-    use super::{Filter, Length, MeanLength};
+    use unicode_script::Script;
+
+    use super::{Filter, Length, MeanLength, MeanLengths};
     use crate::filtering::filter::FilterMut;
 
     #[test]
@@ -166,4 +335,87 @@ mod tests {
         assert_eq!(f.detect(&long_invalid), false);
         assert_eq!(f.detect(&short_invalid), false);
     }
+
+    #[test]
+    fn mean_nb_std() {
+        let mut f = MeanLength::default();
+        for length in [8, 10, 12] {
+            f.detect_mut(&"a".repeat(length));
+        }
+
+        // std is ~1.63
+        assert!(!f.detect(&"a".repeat(13)));
+        f.set_nb_std(2.0);
+        assert!(f.detect(&"a".repeat(13)));
+    }
+
+    #[test]
+    fn mean_lengths_per_lang() {
+        let mut f = MeanLengths::default();
+        for length in [8, 10, 12] {
+            f.detect_mut("en", &"a".repeat(length));
+            f.detect_mut("zh", &"字".repeat(length / 4));
+        }
+
+        assert!(f.get("en").unwrap().detect(&"a".repeat(10)));
+        assert!(!f.get("zh").unwrap().detect(&"字".repeat(10)));
+        assert!(f.get("fr").is_none());
+    }
+
+    #[test]
+    fn mean_lengths_roundtrip() {
+        let mut f = MeanLengths::default();
+        f.detect_mut("en", "foo");
+        f.detect_mut("en", "foobar");
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("lengths.json");
+        f.to_path(&path).unwrap();
+
+        let f2 = MeanLengths::from_path(&path).unwrap();
+        let (en, en2) = (f.get("en").unwrap(), f2.get("en").unwrap());
+        assert_eq!(en.nb_measures(), en2.nb_measures());
+        assert_eq!(en.mean(), en2.mean());
+        assert_eq!(en.std(), en2.std());
+    }
+
+    #[test]
+    fn length_scripts() {
+        let f = Length::default();
+        let zh: String = ['字'; 45].iter().collect();
+        let zh_short: String = ['字'; 30].iter().collect();
+        let th: String = ['ก'; 45].iter().collect();
+        let latin: String = ['z'; 45].iter().collect();
+
+        assert!(f.detect(&zh));
+        assert!(!f.detect(&zh_short));
+        assert!(f.detect(&th));
+        assert!(!f.detect(&latin));
+
+        // punctuation and digits don't change the script
+        assert!(f.detect(&format!("{zh}!!! 123")));
+    }
+
+    #[test]
+    fn length_custom_scripts() {
+        let zh: String = ['字'; 45].iter().collect();
+
+        let mut f = Length::default();
+        f.clear_script_ratios();
+        assert!(!f.detect(&zh));
+
+        f.set_script_ratio(Script::Han, 0.2);
+        assert_eq!(f.min_size_for(&zh), 20);
+    }
+
+    #[test]
+    fn length_mixed_scripts() {
+        // most frequent script wins, whatever the order
+        let f = Length::default();
+        let zh: String = ['字'; 45].iter().collect();
+        assert_eq!(f.min_size_for(&format!("abc {zh}")), 40);
+        assert_eq!(f.min_size_for(&format!("{zh} {}", "z".repeat(50))), 100);
+        // scripts after the first MAX_SCRIPTS ones are ignored
+        assert_eq!(f.min_size_for(&format!("a α б ב {zh}")), 100);
+    }
 }
@@ -8,5 +8,10 @@ pub(crate) mod model;
 mod multilingual;
 mod tag_convert;
 
+pub use model::FastText;
+pub use model::FastTextBuilder;
+pub use model::Predict;
 pub use multilingual::Multilingual;
+pub use multilingual::MultilingualKind;
 pub use multilingual::StrictMultilingual;
+pub use tag_convert::LabelMap;
@@ -9,17 +9,17 @@ use std::{
 };
 
 use fasttext::FastText as FastTextLib;
-use log::error;
 use oxilangtag::LanguageTag;
 
 use crate::error::Error;
 
-use super::{identification::Identification, tag_convert::Tag};
+use super::{identification::Identification, tag_convert::LabelMap};
 
-/// Covers individual sentence identifications, lang bins and total size of document in bytes
+/// Covers individual sentence identifications (and their sizes in bytes), lang bins and total size of document in bytes
 #[derive(Debug)]
 pub struct DocIdentification<T: Deref<Target = str> + Clone> {
     line_ids: Vec<Option<Identification<T>>>,
+    line_sizes: Vec<usize>,
     lang_bins: HashMap<Option<LanguageTag<T>>, (usize, f32)>,
     total_size: usize,
 }
@@ -29,6 +29,15 @@ impl<T: Deref<Target = str> + Clone> DocIdentification<T> {
         self.line_ids.as_ref()
     }
 
+    /// Get line identifications along with the size (in bytes) of each line.
+    pub fn weighted_line_ids(&self) -> Vec<(Option<Identification<T>>, usize)> {
+        self.line_ids
+            .iter()
+            .cloned()
+            .zip(self.line_sizes.iter().copied())
+            .collect()
+    }
+
     pub fn lang_bins(&self) -> &HashMap<Option<LanguageTag<T>>, (usize, f32)> {
         &self.lang_bins
     }
@@ -48,6 +57,12 @@ pub trait ModelKind {
 pub trait Predict<T: Deref<Target = str> + Clone> {
     fn predict_one(&self, line: &str) -> Result<Option<Identification<T>>, Error>;
     fn predict(&self, line: &str) -> Result<Option<Vec<Identification<T>>>, Error>;
+    /// Top-1 prediction on each line of a set of lines, that can come from several documents.
+    ///
+    /// Lines are predicted one at a time (fastText has no batched prediction),
+    /// so this is not faster than [Predict::predict_one] on each line.
+    /// Unicode null chars are ignored, without allocating for lines that don't hold any.
+    fn predict_lines(&self, lines: &[&str]) -> Result<Vec<Option<Identification<T>>>, Error>;
     fn weighted_ids(&self, lines: Lines) -> Result<DocIdentification<T>, Error>;
 }
 
@@ -56,36 +71,30 @@ pub trait Predict<T: Deref<Target = str> + Clone> {
 /// ModelKind will condition the implementation of the tag conversion
 pub struct FastText {
     inner: FastTextLib,
+    labels: LabelMap,
     pub k: i32,
     pub threshold: f32,
 }
 
+impl FastText {
+    /// Get the label conversion table, along with labels that couldn't be converted.
+    pub fn labels(&self) -> &LabelMap {
+        &self.labels
+    }
+}
+
 /// Prediction for new tags/model
 impl Predict<String> for FastText {
     fn predict_one(&self, line: &str) -> Result<Option<Identification<String>>, Error> {
         let pred = self.inner.predict(line, 1, self.threshold)?;
-        if pred.is_empty() {
-            Ok(None)
-        } else {
-            // unwrapping because we know pred is not empty.
-            // We might have a better way of doing this.
-            // The idea is to move out of pred, since we won't need it afterwards.
-            let pred = pred.into_iter().next().unwrap();
-            // convert prediction to newtag
-            let pred_to_languagetag: Result<LanguageTag<String>, _> =
-                Tag::new(&pred.label).try_into();
-            match pred_to_languagetag {
-                Ok(label) => {
-                    let id = Identification::new(label, pred.prob);
-
-                    Ok(Some(id))
-                }
-                Err(e) => {
-                    error!("Couldn't parse label {}: {e:?}", &pred.label);
-                    Err(e.into())
-                }
-            }
-        }
+
+        // The idea is to move out of pred, since we won't need it afterwards.
+        // Labels that can't be converted are considered as no identification.
+        Ok(pred.into_iter().next().and_then(|pred| {
+            self.labels
+                .convert(&pred.label)
+                .map(|label| Identification::new(label, pred.prob))
+        }))
     }
 
     fn predict(&self, line: &str) -> Result<Option<Vec<Identification<String>>>, Error> {
@@ -95,20 +104,10 @@ impl Predict<String> for FastText {
         } else {
             let identifications: Vec<Identification<String>> = predictions
                 .into_iter()
-                //TODO: try_into coerces into OldTag?
-                .map(|pred| {
-                    let label: Result<LanguageTag<String>, _> = Tag::new(&pred.label).try_into();
-                    match label {
-                        Ok(l) => Ok(Identification::new(l, pred.prob)),
-                        Err(e) => Err(e),
-                    }
-                })
-                .filter_map(|pred_result| match pred_result {
-                    Ok(p) => Some(p),
-                    Err(e) => {
-                        error!("Error with tag: {e}");
-                        None
-                    }
+                .filter_map(|pred| {
+                    self.labels
+                        .convert(&pred.label)
+                        .map(|label| Identification::new(label, pred.prob))
                 })
                 .collect();
             //do new stuff
@@ -116,44 +115,58 @@ impl Predict<String> for FastText {
         }
     }
 
+    fn predict_lines(&self, lines: &[&str]) -> Result<Vec<Option<Identification<String>>>, Error> {
+        // filter out unicode null chars
+        // this prevents fasttext errors and hopefully improves
+        // corpus quality.
+        // The buffer is reused and only filled for lines that do hold null chars.
+        let mut buf = String::new();
+
+        lines
+            .iter()
+            .map(|line| {
+                if line.contains(char::from(0)) {
+                    buf.clear();
+                    buf.extend(line.chars().filter(|c| *c != char::from(0)));
+                    self.predict_one(&buf)
+                } else {
+                    self.predict_one(line)
+                }
+            })
+            .collect()
+    }
+
     fn weighted_ids(&self, lines: Lines) -> Result<DocIdentification<String>, Error> {
         // per-lang and total byte counts
         // lang_count maps Lang -> (lang_byte_count, sum(byte_count*prob))
         let mut lang_count = HashMap::new();
         let mut total_count = 0;
 
-        // filter out unicode null chars
-        // this prevents fasttext errors and hopefully improves
-        // corpus quality
-        // TODO: check if we need this line
-        let lines = lines.map(|l| l.replace(char::from(0), ""));
+        let lines: Vec<&str> = lines.collect();
+        let ids = self.predict_lines(&lines)?;
+
+        // get length of each line, null chars excluded
+        let line_sizes: Vec<usize> = lines
+            .iter()
+            .map(|line| line.len() - line.matches(char::from(0)).count())
+            .collect();
+
+        // add to byte count for document-level identification
+        for (id, byte_count) in ids.iter().zip(line_sizes.iter().copied()) {
+            // map Identification to its lang, or keep None to store the "None" language identification
+            let ide_label = id.as_ref().map(|i| i.label().clone());
+            let ide_prob = id.as_ref().map(|i| *i.prob());
+
+            lang_count
+                .entry(ide_label)
+                .and_modify(|(count, count_times_prob)| {
+                    *count += byte_count;
+                    *count_times_prob += byte_count as f32 * ide_prob.unwrap_or(1.0f32);
+                })
+                .or_insert((byte_count, byte_count as f32 * ide_prob.unwrap_or(1.0f32)));
 
-        let ids: Vec<Option<Identification<_>>> = lines
-            .map(|line| {
-                // identify
-                let id = self.predict_one(&line);
-
-                // add to byte count for document-level identification
-                if let Ok(ref ide) = id {
-                    // map Identification to its lang, or keep None to store the "None" language identification
-                    let ide_label = ide.as_ref().map(|i| i.label().clone());
-                    let ide_prob = ide.as_ref().map(|i| *i.prob());
-                    // get length of current line
-                    let byte_count = line.len();
-
-                    lang_count
-                        .entry(ide_label)
-                        .and_modify(|(count, count_times_prob)| {
-                            *count += byte_count;
-                            *count_times_prob += byte_count as f32 * ide_prob.unwrap_or(1.0f32);
-                        })
-                        .or_insert((byte_count, byte_count as f32 * ide_prob.unwrap_or(1.0f32)));
-
-                    total_count += byte_count;
-                }
-                id
-            })
-            .collect::<Result<_, Error>>()?;
+            total_count += byte_count;
+        }
 
         // divide by total count to get probs between 0 and 1.
         for (_, count_times_prob) in lang_count.values_mut() {
@@ -162,6 +175,7 @@ impl Predict<String> for FastText {
 
         Ok(DocIdentification {
             line_ids: ids,
+            line_sizes,
             lang_bins: lang_count,
             total_size: total_count,
         })
@@ -171,6 +185,7 @@ impl Predict<String> for FastText {
 /// Fasttext builder.
 pub struct FastTextBuilder<'a> {
     path: Option<&'a Path>,
+    labels_path: Option<&'a Path>,
     k: Option<i32>,
     threshold: Option<f32>,
 }
@@ -181,6 +196,14 @@ impl<'a> FastTextBuilder<'a> {
         ft.load_model(path)?;
         Ok(ft)
     }
+
+    /// Load the label conversion table if a path is provided, or use the default one.
+    fn init_labels(labels_path: Option<&Path>) -> Result<LabelMap, Error> {
+        match labels_path {
+            Some(p) => LabelMap::from_path(p),
+            None => Ok(LabelMap::default()),
+        }
+    }
     /// attempt to build, resort to the following defaults if not set:
     /// - path: "./lid.208a.bin"
     /// - k: 1
@@ -201,6 +224,7 @@ impl<'a> FastTextBuilder<'a> {
 
         Ok(FastText {
             inner,
+            labels: Self::init_labels(self.labels_path)?,
             k,
             threshold,
         })
@@ -228,6 +252,7 @@ impl<'a> FastTextBuilder<'a> {
             .ok_or(Error::Custom("Couldn't parse path".to_string()))?;
         Ok(FastText {
             inner: Self::init_fasttextlib(path)?,
+            labels: Self::init_labels(self.labels_path)?,
             k: self.k.unwrap(),
             threshold: self.threshold.unwrap(),
         })
@@ -237,6 +262,12 @@ impl<'a> FastTextBuilder<'a> {
         self
     }
 
+    /// Use a custom label conversion table (see [LabelMap::from_path]).
+    pub fn labels_path<'b>(&'b mut self, labels_path: &'a Path) -> &'b mut FastTextBuilder<'a> {
+        self.labels_path = Some(labels_path);
+        self
+    }
+
     pub fn k<'b>(&'b mut self, k: i32) -> &'b mut FastTextBuilder<'a> {
         self.k = Some(k);
         self
@@ -252,6 +283,7 @@ impl<'a> Default for FastTextBuilder<'a> {
     fn default() -> Self {
         Self {
             path: Some(Path::new("lid.176.bin")),
+            labels_path: None,
             k: Some(1),
             threshold: Some(0.8),
         }
@@ -262,7 +294,7 @@ impl<'a> Default for FastTextBuilder<'a> {
 mod tests {
     use std::path::Path;
 
-    use super::{FastText, FastTextBuilder, Predict};
+    use super::{FastText, FastTextBuilder, Identification, Predict};
 
     #[test]
     fn test_new_one_sentence() {
@@ -292,6 +324,34 @@ mod tests {
         println!("{pred:?}");
     }
 
+    #[test]
+    fn test_predict_lines() {
+        let model: FastText = FastTextBuilder::default()
+            .path(Path::new("lid.176.bin"))
+            .build_or_default()
+            .unwrap();
+
+        let lines = [
+            "Ceci est une phrase en Français :)",
+            "This is an english sentence.",
+            "Ceci est une phrase en Français :)",
+            "This is an\0 english sentence.",
+        ];
+
+        let ids = model.predict_lines(&lines).unwrap();
+        let one_by_one: Vec<_> = lines
+            .iter()
+            .map(|line| model.predict_one(&line.replace(char::from(0), "")).unwrap())
+            .collect();
+
+        let labels = |ids: &[Option<Identification<String>>]| -> Vec<_> {
+            ids.iter()
+                .map(|id| id.as_ref().map(|id| (id.label().to_string(), *id.prob())))
+                .collect()
+        };
+        assert_eq!(labels(&ids), labels(&one_by_one));
+    }
+
     #[test]
     fn test_old_and_new_coherence() {
         let old_model: FastText = FastTextBuilder::default()
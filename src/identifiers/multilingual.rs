@@ -13,11 +13,16 @@ There are currently two multilinguality implementations:
 
 There are other criteria that are specified in the structs docs.
 
+Both implementations can work on line counts (`&[Option<Identification>]`) or on byte counts (`&[(Option<Identification>, usize)]`),
+and can be selected at runtime with [MultilingualKind].
+
 !*/
 use std::collections::HashMap;
+use std::str::FromStr;
 
 use itertools::Itertools;
 use log::debug;
+use oxilangtag::LanguageTag;
 
 use crate::filtering::Filter;
 
@@ -30,6 +35,7 @@ use super::identification::Identification;
 /// * `threshold_confidence`: Minimal prediction confidence for a given line
 /// * `max_langs`: Maximum number of languages present in a single Document
 /// * `min_confident_pctg`: Minimal percentage of lines having a `threshold_confidence` prediction confidence
+#[derive(Debug)]
 pub struct StrictMultilingual {
     min_sentences: usize,
     threshold_confidence: f32,
@@ -205,31 +211,20 @@ impl Default for StrictMultilingual {
 /// # Example
 ///
 /// If we have a 100 sentence document with 60 english lines, we'd need at least 60/4 = 15 lines in another language.
+#[derive(Debug)]
 pub struct Multilingual {
     min_sentences: usize,
     limit: usize,
     q: f32,
 }
 
-impl Filter<&[Option<Identification<String>>]> for Multilingual {
-    fn detect(&self, item: &[Option<Identification<String>>]) -> bool {
-        if item.len() < self.min_sentences {
-            return false;
-        }
-        // 2 langs minimum, the second one has at least 1/4 lines compared to the first one
-
-        let mut sentences_per_lang = HashMap::new();
-        // count lines for each language AND for no-identification
-        for id in item {
-            // key is None for no identification
-            let key = id.as_ref().map(|id| id.label());
-
-            let count = sentences_per_lang.entry(key).or_insert(0);
-            *count += 1;
-        }
-
-        debug!("sentences per lang: {:?}", sentences_per_lang);
-        let nb_langs = sentences_per_lang.keys().filter(|x| x.is_some()).count();
+impl Multilingual {
+    /// checks that counts (lines or bytes) meet the `C_n+1 >= (C_n)/Q` criteria.
+    ///
+    /// `counts` maps languages (or [None] for no identification) to their line/byte count.
+    fn detect_counts(&self, counts: HashMap<Option<&LanguageTag<String>>, usize>) -> bool {
+        debug!("counts per lang: {:?}", counts);
+        let nb_langs = counts.keys().filter(|x| x.is_some()).count();
 
         // check if document is monolingual
         if nb_langs < 2 {
@@ -238,7 +233,7 @@ impl Filter<&[Option<Identification<String>>]> for Multilingual {
         }
 
         // order by count
-        let counts_ordered: Vec<_> = sentences_per_lang
+        let counts_ordered: Vec<_> = counts
             .into_iter()
             .sorted_unstable_by(|a, b| b.1.cmp(&a.1))
             .collect();
@@ -257,7 +252,7 @@ impl Filter<&[Option<Identification<String>>]> for Multilingual {
 
         // first threshold is count for first language, divided by q
         let (first_lang, first_count) = l.next().unwrap();
-        debug!("{:?} is first with {} lines", first_lang, first_count);
+        debug!("{:?} is first with a count of {}", first_lang, first_count);
         let mut threshold = first_count as f32 / self.q;
 
         debug!("threshold is {}", threshold);
@@ -284,6 +279,47 @@ impl Filter<&[Option<Identification<String>>]> for Multilingual {
     }
 }
 
+impl Filter<&[Option<Identification<String>>]> for Multilingual {
+    fn detect(&self, item: &[Option<Identification<String>>]) -> bool {
+        if item.len() < self.min_sentences {
+            return false;
+        }
+        // 2 langs minimum, the second one has at least 1/4 lines compared to the first one
+
+        let mut sentences_per_lang = HashMap::new();
+        // count lines for each language AND for no-identification
+        for id in item {
+            // key is None for no identification
+            let key = id.as_ref().map(|id| id.label());
+
+            let count = sentences_per_lang.entry(key).or_insert(0);
+            *count += 1;
+        }
+
+        self.detect_counts(sentences_per_lang)
+    }
+}
+
+impl Filter<&[(Option<Identification<String>>, usize)]> for Multilingual {
+    fn detect(&self, item: &[(Option<Identification<String>>, usize)]) -> bool {
+        if item.len() < self.min_sentences {
+            return false;
+        }
+
+        let mut bytes_per_lang = HashMap::new();
+        // count bytes for each language AND for no-identification
+        for (id, bytes) in item {
+            // key is None for no identification
+            let key = id.as_ref().map(|id| id.label());
+
+            let count = bytes_per_lang.entry(key).or_insert(0);
+            *count += bytes;
+        }
+
+        self.detect_counts(bytes_per_lang)
+    }
+}
+
 impl Default for Multilingual {
     fn default() -> Self {
         Self {
@@ -294,13 +330,62 @@ impl Default for Multilingual {
     }
 }
 
+/// Multilinguality detector selection.
+///
+/// Enables choosing between [StrictMultilingual] and [Multilingual] at runtime.
+/// Can be parsed from `strict` or `ratio`.
+#[derive(Debug)]
+pub enum MultilingualKind {
+    Strict(StrictMultilingual),
+    Ratio(Multilingual),
+}
+
+impl Default for MultilingualKind {
+    fn default() -> Self {
+        MultilingualKind::Strict(StrictMultilingual::default())
+    }
+}
+
+impl FromStr for MultilingualKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "strict" => Ok(Self::Strict(StrictMultilingual::default())),
+            "ratio" => Ok(Self::Ratio(Multilingual::default())),
+            other => Err(format!(
+                "unknown multilingual detector {other} (expected strict or ratio)"
+            )),
+        }
+    }
+}
+
+impl Filter<&[Option<Identification<String>>]> for MultilingualKind {
+    fn detect(&self, item: &[Option<Identification<String>>]) -> bool {
+        match self {
+            Self::Strict(m) => m.detect(item),
+            Self::Ratio(m) => m.detect(item),
+        }
+    }
+}
+
+impl Filter<&[(Option<Identification<String>>, usize)]> for MultilingualKind {
+    fn detect(&self, item: &[(Option<Identification<String>>, usize)]) -> bool {
+        match self {
+            Self::Strict(m) => m.detect(item),
+            Self::Ratio(m) => m.detect(item),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
     use crate::{
         filtering::Filter,
         identifiers::{
-            identification::Identification, multilingual::Multilingual, StrictMultilingual,
+            identification::Identification, multilingual::Multilingual, MultilingualKind,
+            StrictMultilingual,
         },
     };
     use lazy_static::lazy_static;
@@ -317,7 +402,7 @@ mod tests {
         let id = Some(Identification::new(ID_EN.clone(), 1.0));
         let ids = vec![id; 10];
         let m = Multilingual::default();
-        assert_eq!(m.detect(&ids), false);
+        assert_eq!(m.detect(&ids[..]), false);
     }
 
     #[test]
@@ -332,7 +417,51 @@ mod tests {
         .cycle();
         let ids: Vec<_> = id.take(20).collect();
         let m = Multilingual::default();
-        assert_eq!(m.detect(&ids), true);
+        assert_eq!(m.detect(&ids[..]), true);
+    }
+
+    #[test]
+    fn test_multilingual_bytes() {
+        let id = [
+            (Some(Identification::new(ID_EN.clone(), 1.0)), 100),
+            (Some(Identification::new(ID_FR.clone(), 1.0)), 30),
+        ]
+        .into_iter()
+        .cycle();
+        let ids: Vec<(_, usize)> = id.take(20).collect();
+        let m = Multilingual::default();
+        assert!(m.detect(&ids[..]));
+    }
+
+    #[test]
+    fn test_multilingual_bytes_false() {
+        // same number of lines, but french lines are way shorter
+        let id = [
+            (Some(Identification::new(ID_EN.clone(), 1.0)), 100),
+            (Some(Identification::new(ID_FR.clone(), 1.0)), 10),
+        ]
+        .into_iter()
+        .cycle();
+        let ids: Vec<(_, usize)> = id.take(20).collect();
+        let m = Multilingual::default();
+        assert!(!m.detect(&ids[..]));
+
+        // line-based detection would have flagged it
+        let ids: Vec<_> = ids.into_iter().map(|(id, _)| id).collect();
+        assert!(m.detect(&ids[..]));
+    }
+
+    #[test]
+    fn test_kind_from_str() {
+        assert!(matches!(
+            "strict".parse::<MultilingualKind>(),
+            Ok(MultilingualKind::Strict(_))
+        ));
+        assert!(matches!(
+            "ratio".parse::<MultilingualKind>(),
+            Ok(MultilingualKind::Ratio(_))
+        ));
+        assert!("foo".parse::<MultilingualKind>().is_err());
     }
 
     #[test]
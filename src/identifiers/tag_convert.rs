@@ -1,9 +1,23 @@
 //! Conversion utilities or fasttext tags to standardized BCP47.
-use std::{borrow::Cow, collections::HashMap, convert::TryFrom};
+//!
+//! The default conversion table ([NEW_TAG_REPLACE]) targets lid.176 and NLLB labels.
+//! Other models can provide their own table through [LabelMap::from_path].
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    convert::TryFrom,
+    fs::File,
+    io::{BufRead, BufReader},
+    path::Path,
+    sync::Mutex,
+};
 
 use lazy_static::lazy_static;
+use log::{info, warn};
 use oxilangtag::{LanguageTag, LanguageTagParseError};
 
+use crate::error::Error;
+
 lazy_static! {
     pub static ref NEW_TAG_REPLACE: HashMap<&'static str, &'static str> = [
         ("abk", "ab"),
@@ -166,22 +180,142 @@ lazy_static! {
     .collect();
 }
 
+/// Model label to BCP47 conversion table.
+///
+/// Labels that are not in the table are converted by replacing `_` by `-`.
+/// Labels that can't be converted into a valid language tag are counted, and can be reported
+/// once using [LabelMap::report_unknown].
+pub struct LabelMap {
+    labels: HashMap<String, String>,
+    unknown: Mutex<HashMap<String, usize>>,
+}
+
+impl LabelMap {
+    /// Create a new [LabelMap] from a `label -> tag` table.
+    pub fn new(labels: HashMap<String, String>) -> Self {
+        Self {
+            labels,
+            unknown: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Load a conversion table from a file.
+    ///
+    /// Each line holds a model label and its BCP47 tag, separated by whitespace (ex. `eng_Latn\ten`).
+    /// The `__label__` prefix is optional. Empty lines and lines starting with `#` are ignored.
+    ///
+    /// The loaded table replaces the default one.
+    pub fn from_path(path: &Path) -> Result<Self, Error> {
+        let reader = BufReader::new(File::open(path)?);
+        let mut labels = HashMap::new();
+
+        for (line_number, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut columns = line.split_whitespace();
+            match (columns.next(), columns.next(), columns.next()) {
+                (Some(label), Some(tag), None) => {
+                    let label = label.strip_prefix("__label__").unwrap_or(label);
+                    labels.insert(label.to_string(), tag.to_string());
+                }
+                _ => {
+                    return Err(Error::Custom(format!(
+                        "{:?}:{}: expected a label and a tag, got {:?}",
+                        path,
+                        line_number + 1,
+                        line
+                    )))
+                }
+            }
+        }
+
+        info!("loaded {} label mappings from {:?}", labels.len(), path);
+        Ok(Self::new(labels))
+    }
+
+    /// Convert a model label (with or without the `__label__` prefix) into a language tag.
+    ///
+    /// Returns [None] and counts the label if it can't be converted.
+    pub fn convert(&self, label: &str) -> Option<LanguageTag<String>> {
+        match Tag::with_map(label, self).try_into() {
+            Ok(tag) => Some(tag),
+            Err(_) => {
+                let mut unknown = self.unknown.lock().unwrap();
+                *unknown.entry(label.to_string()).or_insert(0) += 1;
+                None
+            }
+        }
+    }
+
+    /// Get the labels that couldn't be converted, along with their number of occurrences.
+    pub fn unknown(&self) -> HashMap<String, usize> {
+        self.unknown.lock().unwrap().clone()
+    }
+
+    /// Log labels that couldn't be converted, along with their number of occurrences.
+    pub fn report_unknown(&self) {
+        let unknown = self.unknown.lock().unwrap();
+        if unknown.is_empty() {
+            return;
+        }
+
+        let mut counts: Vec<_> = unknown.iter().collect();
+        counts.sort_unstable_by(|a, b| b.1.cmp(a.1));
+        warn!(
+            "{} unknown labels were treated as unidentified: {:?}",
+            counts.len(),
+            counts
+        );
+    }
+
+    #[inline]
+    fn get(&self, label: &str) -> Option<&str> {
+        self.labels.get(label).map(String::as_str)
+    }
+}
+
+impl Default for LabelMap {
+    /// Uses [NEW_TAG_REPLACE].
+    fn default() -> Self {
+        Self::new(
+            NEW_TAG_REPLACE
+                .iter()
+                .map(|(label, tag)| (label.to_string(), tag.to_string()))
+                .collect(),
+        )
+    }
+}
+
+lazy_static! {
+    static ref DEFAULT_LABEL_MAP: LabelMap = LabelMap::default();
+}
+
 pub struct Tag<'a> {
     inner: Cow<'a, str>,
 }
 
 impl<'a> Tag<'a> {
+    /// Convert using the default table ([NEW_TAG_REPLACE]).
     pub fn new(tag: &'a str) -> Self {
+        Self::with_map(tag, &DEFAULT_LABEL_MAP)
+    }
+
+    /// Convert using a custom table.
+    pub fn with_map(tag: &'a str, map: &LabelMap) -> Self {
         Self {
-            // attempt to remove first nine chars or pass the whole thing.
-            inner: Tag::fix(&tag.get(9..).unwrap_or(tag)),
+            // attempt to remove the __label__ prefix or pass the whole thing.
+            inner: Tag::fix(tag.strip_prefix("__label__").unwrap_or(tag), map),
         }
     }
 
     #[inline]
-    fn fix(tag: &'a str) -> Cow<'a, str> {
+    fn fix(tag: &'a str, map: &LabelMap) -> Cow<'a, str> {
         // go from __label__foo_bar to foo_bar
-        let tag = match NEW_TAG_REPLACE.get(&tag) {
+        let tag = match map.get(tag) {
             None => Cow::from(tag),
             Some(x) => Cow::from(x.to_string()),
         };
@@ -210,9 +344,11 @@ impl<'a> TryFrom<Tag<'a>> for LanguageTag<String> {
 #[cfg(test)]
 mod tests {
 
+    use std::{collections::HashMap, io::Write};
+
     use oxilangtag::LanguageTag;
 
-    use crate::identifiers::tag_convert::Tag;
+    use crate::identifiers::tag_convert::{LabelMap, Tag};
 
     // use super::{NewTag, OldTag};
 
@@ -250,4 +386,43 @@ mod tests {
             assert_eq!(erroneous, correct);
         }
     }
+
+    #[test]
+    fn test_label_map_from_path() {
+        let mut f = tempfile::NamedTempFile::new().unwrap();
+        writeln!(f, "# OpenLID labels").unwrap();
+        writeln!(f, "__label__eng_Latn\ten").unwrap();
+        writeln!(f).unwrap();
+        writeln!(f, "fra_Latn fr").unwrap();
+
+        let map = LabelMap::from_path(f.path()).unwrap();
+        let en: LanguageTag<String> = Tag::with_map("__label__eng_Latn", &map).try_into().unwrap();
+        let fr: LanguageTag<String> = Tag::with_map("__label__fra_Latn", &map).try_into().unwrap();
+        assert_eq!(en, "en");
+        assert_eq!(fr, "fr");
+
+        // default table is not used anymore
+        let als: LanguageTag<String> = Tag::with_map("__label__als", &map).try_into().unwrap();
+        assert_eq!(als, "als");
+    }
+
+    #[test]
+    fn test_label_map_invalid_file() {
+        let mut f = tempfile::NamedTempFile::new().unwrap();
+        writeln!(f, "eng_Latn en extra").unwrap();
+
+        assert!(LabelMap::from_path(f.path()).is_err());
+    }
+
+    #[test]
+    fn test_unknown_labels() {
+        let map = LabelMap::new(HashMap::new());
+        assert!(map.convert("__label__not a tag").is_none());
+        assert!(map.convert("__label__not a tag").is_none());
+        assert!(map.convert("__label__fr").is_some());
+
+        let unknown = map.unknown();
+        assert_eq!(unknown.len(), 1);
+        assert_eq!(unknown.get("__label__not a tag"), Some(&2));
+    }
 }
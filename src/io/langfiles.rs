@@ -18,8 +18,8 @@ use oxilangtag::LanguageTag;
 use crate::error;
 use crate::error::Error;
 
-// use super::writer::{WriterDoc, WriterTrait};
-use oscar_io::v3::{Writer, WriterTrait};
+use super::writer::Writer;
+use oscar_io::v3::WriterTrait;
 /// Holds references to [Writer].
 // pub struct LangFiles {
 //     writers: HashMap<&'static str, Arc<Mutex<Writer>>>,
@@ -6,6 +6,9 @@ Textual/contextual data saving and loading.
 Currently only saving is implemented but loading is planned in order to facilitate operations on already generated corpora.
 !*/
 mod langfiles;
+mod rejected;
+mod writer;
 // pub use langfiles::LangFiles;
 pub use langfiles::LangFilesDoc;
-// pub use writer::Writer;
+pub use rejected::{RejectedWriter, Rejection, Stage};
+pub use writer::Writer;
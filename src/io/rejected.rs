@@ -0,0 +1,145 @@
+/*! Audit corpus of rejected records.
+
+Records rejected during the pipeline are (optionally) written as JSONL in `rejected.jsonl`, one [Rejection] per line,
+along with the [Stage] and reason of the rejection.
+This enables the measurement of false positive rates of each filter, per language.
+
+Rejections can be sampled (see [RejectedWriter::new]), in which case each rejection is written with the same probability.
+!*/
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+
+/// Pipeline step at which a record has been rejected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Stage {
+    /// Only boilerplate lines (see [crate::transformers::Boilerplate]).
+    Boilerplate,
+    /// No sentences are left after short sentences removal at start/end.
+    ShortSentences,
+    /// Rejected by the record filter (ex. pfilter).
+    RecordFilter,
+    /// No language could be identified with enough confidence.
+    Identification,
+    /// Dropped by the removal policy (ex. noisy+tiny).
+    Removal,
+}
+
+/// A rejected record.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Rejection {
+    pub stage: Stage,
+    pub reason: String,
+    pub warc_id: String,
+    pub url: Option<String>,
+    /// Document language. For rejections happening before identification, this is the language that would have been
+    /// identified, if any.
+    pub lang: Option<String>,
+    pub confidence: Option<f32>,
+    pub content: String,
+}
+
+/// Thread-safe JSONL writer of [Rejection]s.
+pub struct RejectedWriter {
+    writer: Mutex<BufWriter<File>>,
+    sample: f64,
+}
+
+impl RejectedWriter {
+    /// Create a writer in `dst`, creating the folder if needed.
+    ///
+    /// `sample` is the probability of a rejection being written (`1.0` writes every rejection).
+    pub fn new(dst: &Path, sample: f64) -> Result<Self, Error> {
+        if !(0.0..=1.0).contains(&sample) {
+            return Err(Error::Custom(format!(
+                "rejected sample rate must be in [0, 1] (got {sample})"
+            )));
+        }
+
+        if !dst.exists() {
+            std::fs::create_dir_all(dst)?;
+        }
+
+        let f = File::create(dst.join("rejected.jsonl"))?;
+        Ok(Self {
+            writer: Mutex::new(BufWriter::new(f)),
+            sample,
+        })
+    }
+
+    /// Draw whether the next rejection should be written.
+    ///
+    /// Useful to avoid building a [Rejection] (and identifying its content) when it won't be written.
+    pub fn sampled(&self) -> bool {
+        self.sample >= 1.0 || rand::thread_rng().gen_bool(self.sample)
+    }
+
+    /// Write a rejection.
+    pub fn write(&self, rejection: &Rejection) -> Result<(), Error> {
+        let mut writer = self.writer.lock().unwrap();
+        serde_json::to_writer(&mut *writer, rejection)?;
+        writer.write_all(b"\n")?;
+        Ok(())
+    }
+
+    /// Flush the underlying file.
+    pub fn flush(&self) -> Result<(), Error> {
+        self.writer.lock().unwrap().flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader};
+
+    use super::*;
+
+    fn rejection(stage: Stage) -> Rejection {
+        Rejection {
+            stage,
+            reason: "pfilter=0.6".to_string(),
+            warc_id: "<urn:uuid:foo>".to_string(),
+            url: Some("https://example.com".to_string()),
+            lang: Some("en".to_string()),
+            confidence: None,
+            content: "foo\nbar".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_write() {
+        let dir = tempfile::tempdir().unwrap();
+        let w = RejectedWriter::new(dir.path(), 1.0).unwrap();
+        assert!(w.sampled());
+
+        let rejections = vec![rejection(Stage::RecordFilter), rejection(Stage::Removal)];
+        for r in &rejections {
+            w.write(r).unwrap();
+        }
+        w.flush().unwrap();
+
+        let f = File::open(dir.path().join("rejected.jsonl")).unwrap();
+        let read: Vec<Rejection> = BufReader::new(f)
+            .lines()
+            .map(|line| serde_json::from_str(&line.unwrap()).unwrap())
+            .collect();
+        assert_eq!(read, rejections);
+    }
+
+    #[test]
+    fn test_sample() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(RejectedWriter::new(dir.path(), 1.5).is_err());
+
+        let w = RejectedWriter::new(dir.path(), 0.0).unwrap();
+        assert!(!w.sampled());
+    }
+}
@@ -0,0 +1,200 @@
+/*! Rotating JSON Lines document writer for a given language.
+
+Writes [Document]s (along with their fields) with the same layout as [oscar_io::v3::Writer]:
+documents go in `<lang>_meta.jsonl`, and once there's more than one part,
+files are named `<lang>_meta_part_<n>.jsonl` (the first one being renamed to `<lang>_meta_part_1.jsonl`).
+
+A new part is started when the current one reaches the maximum file size (if any), or after [WriterTrait::close_meta].
+Nothing is created unless a write is performed.
+!*/
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use log::{debug, warn};
+use oscar_io::v3::WriterTrait;
+use oxilangtag::LanguageTag;
+
+use crate::pipelines::oscardoc::types::Document;
+
+pub struct Writer {
+    lang: LanguageTag<String>,
+    dst: PathBuf,
+    max_file_size: Option<u64>,
+    file: Option<BufWriter<File>>,
+    file_size: u64,
+    nb_files: u64,
+}
+
+impl Writer {
+    /// Rotate file.
+    ///
+    /// The first file is named `lang_meta.jsonl`, and is renamed `lang_meta_part_1.jsonl` if there's > 1 number of files.
+    fn create_next_file(&mut self) -> std::io::Result<()> {
+        let filename = if self.nb_files == 0 {
+            format!("{}_meta.jsonl", self.lang)
+        } else {
+            format!("{}_meta_part_{}.jsonl", self.lang, self.nb_files + 1)
+        };
+
+        let file = File::create(self.dst.join(filename))?;
+
+        if self.nb_files == 1 {
+            let from = self.dst.join(format!("{}_meta.jsonl", self.lang));
+            let to = self.dst.join(format!("{}_meta_part_1.jsonl", self.lang));
+
+            debug!("renaming {:?} to {:?}", from, to);
+            std::fs::rename(from, to)?;
+        }
+
+        self.file = Some(BufWriter::new(file));
+        self.file_size = 0;
+        self.nb_files += 1;
+        Ok(())
+    }
+
+    /// Write serialized documents in the current file, closing it if it reaches the maximum size.
+    ///
+    /// Documents are never split across files, so a part can be larger than the maximum size.
+    fn write_all(&mut self, buf: &[u8]) -> std::io::Result<()> {
+        if self.file.is_none() {
+            self.create_next_file()?;
+        }
+
+        if let Some(file) = &mut self.file {
+            file.write_all(buf)?;
+            file.flush()?;
+            self.file_size += buf.len() as u64;
+        }
+
+        if let Some(max_file_size) = self.max_file_size {
+            if self.file_size >= max_file_size {
+                self.file = None;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl WriterTrait for Writer {
+    type Item = Document;
+
+    /// Create a new Writer for provided language.
+    /// Files will be written at the root of the `dst` file, and shouldn't exceed `max_file_size`.
+    fn new(
+        dst: &Path,
+        lang: LanguageTag<String>,
+        max_file_size: Option<u64>,
+    ) -> Result<Self, oscar_io::Error> {
+        Ok(Self {
+            lang,
+            dst: dst.to_path_buf(),
+            max_file_size,
+            file: None,
+            file_size: 0,
+            nb_files: 0,
+        })
+    }
+
+    fn write(&mut self, docs: Vec<Document>) -> Result<(), oscar_io::Error> {
+        let mut docs_str = String::new();
+        for doc in docs {
+            docs_str += &serde_json::to_string(&doc)?;
+            docs_str.push('\n');
+        }
+        self.write_all(docs_str.as_bytes())?;
+
+        Ok(())
+    }
+
+    fn write_single(&mut self, doc: &Document) -> Result<(), oscar_io::Error> {
+        let mut doc_str = serde_json::to_string(doc)?;
+        doc_str.push('\n');
+        self.write_all(doc_str.as_bytes())?;
+
+        Ok(())
+    }
+
+    /// Flush and close the current file.
+    fn close_meta(&mut self) -> Result<(), oscar_io::Error> {
+        match self.file.take() {
+            Some(mut file) => file.flush()?,
+            // the last part may have been closed on reaching the maximum size
+            None if self.nb_files > 0 => (),
+            None => warn!("{}: trying to close an unopened Writer.", self.lang),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::path::Path;
+
+    use oscar_io::v3::WriterTrait;
+    use oxilangtag::LanguageTag;
+
+    use crate::pipelines::oscardoc::types::{Document, Metadata};
+    use oscar_io::common::Identification;
+
+    use super::Writer;
+
+    fn doc() -> Document {
+        let lang = LanguageTag::parse("fr".to_string()).unwrap();
+        let id = Identification::new(lang, 1.0);
+        Document::new(
+            "Bonjour\nça va ?".to_string(),
+            HashMap::new(),
+            Metadata::new(&id, &[Some(id.clone()), Some(id.clone())]),
+        )
+    }
+
+    fn read(path: &Path) -> Vec<Document> {
+        std::fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_write() {
+        let dst = tempfile::tempdir().unwrap();
+        let lang = LanguageTag::parse("fr".to_string()).unwrap();
+        let mut w = Writer::new(dst.path(), lang, None).unwrap();
+        assert!(!dst.path().join("fr_meta.jsonl").exists());
+
+        let mut doc = doc();
+        doc.add_field("url:domain", "example.fr");
+
+        w.write(vec![doc.clone(), doc.clone()]).unwrap();
+        w.write_single(&doc).unwrap();
+        w.close_meta().unwrap();
+
+        let docs = read(&dst.path().join("fr_meta.jsonl"));
+        assert_eq!(docs, vec![doc.clone(), doc.clone(), doc]);
+    }
+
+    #[test]
+    fn test_parts() {
+        let dst = tempfile::tempdir().unwrap();
+        let lang = LanguageTag::parse("fr".to_string()).unwrap();
+        let mut w = Writer::new(dst.path(), lang, Some(10)).unwrap();
+
+        let doc = doc();
+        w.write(vec![doc.clone(), doc.clone()]).unwrap();
+        assert!(dst.path().join("fr_meta.jsonl").exists());
+
+        w.write_single(&doc).unwrap();
+        w.write_single(&doc).unwrap();
+        assert!(!dst.path().join("fr_meta.jsonl").exists());
+
+        let parts: Vec<usize> = (1..=3)
+            .map(|part| read(&dst.path().join(format!("fr_meta_part_{part}.jsonl"))).len())
+            .collect();
+        assert_eq!(parts, vec![2, 1, 1]);
+        assert!(!dst.path().join("fr_meta_part_4.jsonl").exists());
+    }
+}
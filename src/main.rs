@@ -5,7 +5,15 @@ use std::fs::File;
 use std::io::Write;
 use structopt::StructOpt;
 
+use crate::filtering::line_frequency::LineFrequencies;
+use crate::filtering::perplexity::PerplexityCutoffs;
+use crate::filtering::sentence::MeanLengths;
+use crate::io::RejectedWriter;
+use crate::pipelines::oscardoc::removal::RemovalPolicy;
 use crate::pipelines::Pipeline;
+use crate::transformers::{
+    Allowlist, Boilerplate, ContentClassifier, LanguageSplit, Pii, QualityThresholds,
+};
 
 #[macro_use]
 extern crate log;
@@ -58,9 +66,56 @@ async fn main() -> Result<(), error::Error> {
 
         cli::Ungoliant::Pipeline(p) => {
             let mut schema_filepath = p.dst.clone();
-            let p =
+            let mut pipeline =
                 pipelines::OscarDocNew::new(p.src, p.dst, p.lid_path, p.blocklist, p.kenlms_path);
-            p.run()?;
+            pipeline.set_lid_labels_map(p.lid_labels_map);
+            pipeline.set_multilingual(p.multilingual);
+            pipeline.set_language_split(p.split_languages.map(LanguageSplit::new));
+            pipeline.set_filter(p.filter);
+            pipeline.set_trimming(p.trimming);
+            pipeline.set_unicode_repair(p.repair_unicode);
+            pipeline.set_url_metadata(p.url_metadata);
+            pipeline.set_pii(p.pii.map(Pii::new));
+            pipeline.set_blocklist_category(p.blocklist_category);
+            if let Some(path) = &p.allowlist {
+                pipeline.set_allowlist(Some(Allowlist::from_path(path)?));
+            }
+            pipeline.set_quality_kenlms_path(p.quality_kenlms_path);
+            pipeline.set_kenlms_capacity(p.kenlms_max_loaded, p.kenlms_max_mb.map(|mb| mb << 20));
+            pipeline.set_kenlms_appendable(p.kenlms_appendable);
+            if let Some(path) = &p.perplexity_cutoffs {
+                pipeline.set_perplexity_cutoffs(Some(PerplexityCutoffs::from_path(path)?));
+            }
+            if let Some(path) = &p.line_frequencies {
+                pipeline.set_boilerplate(Some(Boilerplate::new(
+                    LineFrequencies::from_path(path)?,
+                    p.boilerplate_threshold,
+                    p.boilerplate_mode,
+                )));
+            }
+            if let Some(path) = &p.quality_thresholds {
+                pipeline.set_quality_warnings(Some(QualityThresholds::from_path(path)?));
+            } else if p.quality_warnings {
+                pipeline.set_quality_warnings(Some(QualityThresholds::default()));
+            }
+            pipeline.set_scores(p.scores);
+            if let Some(path) = &p.removal_rules {
+                pipeline.set_removal_policy(RemovalPolicy::from_path(path)?);
+            }
+            if let Some(path) = &p.rejected_dir {
+                pipeline.set_rejected(Some(RejectedWriter::new(path, p.rejected_sample)?));
+            }
+            if let Some(path) = &p.content_lexicons {
+                let mut classifier = ContentClassifier::from_dir(path, p.content_threshold)?;
+                classifier.set_midpoint(p.content_midpoint);
+                pipeline.set_content_classifier(Some(classifier));
+            }
+            if let Some(path) = &p.length_stats {
+                let mut lengths = MeanLengths::from_path(path)?;
+                lengths.set_nb_std(p.length_stats_std);
+                pipeline.set_length_stats(Some(lengths));
+            }
+            pipeline.run()?;
 
             schema_filepath.push("metadata_schema.json");
             info!("creating json schema file {:?}", schema_filepath);
@@ -84,6 +139,53 @@ async fn main() -> Result<(), error::Error> {
             let l = r.lang.parse().expect("unexpected language");
             let rb = processing::rebuild::Rebuilder::new(&r.src_rebuild, &r.src_shards, &r.dst, l);
             rb.run()?;
+        }
+        cli::Ungoliant::EvalLid(e) => {
+            let mut builder = identifiers::model::FastTextBuilder::default();
+            builder.path(&e.lid_path).k(1).threshold(e.threshold);
+            if let Some(labels_path) = &e.lid_labels_map {
+                builder.labels_path(labels_path);
+            }
+            let identifier = builder.build()?;
+
+            let granularity = if e.documents {
+                processing::eval_lid::Granularity::Document
+            } else {
+                processing::eval_lid::Granularity::Line
+            };
+            let settings = processing::eval_lid::DocumentSettings {
+                trimming: e.trimming,
+                multilingual: e.multilingual,
+            };
+            processing::eval_lid::eval_lid(&identifier, &e.src, granularity, &settings, e.bins)?;
+        }
+        cli::Ungoliant::CalibrateLengths(c) => {
+            let mut builder = identifiers::model::FastTextBuilder::default();
+            builder.path(&c.lid_path).k(1).threshold(0.8);
+            if let Some(labels_path) = &c.lid_labels_map {
+                builder.labels_path(labels_path);
+            }
+            let identifier = builder.build()?;
+
+            processing::length_calibration::calibrate_lengths(
+                &c.src,
+                &c.dst,
+                &identifier,
+                c.nb_shards,
+            )?;
+        }
+        cli::Ungoliant::CountLines(c) => {
+            processing::line_frequency::count_lines(
+                &c.src,
+                &c.dst,
+                c.nb_shards,
+                c.per_domain,
+                c.min_count,
+                c.max_lines,
+            )?;
+        }
+        cli::Ungoliant::CalibratePerplexity(c) => {
+            processing::perplexity_calibration::calibrate_perplexity(&c.src, &c.dst, c.sample)?;
         } //cli::Ungoliant::Check(c) => processing::check::check(c.src, c.dst)?,
     };
     Ok(())
@@ -1,5 +1,6 @@
 //! OSCAR Schema v2.0 pipeline
 mod pipeline;
+pub mod removal;
 pub mod types;
 
 pub use pipeline::OscarDoc;
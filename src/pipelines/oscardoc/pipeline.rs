@@ -8,13 +8,23 @@
 //! Each record is composed of a metadata header and a body containing sentences.
 //!
 //! # Processing
+//! 1. Optionally, lines that appear in many documents of the same domain are removed (see [Boilerplate]).
 //! 1. Each record passes through a quality filter that by default checks the content distribution between
-//!   short and long sentences, discarding records where the content is primarly in short sentences. (sentence = newline-separated string)
+//!   short and long sentences, discarding records where the content is primarly in short sentences. (sentence = newline-separated string). Other filters can be used instead (see [record::FilterKind]).
+//! 1. Optionally, mojibake and control characters are repaired and content is normalized to NFC (see [UnicodeRepair]).
 //! 1. The remaining ones get identified both by line and as a whole (we keep the language that has the most information (=bytes)).
-//! 1. We pass the records in the adult content annotator
-//! 1. We remove remaining short sentences at start/end[^1]
+//! 1. Optionally, big blocks of lines in another language are moved to separate documents (see [LanguageSplit]).
+//! 1. We pass the records in the adult content annotator, and optionally in the content classifier (see [ContentClassifier]).
+//! 1. Optionally, URL metadata is added (see [UrlMetadata]).
+//! 1. Optionally, PII is counted (see [Pii]).
+//! 1. We remove remaining short sentences at start/end[^1] (see [TrimmingKind] for the available strategies)
+//! 1. Optionally, PII is redacted, once every annotator (KenLMs included) has seen the original content.
+//! 1. Documents are kept, dropped or diverted to a rejected corpus depending on the [RemovalPolicy].
 //! 1. We then write documents in files.
 //!
+//! Records discarded by boilerplate removal, short sentences removal, the quality filter, identification or the removal policy
+//! can be written in an audit corpus (see [RejectedWriter]).
+//!
 //! [^1]: We should do this after step 1: better efficiency.
 use std::fs::File;
 use std::path::Path;
@@ -22,45 +32,88 @@ use std::path::Path;
 use std::{collections::HashMap, path::PathBuf};
 
 use crate::error::Error;
-use crate::filtering::{record, Filter};
+use crate::filtering::{perplexity::PerplexityCutoffs, record, sentence::MeanLengths, Filter};
 use crate::identifiers::identification::Identification;
 use crate::identifiers::model::{FastText, FastTextBuilder, Predict};
-use crate::identifiers::StrictMultilingual;
+use crate::identifiers::MultilingualKind;
+use crate::pipelines::oscardoc::removal::{Action, RemovalPolicy};
 use crate::pipelines::oscardoc::types::Location;
 use crate::pipelines::oscardoc::types::RebuildWriters;
-use oscar_io::v3::{Document, Metadata, WriterTrait};
+use crate::pipelines::oscardoc::types::{Document, Metadata};
+use oscar_io::v3::WriterTrait;
 
-use crate::pipelines::oscardoc::types::{LocationBuilder, ShardResult};
+use crate::pipelines::oscardoc::types::{LocationBuilder, RebuildInformation, ShardResult};
 use crate::pipelines::pipeline::Pipeline;
 use crate::sources::commoncrawl::Wet;
 
+#[cfg(feature = "kenlm")]
+use crate::transformers::{AdultDetector, AdultDetectorBuilder, QualityModelBuilder};
 use crate::transformers::{
-    self, Annotate, Annotator, ContentDetector, Header, Noisy, ShortSentences, TinyDocument,
-    Transform, LSH,
+    Allowlist, Annotate, Annotator, Boilerplate, BoilerplateMode, Bullets, ContentClassifier,
+    ContentDetector, Duplicates, Ellipsis, Header, LanguageSplit, LengthOutliers, Markers,
+    NgramRepetition, Noisy, Pii, PiiMode, QualityThresholds, ShortSentences, SymbolRatio,
+    TerminalPunctuation, TinyDocument, Transform, TrimmingKind, UnicodeRepair, UrlMetadata, LSH,
 };
-#[cfg(feature = "kenlm")]
-use crate::transformers::{AdultDetector, AdultDetectorBuilder, Models};
+use crate::transformers::{Capacity, ModelBuilder, Models};
+#[cfg(not(feature = "kenlm"))]
+use crate::transformers::{NgramAdultDetectorBuilder, NgramQualityModelBuilder};
 use log::{debug, error, info, log_enabled, warn};
 use oxilangtag::LanguageTag;
 use rayon::prelude::*;
-use ut1_blocklist::MultipleBlocklist;
 use warc::BufferedBody;
 use warc::{Record, WarcHeader};
 
-use crate::io::LangFilesDoc;
+use crate::io::{LangFilesDoc, RejectedWriter, Rejection, Stage};
 
+/// Minimum confidence of a (non multilingual) document identification.
 const DOC_THRESHOLD: f32 = 0.6f32;
+/// Minimum number of calibration lines for a language length distribution to be used.
+const LENGTH_STATS_MIN_MEASURES: u32 = 1000;
+
+/// KenLM-backed models with the `kenlm` feature, pure-Rust ARPA models otherwise.
+#[cfg(feature = "kenlm")]
+type AdultModelBuilder = AdultDetectorBuilder;
+#[cfg(feature = "kenlm")]
+type QualityBuilder = QualityModelBuilder;
+#[cfg(not(feature = "kenlm"))]
+type AdultModelBuilder = NgramAdultDetectorBuilder;
+#[cfg(not(feature = "kenlm"))]
+type QualityBuilder = NgramQualityModelBuilder;
 
 // TODO: Implement structopt directly here.
 pub struct OscarDoc {
     src: PathBuf,
     dst: PathBuf,
     lid_path: PathBuf,
+    lid_labels_map: Option<PathBuf>,
     blocklist: Option<PathBuf>,
     kenlms_path: Option<PathBuf>,
+    quality_kenlms_path: Option<PathBuf>,
+    kenlms_capacity: (Option<usize>, Option<u64>),
+    kenlms_appendable: bool,
+    perplexity_cutoffs: Option<PerplexityCutoffs>,
+    multilingual: MultilingualKind,
+    language_split: Option<LanguageSplit>,
+    filter: record::RecordFilter,
+    length_stats: Option<MeanLengths>,
+    quality_warnings: Option<QualityThresholds>,
+    scores: bool,
+    removal_policy: RemovalPolicy,
+    rejected: Option<RejectedWriter>,
+    trimming: TrimmingKind,
+    boilerplate: Option<Boilerplate>,
+    unicode_repair: bool,
+    pii: Option<Pii>,
+    content_classifier: Option<ContentClassifier>,
+    blocklist_category: Option<String>,
+    allowlist: Option<Allowlist>,
+    url_metadata: bool,
 }
 
 impl OscarDoc {
+    /// Confidence threshold of line identifications.
+    pub const LINE_THRESHOLD: f32 = 0.8;
+
     pub fn new(
         src: PathBuf,
         dst: PathBuf,
@@ -77,11 +130,150 @@ impl OscarDoc {
             src,
             dst,
             lid_path,
+            lid_labels_map: None,
             blocklist,
             kenlms_path,
+            quality_kenlms_path: None,
+            kenlms_capacity: (None, None),
+            kenlms_appendable: false,
+            perplexity_cutoffs: None,
+            multilingual: MultilingualKind::default(),
+            language_split: None,
+            filter: record::RecordFilter::default(),
+            length_stats: None,
+            quality_warnings: None,
+            scores: false,
+            removal_policy: RemovalPolicy::default(),
+            rejected: None,
+            trimming: TrimmingKind::default(),
+            boilerplate: None,
+            unicode_repair: false,
+            pii: None,
+            content_classifier: None,
+            blocklist_category: None,
+            allowlist: None,
+            url_metadata: false,
         }
     }
 
+    /// Use a custom label conversion table for the identification model (see [crate::identifiers::LabelMap::from_path]).
+    pub fn set_lid_labels_map(&mut self, lid_labels_map: Option<PathBuf>) {
+        self.lid_labels_map = lid_labels_map;
+    }
+
+    /// Set the multilingual detector (defaults to [MultilingualKind::Strict]).
+    pub fn set_multilingual(&mut self, multilingual: MultilingualKind) {
+        self.multilingual = multilingual;
+    }
+
+    /// Enable splitting of blocks of lines in other languages into child documents.
+    pub fn set_language_split(&mut self, language_split: Option<LanguageSplit>) {
+        self.language_split = language_split;
+    }
+
+    /// Set the record-level filter (defaults to [record::PFilter]).
+    pub fn set_filter(&mut self, filter: record::FilterKind) {
+        self.filter = record::RecordFilter::new(filter);
+    }
+
+    /// Flag lines whose length is an outlier for their language, using calibrated distributions
+    /// (see [crate::processing::length_calibration]).
+    pub fn set_length_stats(&mut self, length_stats: Option<MeanLengths>) {
+        self.length_stats = length_stats;
+    }
+
+    /// Enable Gopher/C4-style heuristic quality annotators with the provided thresholds
+    /// (see [crate::transformers::Markers] and siblings).
+    pub fn set_quality_warnings(&mut self, quality_warnings: Option<QualityThresholds>) {
+        self.quality_warnings = quality_warnings;
+    }
+
+    /// Record raw annotator scores (letter ratio, short lines fraction, header/footer short lines, line count)
+    /// as `score:<name>` fields, in addition to annotations.
+    pub fn set_scores(&mut self, scores: bool) {
+        self.scores = scores;
+    }
+
+    /// Set the removal policy (defaults to dropping documents that are both `noisy` and `tiny`).
+    ///
+    /// Diverted documents are written in the `rejected` subfolder of the destination.
+    pub fn set_removal_policy(&mut self, removal_policy: RemovalPolicy) {
+        self.removal_policy = removal_policy;
+    }
+
+    /// Write rejected records in an audit corpus.
+    pub fn set_rejected(&mut self, rejected: Option<RejectedWriter>) {
+        self.rejected = rejected;
+    }
+
+    /// Set the head/foot trimming strategy (defaults to [crate::transformers::RemoveShortSentences]).
+    pub fn set_trimming(&mut self, trimming: TrimmingKind) {
+        self.trimming = trimming;
+    }
+
+    /// Remove or annotate boilerplate lines, depending on the [BoilerplateMode].
+    pub fn set_boilerplate(&mut self, boilerplate: Option<Boilerplate>) {
+        self.boilerplate = boilerplate;
+    }
+
+    /// Repair mojibake and control characters and normalize content to NFC before identification
+    /// (see [UnicodeRepair]).
+    pub fn set_unicode_repair(&mut self, unicode_repair: bool) {
+        self.unicode_repair = unicode_repair;
+    }
+
+    /// Count PII in metadata, redacting it depending on the [PiiMode].
+    pub fn set_pii(&mut self, pii: Option<Pii>) {
+        self.pii = pii;
+    }
+
+    /// Only use the `category` list of the blocklist folder, rather than all of them.
+    pub fn set_blocklist_category(&mut self, blocklist_category: Option<String>) {
+        self.blocklist_category = blocklist_category;
+    }
+
+    /// Never flag domains and URLs present in the allowlist, overriding the blocklists.
+    pub fn set_allowlist(&mut self, allowlist: Option<Allowlist>) {
+        self.allowlist = allowlist;
+    }
+
+    /// Add registered domain, TLD, ccTLD, path depth and listing page fields from the document URL
+    /// (see [UrlMetadata]).
+    pub fn set_url_metadata(&mut self, url_metadata: bool) {
+        self.url_metadata = url_metadata;
+    }
+
+    /// Score content against per-language lexicons, adding categories above the classifier threshold.
+    pub fn set_content_classifier(&mut self, content_classifier: Option<ContentClassifier>) {
+        self.content_classifier = content_classifier;
+    }
+
+    /// Set the folder of per-language quality KenLMs, used to record document perplexity
+    /// in the `score:perplexity` field.
+    ///
+    /// Without the `kenlm` feature, only ARPA models are used.
+    pub fn set_quality_kenlms_path(&mut self, quality_kenlms_path: Option<PathBuf>) {
+        self.quality_kenlms_path = quality_kenlms_path;
+    }
+
+    /// Bound the number of loaded KenLMs and/or their size in bytes, for each KenLM folder.
+    /// Least recently used models are unloaded first.
+    pub fn set_kenlms_capacity(&mut self, max_loaded: Option<usize>, max_bytes: Option<u64>) {
+        self.kenlms_capacity = (max_loaded, max_bytes);
+    }
+
+    /// Look for KenLMs of new languages in KenLM folders while running,
+    /// so that models can be added without restarting the pipeline.
+    pub fn set_kenlms_appendable(&mut self, kenlms_appendable: bool) {
+        self.kenlms_appendable = kenlms_appendable;
+    }
+
+    /// Add head/middle/tail buckets in the `pp_bucket` field, using calibrated perplexity cutoffs
+    /// (see [crate::processing::perplexity_calibration]).
+    pub fn set_perplexity_cutoffs(&mut self, perplexity_cutoffs: Option<PerplexityCutoffs>) {
+        self.perplexity_cutoffs = perplexity_cutoffs;
+    }
+
     /// list files in source folder,
     /// filter out errors from fs and from gzip/wet.
     ///
@@ -125,12 +317,21 @@ impl OscarDoc {
     /// This opens the shard, filters/identifies all documents and then
     /// returns the shard id, along with a [Vec] of documents and their relative location (for rebuilding)
     fn process_shard(
+        &self,
         shard_path: &Path,
         identifier: &FastText,
-        filter: Option<record::FilterKind>,
         annotator: &Annotator<Document>,
     ) -> Result<(usize, Vec<(Document, Location)>), Error> {
         info!("working on shard: {:?}", shard_path);
+        let multilingual = &self.multilingual;
+        let language_split = self.language_split.as_ref();
+        let filter = &self.filter;
+        let rejected = self.rejected.as_ref();
+        let unicode_repair = self.unicode_repair.then_some(UnicodeRepair);
+        let boilerplate = self
+            .boilerplate
+            .as_ref()
+            .filter(|b| b.mode() == BoilerplateMode::Remove);
 
         // get shard number
         let shard_id = Self::get_shard_number(shard_path)?;
@@ -158,50 +359,104 @@ impl OscarDoc {
             (loc, record)
         });
 
-        // remove short sentences, discarding documents that only have short sentences
-        let length_filter = transformers::RemoveShortSentences::default();
+        // remove boilerplate lines, discarding documents that only have boilerplate lines
         let record_iter = record_iter.filter_map(|(mut loc, mut record)| {
-            let bounds = length_filter.transform(&mut record);
-            match bounds.len() {
-                0 => {
-                    debug!("record {} has no sentences kept", record.warc_id());
-                    None
-                }
-                1 => {
-                    loc.set_line_start(*bounds[0].start());
-                    loc.set_line_end(*bounds[0].end());
-                    Some((loc, record))
-                }
-                _ => {
-                    warn!(
-                        "record {} has more than one chunk of sentences kept",
-                        record.warc_id()
+            let boilerplate = match boilerplate {
+                Some(boilerplate) => boilerplate,
+                None => return Some((loc, record)),
+            };
+
+            let body = rejected.map(|_| record.body().to_vec());
+            let edits = boilerplate.transform(&mut record);
+            if edits.is_empty() {
+                debug!("record {} only has boilerplate lines", record.warc_id());
+                if let Some(body) = body {
+                    record.replace_body(body);
+                    Self::reject_record(
+                        rejected,
+                        identifier,
+                        Stage::Boilerplate,
+                        "only boilerplate lines",
+                        &record,
                     );
-                    loc.set_line_start(*bounds[0].start());
-                    loc.set_line_end(*bounds[0].end());
-                    Some((loc, record))
                 }
+                None
+            } else {
+                loc.apply(edits);
+                Some((loc, record))
             }
         });
 
-        // get specified filter or resort to default filter kind
-        let f = filter.unwrap_or_default();
+        // remove short sentences at start/end, discarding documents that only have short sentences
+        let record_iter = record_iter.filter_map(|(mut loc, mut record)| {
+            // keep the original body around for the audit corpus, since it is replaced by the transform
+            let body = rejected.map(|_| record.body().to_vec());
+            let edits = self.trimming.transform(&mut record);
+            if edits.is_empty() {
+                debug!("record {} has no sentences kept", record.warc_id());
+                if let Some(body) = body {
+                    record.replace_body(body);
+                    Self::reject_record(
+                        rejected,
+                        identifier,
+                        Stage::ShortSentences,
+                        "no sentences kept",
+                        &record,
+                    );
+                }
+                None
+            } else {
+                // content is made of all kept chunks, which are all recorded for rebuilding.
+                // edits are relative to the content left by boilerplate removal, if any.
+                loc.apply(edits);
+                Some((loc, record))
+            }
+        });
 
         // get iterator on filtered records.
         // only get records that are valid *and* pass the filter.
-        let record_iter = record_iter.filter_map(|(idx, record)| {
-            if f.detect(&record) {
-                Some((idx, record))
-            } else {
-                None
-            }
+        // rejections are recorded with the rejecting filter, rather than its and/or combination.
+        let record_iter =
+            record_iter.filter_map(|(idx, record)| match filter.detect_reason(&record) {
+                None => Some((idx, record)),
+                Some(reason) => {
+                    Self::reject_record(
+                        rejected,
+                        identifier,
+                        Stage::RecordFilter,
+                        &reason,
+                        &record,
+                    );
+                    None
+                }
+            });
+
+        // repair unicode, so that identification works on clean text
+        let record_iter = record_iter.map(|(mut loc, mut record)| {
+            let repairs = unicode_repair.map(|ur| {
+                let (edits, repairs) = ur.repair_record(&mut record);
+                loc.apply(edits);
+                repairs
+            });
+            (loc, record, repairs)
         });
 
         // identify
         let record_iter = record_iter
-            .map(|(loc, record)| (loc, Self::process_record(record, identifier)))
-            .filter_map(|(loc, res)| match res {
-                Ok(Some(res)) => Some((loc, res)),
+            .map(|(loc, record, repairs)| {
+                (
+                    loc,
+                    Self::process_record(record, identifier, multilingual, rejected),
+                    repairs,
+                )
+            })
+            .filter_map(|(loc, res, repairs)| match res {
+                Ok(Some(mut res)) => {
+                    if let Some(repairs) = repairs {
+                        repairs.add_to(&mut res);
+                    }
+                    Some((loc, res))
+                }
                 Ok(None) => None,
                 Err(e) => {
                     error!("{:?}", e);
@@ -209,22 +464,48 @@ impl OscarDoc {
                 }
             });
 
-        // annotate
+        // split blocks of lines in other languages into child documents, removing their lines from the parent.
+        // children share the parent record, with line bounds and edits mapped onto the parent's kept content.
+        let record_iter = record_iter.flat_map_iter(|(loc, mut r)| {
+            let (edits, children) = match language_split {
+                Some(ls) => ls.split(&mut r),
+                None => (None, Vec::new()),
+            };
+
+            let children: Vec<_> = children
+                .into_iter()
+                .map(|(child, range)| {
+                    let mut child_loc = loc.clone();
+                    child_loc.apply(vec![range.clone()].into());
+                    debug!(
+                        "record {} has a {} block at lines {:?}",
+                        r.warc_id(),
+                        child.identification().label(),
+                        range
+                    );
+                    (child_loc, child)
+                })
+                .collect();
+
+            // parents made only of child lines are dropped
+            let parent = match edits {
+                Some(edits) if edits.is_empty() => None,
+                Some(edits) => {
+                    let mut loc = loc;
+                    loc.apply(edits);
+                    Some((loc, r))
+                }
+                None => Some((loc, r)),
+            };
+            parent.into_iter().chain(children)
+        });
+
+        // PII is redacted later on, once KenLMs have run (see redact_pii)
         let record_iter = record_iter.map(|(loc, mut r)| {
             annotator.annotate(&mut r);
             (r, loc.build().unwrap())
         });
 
-        // remove documents that are both tiny and noisy
-        let record_iter = record_iter.filter_map(|(r, loc): (Document, Location)| {
-            if r.metadata().annotation() == Some(&vec!["noisy".to_string(), "tiny".to_string()]) {
-                debug!("removed document {:?} for noisy+tiny", r.warc_id());
-                None
-            } else {
-                Some((r, loc))
-            }
-        });
-
         let records: Vec<(_, _)> = record_iter.collect();
         info!("Shard {}: Got {} documents", shard_id, records.len());
 
@@ -234,9 +515,18 @@ impl OscarDoc {
     /// process a record
     /// identify each line of the document
     /// then compute the most present identification
-    fn process_record(
+    ///
+    /// Multilingual documents are identified as `multi`, with a confidence being the byte-weighted mean of identified line confidences.
+    /// Their constituent languages and byte shares (of identified bytes) are stored as `multi:<lang>` fields.
+    ///
+    /// Records that can't be identified are written in the audit corpus if `rejected` is provided.
+    ///
+    /// Also used to evaluate identification as the pipeline does it (see [crate::processing::eval_lid]).
+    pub(crate) fn process_record(
         record: Record<BufferedBody>,
         identifier: &FastText,
+        multilingual: &MultilingualKind,
+        rejected: Option<&RejectedWriter>,
     ) -> Result<Option<Document>, Error> {
         // get lines
         let (headers, body) = record.into_raw_parts();
@@ -249,22 +539,43 @@ impl OscarDoc {
         let lang_count = w_ids.lang_bins();
         let total_count = w_ids.total_size();
 
-        //TODO fix multilingual
         // see if the record meets multilingual criteria
-        let multilingual = StrictMultilingual::default().detect(ids);
+        let multilingual = multilingual.detect(w_ids.weighted_line_ids().as_slice());
 
         let ids: Vec<_> = ids
-            .into_iter()
+            .iter()
             .map(|id| id.clone().map(|_id| _id.into_inner()))
             .collect();
 
         if multilingual {
-            //TODO: fix prob on multilingual documents
+            // lang bins confidences are weighted by the total byte count, unidentified bytes included:
+            // rescale them so that confidence and shares are over identified bytes only.
+            let unidentified_count = lang_count.get(&None).map_or(0, |(count, _)| *count);
+            let identified_count = (total_count - unidentified_count).max(1);
+
+            // confidence is the byte-weighted mean of line confidences, over identified lines.
+            let confidence: f32 = lang_count
+                .iter()
+                .filter(|(lang, _)| lang.is_some())
+                .map(|(_, (_, confidence))| confidence)
+                .sum::<f32>()
+                * total_count as f32
+                / identified_count as f32;
+
             let document_identification =
-                Identification::new(LanguageTag::parse("multi".to_string())?, 0.5);
+                Identification::new(LanguageTag::parse("multi".to_string())?, confidence);
 
             let metadata = Metadata::new(&document_identification, ids.as_slice());
-            let doc = Document::new(body.into_owned(), headers.headers, metadata);
+            let mut doc = Document::new(body.into_owned(), headers.headers, metadata);
+
+            // add constituent languages along with their byte share
+            for (lang, (lang_byte_count, _)) in lang_count
+                .iter()
+                .filter_map(|(lang, count)| lang.as_ref().map(|lang| (lang, count)))
+            {
+                let share = *lang_byte_count as f64 / identified_count as f64;
+                doc.add_field(&format!("multi:{lang}"), (share * 1000.0).round() / 1000.0);
+            }
 
             return Ok(Some(doc));
         }
@@ -282,6 +593,21 @@ impl OscarDoc {
             );
 
             if confidence < &DOC_THRESHOLD {
+                if let Some(rejected) = rejected.filter(|r| r.sampled()) {
+                    let rejection = Rejection {
+                        stage: Stage::Identification,
+                        reason: format!("confidence below {DOC_THRESHOLD}"),
+                        warc_id: Self::header_string(&headers.headers, WarcHeader::RecordID)
+                            .unwrap_or_default(),
+                        url: Self::header_string(&headers.headers, WarcHeader::TargetURI),
+                        lang: Some(id.to_string()),
+                        confidence: Some(*confidence),
+                        content: body.into_owned(),
+                    };
+                    if let Err(e) = rejected.write(&rejection) {
+                        error!("{:?}", e);
+                    }
+                }
                 return Ok(None);
             }
 
@@ -306,10 +632,83 @@ impl OscarDoc {
                 debug!("{:?}", &lang_count);
                 debug!("{}", &body);
             }
+            if let Some(rejected) = rejected.filter(|r| r.sampled()) {
+                let rejection = Rejection {
+                    stage: Stage::Identification,
+                    reason: "no language identified".to_string(),
+                    warc_id: Self::header_string(&headers.headers, WarcHeader::RecordID)
+                        .unwrap_or_default(),
+                    url: Self::header_string(&headers.headers, WarcHeader::TargetURI),
+                    lang: None,
+                    confidence: None,
+                    content: body.into_owned(),
+                };
+                if let Err(e) = rejected.write(&rejection) {
+                    error!("{:?}", e);
+                }
+            }
             Ok(None)
         }
     }
 
+    /// Get a WARC header as a string.
+    fn header_string(headers: &HashMap<WarcHeader, Vec<u8>>, header: WarcHeader) -> Option<String> {
+        headers
+            .get(&header)
+            .map(|value| String::from_utf8_lossy(value).into_owned())
+    }
+
+    /// Write a record rejected before identification in the audit corpus.
+    ///
+    /// The record is identified (if sampled) so that rejections can be grouped by language.
+    fn reject_record(
+        rejected: Option<&RejectedWriter>,
+        identifier: &FastText,
+        stage: Stage,
+        reason: &str,
+        record: &Record<BufferedBody>,
+    ) {
+        let rejected = match rejected.filter(|r| r.sampled()) {
+            Some(rejected) => rejected,
+            None => return,
+        };
+
+        let content = String::from_utf8_lossy(record.body()).into_owned();
+        let identification = identifier.weighted_ids(content.lines()).map(|ids| {
+            ids.lang_bins()
+                .iter()
+                .max_by_key(|(_, (bytes, _))| *bytes)
+                .and_then(|(lang, (_, confidence))| {
+                    lang.as_ref().map(|lang| (lang.to_string(), *confidence))
+                })
+        });
+
+        let (lang, confidence) = match identification {
+            Ok(Some((lang, confidence))) => (Some(lang), Some(confidence)),
+            Ok(None) => (None, None),
+            Err(e) => {
+                error!("{:?}", e);
+                (None, None)
+            }
+        };
+
+        let rejection = Rejection {
+            stage,
+            reason: reason.to_string(),
+            warc_id: record.warc_id().to_string(),
+            url: record
+                .header(WarcHeader::TargetURI)
+                .map(|url| url.into_owned()),
+            lang,
+            confidence,
+            content,
+        };
+
+        if let Err(e) = rejected.write(&rejection) {
+            error!("{:?}", e);
+        }
+    }
+
     /// Gets a vector of documents and outputs a hashmap listing the documents per language
     fn sort_by_lang(
         documents: Vec<(Document, Location)>,
@@ -325,34 +724,142 @@ impl OscarDoc {
         ret
     }
 
+    /// Apply the removal policy on documents, dropping documents in place and returning diverted ones.
+    ///
+    /// Dropped documents are written in the audit corpus if `rejected` is provided.
+    fn apply_removal_policy(
+        policy: &RemovalPolicy,
+        documents: &mut HashMap<LanguageTag<String>, Vec<(Document, Location)>>,
+        rejected: Option<&RejectedWriter>,
+    ) -> HashMap<LanguageTag<String>, Vec<(Document, Location)>> {
+        let mut diverted = HashMap::new();
+        for (lang, docs) in documents.iter_mut() {
+            let mut diverted_docs = Vec::new();
+            for (doc, loc) in std::mem::take(docs) {
+                let rule = policy.matching_rule(&doc);
+                match rule.map(|rule| rule.action()).unwrap_or(Action::Keep) {
+                    Action::Keep => docs.push((doc, loc)),
+                    Action::Drop => {
+                        debug!("removed document {:?}", doc.warc_id());
+                        if let Some(rejected) = rejected.filter(|r| r.sampled()) {
+                            let rejection = Rejection {
+                                stage: Stage::Removal,
+                                reason: rule.map(|rule| rule.to_string()).unwrap_or_default(),
+                                warc_id: doc.warc_id().into_owned(),
+                                url: doc.url(),
+                                lang: Some(lang.to_string()),
+                                confidence: Some(*doc.identification().prob()),
+                                content: doc.content().clone(),
+                            };
+                            if let Err(e) = rejected.write(&rejection) {
+                                error!("{:?}", e);
+                            }
+                        }
+                    }
+                    Action::Divert => diverted_docs.push((doc, loc)),
+                }
+            }
+
+            if !diverted_docs.is_empty() {
+                diverted.insert(lang.clone(), diverted_docs);
+            }
+        }
+
+        documents.retain(|_, docs| !docs.is_empty());
+        diverted
+    }
+
+    /// Load kenlm models of a given role (used in logs) from `path`, if any.
+    ///
+    /// Errors if `path` is not a directory.
+    fn load_kenlms<B: ModelBuilder>(
+        path: Option<&Path>,
+        role: &str,
+        capacity: Capacity,
+        appendable: bool,
+    ) -> Result<Option<Models<B>>, Error> {
+        let path = match path {
+            Some(path) => path,
+            None => {
+                warn!("No {role} KenLMs path provided, no {role} perplexity will be computed.");
+                return Ok(None);
+            }
+        };
+
+        if !path.is_dir() {
+            return Err(Error::Custom(format!(
+                "{role} KenLMs path must exist and be a dir: {path:?}"
+            )));
+        }
+
+        let mut models = Models::<B>::from_dir(path)?;
+        models.set_capacity(capacity);
+        models.set_appendable(appendable);
+        if models.is_empty() && !appendable {
+            warn!("No {role} KenLMs found in {path:?}, no {role} perplexity will be computed.");
+        }
+        Ok(Some(models))
+    }
+
     /// run kenlm models on data, adding perplexity.
-    #[cfg(feature = "kenlm")]
-    fn run_kenlms(
-        models: &Models,
-        base_model_path: &Path,
+    ///
+    /// Languages with a loaded model go first, so that they are not evicted by the ones that have to be loaded.
+    /// Languages without model are skipped.
+    fn run_kenlms<B>(
+        models: &Models<B>,
         documents: &mut HashMap<LanguageTag<String>, Vec<(Document, Location)>>,
-    ) {
+    ) where
+        B: ModelBuilder,
+        B::Model: Annotate<Document>,
+    {
         debug!("Running kenlms");
-        for (lang, docs) in documents {
-            // attempt to load model for provided lang.
+        let mut langs: Vec<_> = documents.keys().cloned().collect();
+        langs.sort_by_cached_key(|lang| (!models.is_loaded(lang), lang.to_string()));
+
+        for lang in langs {
+            let docs = documents.get_mut(&lang).unwrap();
+            // get (and load if needed) model for provided lang.
             // It's okay if it's not possible.
-            if !models.is_loaded(lang) {
-                if let Err(e) = models.load(lang) {
-                    debug!("Couldn't load model for lang {lang:?}: {e:?}");
+            // The model stays usable even if another shard evicts it meanwhile.
+            match models.get(&lang) {
+                Ok(model) => {
+                    let model = model.read().unwrap();
+                    for (doc, _) in docs.iter_mut() {
+                        model.annotate(doc);
+                    }
                 }
+                Err(e) => debug!("Could not annotate using model {lang}: {e}"),
             }
+        }
+    }
 
-            // TODO: Possible problem here, if between load and get the HM is modified.
-            // Add a way of dealing with that?
-            // possibly creating a scope and then using "direct" method calls rather than
-            // calls that use read/write locks internally.
-            if let Some(model) = models.models().get(lang.as_ref()) {
-                let model = model.read().unwrap();
-                for (doc, _) in docs {
-                    model.annotate(doc);
+    /// redact PII, adding replacements to document locations.
+    ///
+    /// Done once every annotator (KenLMs included) has run, so that they see the original content.
+    fn redact_pii(
+        pii: &Pii,
+        documents: &mut HashMap<LanguageTag<String>, Vec<(Document, Location)>>,
+    ) {
+        for docs in documents.values_mut() {
+            for (doc, loc) in docs.iter_mut() {
+                // every line is kept, so replacements are relative to the current content
+                let (_, replacements) = pii.transform(doc).into_raw_parts();
+                if !replacements.is_empty() {
+                    let edits = loc.edits().iter().cloned().chain(replacements).collect();
+                    *loc = std::mem::take(loc).with_edits(edits);
                 }
-            } else {
-                error!("Could not annotate using model {lang}: No model");
+            }
+        }
+    }
+
+    /// add perplexity buckets using per-language cutoffs.
+    fn add_perplexity_buckets(
+        cutoffs: &PerplexityCutoffs,
+        documents: &mut HashMap<LanguageTag<String>, Vec<(Document, Location)>>,
+    ) {
+        for (lang, docs) in documents {
+            for (doc, _) in docs {
+                cutoffs.add_to(lang.as_str(), doc);
             }
         }
     }
@@ -389,9 +896,16 @@ impl OscarDoc {
                 let (docs, locations): (Vec<_>, Vec<_>) =
                     docs.into_iter().map(|(doc, loc)| (doc, loc)).unzip();
 
-                // clone metadata
-                let metadata_cloned = docs.iter().map(|doc| doc.metadata().clone()).collect();
-                let mut sr = ShardResult::new(shard_id as i64, locations, metadata_cloned);
+                // clone metadata and fields
+                let rebuild_info = docs
+                    .iter()
+                    .zip(locations)
+                    .map(|(doc, loc)| {
+                        RebuildInformation::new(loc, doc.metadata().clone())
+                            .with_fields(doc.fields())
+                    })
+                    .collect();
+                let mut sr = ShardResult::new(shard_id as i64, rebuild_info);
                 sr.sort();
 
                 // write docs and rebuild files
@@ -426,11 +940,17 @@ impl Pipeline<()> for OscarDoc {
     fn run(&self) -> Result<(), Error> {
         // let errors;
 
-        let cls = FastTextBuilder::default()
-            .path(&self.lid_path)
-            .k(1)
-            .threshold(0.8)
-            .build()?;
+        let cls = {
+            let mut builder = FastTextBuilder::default();
+            builder
+                .path(&self.lid_path)
+                .k(1)
+                .threshold(Self::LINE_THRESHOLD);
+            if let Some(labels_path) = &self.lid_labels_map {
+                builder.labels_path(labels_path);
+            }
+            builder.build()?
+        };
 
         if !self.dst.exists() {
             warn!("Destination file does not exist. Creating");
@@ -449,34 +969,97 @@ impl Pipeline<()> for OscarDoc {
         let results = results.enumerate().par_bridge();
 
         let langfiles = LangFilesDoc::new(&self.dst, None);
-        #[cfg(feature = "kenlm")]
-        let kenlms = if let Some(kenlms_path) = &self.kenlms_path {
-            if !kenlms_path.is_dir() {
-                panic!("KenLMs path must exist and be a dir! {kenlms_path:?}");
-            }
-            Models::from_dir(kenlms_path)?
-        } else {
-            /*  TODO: Remove panic here.
-                We should either:
-                    - Have an "appendable" switch which enables Models to find binaries at runtime (with write lock cost)
-                    - Crash on no kenlms provided OR have a warning to indicate that no kenlm annotations will be done.
-            */
-            panic!("No kenlms path provided but feature turned on!");
+        let kenlms_capacity = Capacity::new(self.kenlms_capacity.0, self.kenlms_capacity.1);
+        let kenlms = Self::load_kenlms::<AdultModelBuilder>(
+            self.kenlms_path.as_deref(),
+            "adult content",
+            kenlms_capacity,
+            self.kenlms_appendable,
+        )?;
+        let quality_kenlms = match &self.quality_kenlms_path {
+            Some(path) => Self::load_kenlms::<QualityBuilder>(
+                Some(path),
+                "quality",
+                kenlms_capacity,
+                self.kenlms_appendable,
+            )?,
+            None => None,
         };
 
         let annotator = {
+            let mut tiny = TinyDocument::default();
+            let mut short_sentences = ShortSentences::default();
+            let mut header = Header::default();
+            let mut noisy = Noisy::default();
+            tiny.set_scores(self.scores);
+            short_sentences.set_scores(self.scores);
+            header.set_scores(self.scores);
+            noisy.set_scores(self.scores);
+
             let mut annotator = Annotator::default();
             annotator
-                .add(Box::new(TinyDocument::default()))
-                .add(Box::new(ShortSentences::default()))
-                .add(Box::new(Header::default()))
+                .add(Box::new(tiny))
+                .add(Box::new(short_sentences))
+                .add(Box::new(header))
                 .add(Box::new(LSH::default()))
-                .add(Box::new(Noisy::default()));
+                .add(Box::new(noisy));
+
+            // add heuristic quality annotators
+            if let Some(thresholds) = &self.quality_warnings {
+                annotator
+                    .add(Box::new(TerminalPunctuation::new(
+                        thresholds.terminal_punctuation,
+                    )))
+                    .add(Box::new(Bullets::new(thresholds.bullets)))
+                    .add(Box::new(Ellipsis::new(thresholds.ellipsis)))
+                    .add(Box::new(SymbolRatio::new(thresholds.symbols)))
+                    .add(Box::new(Duplicates::new(
+                        thresholds.duplicate_items,
+                        thresholds.duplicate_chars,
+                    )))
+                    .add(Box::new(NgramRepetition::new(
+                        thresholds.ngram_top.clone(),
+                        thresholds.ngram_dup.clone(),
+                    )))
+                    .add(Box::new(Markers::new(thresholds.markers.clone())));
+            }
+
+            // add domain and url fields
+            if self.url_metadata {
+                annotator.add(Box::new(UrlMetadata));
+            }
+
+            // count PII
+            if let Some(pii) = self.pii {
+                annotator.add(Box::new(pii));
+            }
+
+            // flag boilerplate lines if they are not removed
+            if let Some(boilerplate) = &self.boilerplate {
+                if boilerplate.mode() == BoilerplateMode::Annotate {
+                    annotator.add(Box::new(boilerplate.clone()));
+                }
+            }
+
+            // flag length outliers if length distributions are provided
+            if let Some(length_stats) = &self.length_stats {
+                annotator.add(Box::new(LengthOutliers::new(
+                    length_stats.clone(),
+                    LENGTH_STATS_MIN_MEASURES,
+                )));
+            }
 
             // add ut1 blocklists for categories
             if let Some(path) = &self.blocklist {
-                let bl = MultipleBlocklist::from_dir(&path)?;
-                annotator.add(Box::new(ContentDetector::new(bl)));
+                let mut detector =
+                    ContentDetector::from_dir(path, self.blocklist_category.as_deref())?;
+                detector.set_allowlist(self.allowlist.clone());
+                annotator.add(Box::new(detector));
+            }
+
+            // after the blocklists, that replace categories
+            if let Some(content_classifier) = &self.content_classifier {
+                annotator.add(Box::new(content_classifier.clone()));
             }
 
             annotator
@@ -487,9 +1070,26 @@ impl Pipeline<()> for OscarDoc {
 
         let rebuild_files = RebuildWriters::with_dst(&dst_rebuild)?;
 
+        // diverted documents are written in a separate corpus, with its own rebuild files
+        let rejected = if self.removal_policy.diverts() {
+            let dst_rejected = self.dst.join("rejected");
+            if !dst_rejected.exists() {
+                std::fs::create_dir(&dst_rejected)?;
+            }
+            let dst_rejected_rebuild = dst_rejected.join("rebuild");
+            let rejected_rebuild_files = RebuildWriters::with_dst(&dst_rejected_rebuild)?;
+            Some((
+                LangFilesDoc::new(&dst_rejected, None),
+                rejected_rebuild_files,
+                dst_rejected_rebuild,
+            ))
+        } else {
+            None
+        };
+
         //iterate over shards
         let shards_results =
-            results.map(|(idx, shard)| (idx, Self::process_shard(&shard, &cls, None, &annotator)));
+            results.map(|(idx, shard)| (idx, self.process_shard(&shard, &cls, &annotator)));
 
         // for each shard result, sort by lang and write concurrently.
         shards_results.for_each(|(idx, shard_result)| {
@@ -498,9 +1098,34 @@ impl Pipeline<()> for OscarDoc {
 
                 // run kenlms after identification so that shard results are already
                 // sorted by language.
-                #[cfg(feature = "kenlm")]
-                if let Some(kenlms_path) = &self.kenlms_path {
-                    Self::run_kenlms(&kenlms, kenlms_path, &mut hm);
+                if let Some(kenlms) = &kenlms {
+                    Self::run_kenlms(kenlms, &mut hm);
+                }
+                if let Some(quality_kenlms) = &quality_kenlms {
+                    Self::run_kenlms(quality_kenlms, &mut hm);
+                }
+                if let Some(cutoffs) = &self.perplexity_cutoffs {
+                    Self::add_perplexity_buckets(cutoffs, &mut hm);
+                }
+                if let Some(pii) = self.pii.filter(|pii| pii.mode() == PiiMode::Redact) {
+                    Self::redact_pii(&pii, &mut hm);
+                }
+
+                // apply removal policy after kenlms so that rules can use harmful_pp and perplexity buckets.
+                let diverted = Self::apply_removal_policy(
+                    &self.removal_policy,
+                    &mut hm,
+                    self.rejected.as_ref(),
+                );
+                if let Some((langfiles, rebuild_files, dst_rebuild)) = &rejected {
+                    Self::write_documents(
+                        langfiles,
+                        rebuild_files,
+                        dst_rebuild,
+                        shard_id,
+                        diverted,
+                    )
+                    .unwrap();
                 }
 
                 Self::write_documents(&langfiles, &rebuild_files, &dst_rebuild, shard_id, hm)
@@ -510,6 +1135,19 @@ impl Pipeline<()> for OscarDoc {
             }
         });
 
+        cls.labels().report_unknown();
+        self.filter.report();
+        self.removal_policy.report();
+        if let Some(kenlms) = &kenlms {
+            kenlms.report();
+        }
+        if let Some(quality_kenlms) = &quality_kenlms {
+            quality_kenlms.report();
+        }
+        if let Some(rejected) = &self.rejected {
+            rejected.flush()?;
+        }
+
         Ok(())
     }
 }
@@ -0,0 +1,308 @@
+/*! Removal policy for annotated documents.
+
+Once documents are identified and annotated, a [RemovalPolicy] decides whether they are kept, dropped or
+diverted to a side corpus of rejected documents (for auditing).
+
+A policy is an ordered list of [Rule]s, each one being a [Predicate] and an [Action].
+The action of the first matching rule is applied, and documents that match no rule are kept.
+
+Policies are loaded from JSON files:
+
+```json
+{
+  "rules": [
+    {"name": "noisy_tiny", "when": {"all": [{"annotation": "noisy"}, {"annotation": "tiny"}]}, "action": "drop"},
+    {"name": "keep_short_adult", "when": {"all": [{"category": "adult"}, {"length": {"below": 100}}]}, "action": "keep"},
+    {"name": "adult", "when": {"any": [{"category": "adult"}, {"harmful_pp": {"below": 20.0}}]}, "action": "divert"},
+    {"name": "low_confidence", "when": {"confidence": {"below": 0.7}}, "action": "divert"}
+  ]
+}
+```
+
+The default policy drops documents that are both `noisy` and `tiny`.
+!*/
+use std::fmt;
+use std::fs::File;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use log::info;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::error::Error;
+
+use super::types::Document;
+
+/// Bounds on a value.
+///
+/// A value is in range if it is strictly above `above` and strictly below `below`.
+/// Missing bounds are not checked.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Range {
+    #[serde(default)]
+    above: Option<f64>,
+    #[serde(default)]
+    below: Option<f64>,
+}
+
+impl Range {
+    pub fn new(above: Option<f64>, below: Option<f64>) -> Self {
+        Self { above, below }
+    }
+
+    /// Check if `value` is within bounds.
+    pub fn contains(&self, value: f64) -> bool {
+        self.above.iter().all(|above| value > *above)
+            && self.below.iter().all(|below| value < *below)
+    }
+}
+
+/// Predicates over document metadata and content.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Predicate {
+    /// Document has the annotation.
+    Annotation(String),
+    /// Document has the category.
+    Category(String),
+    /// Adult content perplexity is in range. Documents without perplexity do not match.
+    HarmfulPp(Range),
+    /// Document identification confidence is in range.
+    Confidence(Range),
+    /// Document length (in chars) is in range.
+    Length(Range),
+    /// The `score:<name>` field is in range. Documents without the score do not match.
+    Score { name: String, range: Range },
+    /// The `name` field has the value (ex. `{"field": {"name": "pp_bucket", "value": "tail"}}`).
+    Field { name: String, value: Value },
+    /// Every predicate matches.
+    All(Vec<Predicate>),
+    /// At least one predicate matches.
+    Any(Vec<Predicate>),
+    /// The predicate does not match.
+    Not(Box<Predicate>),
+}
+
+impl Predicate {
+    /// Check if the document matches the predicate.
+    pub fn matches(&self, doc: &Document) -> bool {
+        let metadata = doc.metadata();
+        match self {
+            Self::Annotation(annotation) => metadata
+                .annotation()
+                .is_some_and(|annotations| annotations.contains(annotation)),
+            Self::Category(category) => metadata
+                .categories()
+                .is_some_and(|categories| categories.contains(category)),
+            Self::HarmfulPp(range) => metadata
+                .harmful_pp()
+                .is_some_and(|pp| range.contains(pp as f64)),
+            Self::Confidence(range) => range.contains(*doc.identification().prob() as f64),
+            Self::Length(range) => range.contains(doc.content().chars().count() as f64),
+            Self::Score { name, range } => {
+                doc.score(name).is_some_and(|score| range.contains(score))
+            }
+            Self::Field { name, value } => doc.field(name) == Some(value),
+            Self::All(predicates) => predicates.iter().all(|p| p.matches(doc)),
+            Self::Any(predicates) => predicates.iter().any(|p| p.matches(doc)),
+            Self::Not(predicate) => !predicate.matches(doc),
+        }
+    }
+}
+
+/// What to do with documents matching a rule.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Keep,
+    Drop,
+    /// Write the document in the rejected corpus rather than in the main one.
+    Divert,
+}
+
+/// A named predicate along with the action to take for matching documents.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Rule {
+    #[serde(default)]
+    name: Option<String>,
+    when: Predicate,
+    action: Action,
+    #[serde(skip)]
+    matched: AtomicUsize,
+}
+
+impl Rule {
+    pub fn new(name: Option<String>, when: Predicate, action: Action) -> Self {
+        Self {
+            name,
+            when,
+            action,
+            matched: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn action(&self) -> Action {
+        self.action
+    }
+
+    /// Get the number of documents this rule has been applied to.
+    pub fn matched(&self) -> usize {
+        self.matched.load(Ordering::Relaxed)
+    }
+}
+
+/// Ordered list of removal rules.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RemovalPolicy {
+    rules: Vec<Rule>,
+}
+
+impl RemovalPolicy {
+    pub fn new(rules: Vec<Rule>) -> Self {
+        Self { rules }
+    }
+
+    /// Load a policy from a JSON file.
+    pub fn from_path(path: &Path) -> Result<Self, Error> {
+        let f = File::open(path)?;
+        Ok(serde_json::from_reader(f)?)
+    }
+
+    /// Get the first rule matching the document.
+    pub fn matching_rule(&self, doc: &Document) -> Option<&Rule> {
+        let rule = self.rules.iter().find(|rule| rule.when.matches(doc));
+        if let Some(rule) = rule {
+            rule.matched.fetch_add(1, Ordering::Relaxed);
+        }
+        rule
+    }
+
+    /// Get the action of the first rule matching the document, or [Action::Keep] if none matches.
+    pub fn action(&self, doc: &Document) -> Action {
+        self.matching_rule(doc)
+            .map(Rule::action)
+            .unwrap_or(Action::Keep)
+    }
+
+    /// Check if some documents can be diverted.
+    pub fn diverts(&self) -> bool {
+        self.rules.iter().any(|rule| rule.action == Action::Divert)
+    }
+
+    /// Log the number of documents each rule has been applied to.
+    pub fn report(&self) {
+        for rule in &self.rules {
+            info!("removal rule {}: {} documents", rule, rule.matched());
+        }
+    }
+}
+
+/// Rules are displayed as their name (or predicate if unnamed) along with their action.
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.name {
+            Some(name) => write!(f, "{} ({:?})", name, self.action),
+            None => write!(f, "{:?} ({:?})", self.when, self.action),
+        }
+    }
+}
+
+impl Default for RemovalPolicy {
+    fn default() -> Self {
+        Self::new(vec![Rule::new(
+            Some("noisy_tiny".to_string()),
+            Predicate::All(vec![
+                Predicate::Annotation("noisy".to_string()),
+                Predicate::Annotation("tiny".to_string()),
+            ]),
+            Action::Drop,
+        )])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use oxilangtag::LanguageTag;
+    use std::collections::HashMap;
+
+    use crate::identifiers::identification::Identification;
+    use crate::pipelines::oscardoc::types::{Document, Metadata};
+
+    use super::*;
+
+    fn doc(content: &str, prob: f32, annotations: &[&str]) -> Document {
+        let id = Identification::new(LanguageTag::parse("en".to_string()).unwrap(), prob);
+        let mut metadata = Metadata::new(&id, &[]);
+        for annotation in annotations {
+            metadata.add_annotation(annotation.to_string());
+        }
+        Document::new(content.to_string(), HashMap::new(), metadata)
+    }
+
+    #[test]
+    fn test_default_any_order() {
+        let policy = RemovalPolicy::default();
+        let tiny_noisy = doc("foo", 1.0, &["tiny", "short_sentences", "noisy"]);
+        let noisy_tiny = doc("foo", 1.0, &["noisy", "tiny"]);
+        let tiny = doc("foo", 1.0, &["tiny"]);
+
+        assert_eq!(policy.action(&tiny_noisy), Action::Drop);
+        assert_eq!(policy.action(&noisy_tiny), Action::Drop);
+        assert_eq!(policy.action(&tiny), Action::Keep);
+        assert_eq!(policy.rules[0].matched(), 2);
+    }
+
+    #[test]
+    fn test_first_match() {
+        let policy: RemovalPolicy = serde_json::from_str(
+            r#"{"rules": [
+                {"when": {"length": {"below": 5}}, "action": "keep"},
+                {"name": "low_confidence", "when": {"confidence": {"below": 0.7}}, "action": "divert"},
+                {"when": {"not": {"annotation": "header"}}, "action": "drop"}
+            ]}"#,
+        )
+        .unwrap();
+
+        assert!(policy.diverts());
+        assert_eq!(policy.action(&doc("foo", 0.5, &[])), Action::Keep);
+        assert_eq!(policy.action(&doc("foo bar", 0.5, &[])), Action::Divert);
+        assert_eq!(policy.action(&doc("foo bar", 0.9, &[])), Action::Drop);
+        assert_eq!(
+            policy.action(&doc("foo bar", 0.9, &["header"])),
+            Action::Keep
+        );
+    }
+
+    #[test]
+    fn test_metadata_predicates() {
+        let mut d = doc("foo", 1.0, &[]);
+        d.add_score("letter_ratio", 0.4);
+        d.add_field("pp_bucket", "tail");
+        d.metadata_mut().add_category("adult".to_string());
+
+        assert!(Predicate::Category("adult".to_string()).matches(&d));
+        assert!(!Predicate::Category("gambling".to_string()).matches(&d));
+
+        let score = |name: &str| Predicate::Score {
+            name: name.to_string(),
+            range: Range::new(None, Some(0.5)),
+        };
+        assert!(score("letter_ratio").matches(&d));
+        assert!(!score("short_lines_ratio").matches(&d));
+
+        let bucket: Predicate =
+            serde_json::from_str(r#"{"field": {"name": "pp_bucket", "value": "tail"}}"#).unwrap();
+        assert!(bucket.matches(&d));
+        assert!(!Predicate::Field {
+            name: "pp_bucket".to_string(),
+            value: "head".into()
+        }
+        .matches(&d));
+
+        let harmful = Predicate::HarmfulPp(Range::new(None, Some(20.0)));
+        assert!(!harmful.matches(&d));
+        d.metadata_mut().set_harmful_pp(Some(10.0));
+        assert!(harmful.matches(&d));
+    }
+}
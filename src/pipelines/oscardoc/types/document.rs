@@ -0,0 +1,240 @@
+/*! OSCAR Document with keyed fields.
+
+[oscar_io::v3::Metadata] has a fixed set of fields, so [Document] wraps an [oscar_io::v3::Document]
+and adds a map of keyed fields (counts, URL information, annotator scores…).
+
+Fields are serialized in the `fields` object of the document metadata,
+so that documents stay readable by [oscar_io] readers, that ignore it.
+Documents without fields are serialized exactly as [oscar_io::v3::Document]s.
+Quality warnings (`metadata.quality_warnings`) are kept for actual warnings.
+
+Raw annotator scores are stored as `score:<name>` fields, so that corpora can be re-thresholded afterwards.
+!*/
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::ops::{Deref, DerefMut};
+
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
+use warc::WarcHeader;
+
+use super::Metadata;
+
+/// Keyed document fields, sorted by key.
+pub type Fields = BTreeMap<String, Value>;
+
+/// Key of the fields object in the serialized metadata.
+const FIELDS_KEY: &str = "fields";
+
+/// Decimals kept for scores (see [Document::add_score]).
+const SCORE_DECIMALS: i32 = 4;
+
+/// An [oscar_io::v3::Document] along with its keyed fields.
+///
+/// Dereferences to [oscar_io::v3::Document], so that content, headers and metadata are accessed the same way.
+#[derive(Clone, PartialEq)]
+pub struct Document {
+    inner: oscar_io::v3::Document,
+    fields: Fields,
+}
+
+impl Document {
+    pub fn new(
+        content: String,
+        warc_headers: HashMap<WarcHeader, Vec<u8>>,
+        metadata: Metadata,
+    ) -> Self {
+        Self {
+            inner: oscar_io::v3::Document::new(content, warc_headers, metadata),
+            fields: Fields::new(),
+        }
+    }
+
+    /// Replace the document fields.
+    pub fn with_fields(mut self, fields: Fields) -> Self {
+        self.fields = fields;
+        self
+    }
+
+    /// Add (or replace) a field.
+    pub fn add_field(&mut self, key: &str, value: impl Into<Value>) {
+        self.fields.insert(key.to_string(), value.into());
+    }
+
+    /// Get the value of a field.
+    pub fn field(&self, key: &str) -> Option<&Value> {
+        self.fields.get(key)
+    }
+
+    /// Get the document fields.
+    pub fn fields(&self) -> &Fields {
+        &self.fields
+    }
+
+    /// Add a `score:<name>` field, rounded to 4 decimals.
+    ///
+    /// Non-finite scores are stored as `null`.
+    pub fn add_score(&mut self, name: &str, score: f64) {
+        let factor = 10f64.powi(SCORE_DECIMALS);
+        self.add_field(&format!("score:{name}"), (score * factor).round() / factor);
+    }
+
+    /// Get the value of the `score:<name>` field, if it exists and is a number.
+    pub fn score(&self, name: &str) -> Option<f64> {
+        self.field(&format!("score:{name}")).and_then(Value::as_f64)
+    }
+}
+
+impl Deref for Document {
+    type Target = oscar_io::v3::Document;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+impl DerefMut for Document {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.inner
+    }
+}
+
+impl std::fmt::Debug for Document {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Document")
+            .field("inner", &self.inner)
+            .field("fields", &self.fields)
+            .finish()
+    }
+}
+
+/// Serializable version of [Document].
+///
+/// Keeps the [oscar_io::v3::Document] layout, so that documents without fields are serialized the same way.
+#[derive(Serialize)]
+struct DocumentSer<'a> {
+    content: &'a str,
+    warc_headers: HashMap<&'a WarcHeader, Cow<'a, str>>,
+    metadata: MetadataSer<'a>,
+}
+
+/// Serializable [Metadata], along with the document fields.
+#[derive(Serialize)]
+struct MetadataSer<'a> {
+    #[serde(flatten)]
+    metadata: &'a Metadata,
+    #[serde(skip_serializing_if = "Fields::is_empty")]
+    fields: &'a Fields,
+}
+
+impl Serialize for Document {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let warc_headers = self
+            .warc_headers()
+            .iter()
+            .map(|(k, v)| (k, String::from_utf8_lossy(v)))
+            .collect();
+
+        DocumentSer {
+            content: self.content(),
+            warc_headers,
+            metadata: MetadataSer {
+                metadata: self.metadata(),
+                fields: &self.fields,
+            },
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Document {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut doc = Value::deserialize(deserializer)?;
+        let fields = doc
+            .get_mut("metadata")
+            .and_then(Value::as_object_mut)
+            .and_then(|metadata| metadata.remove(FIELDS_KEY));
+        let fields = match fields {
+            Some(fields) => serde_json::from_value(fields).map_err(D::Error::custom)?,
+            None => Fields::new(),
+        };
+        let inner = serde_json::from_value(doc).map_err(D::Error::custom)?;
+
+        Ok(Self { inner, fields })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use oxilangtag::LanguageTag;
+    use serde_json::json;
+
+    use crate::pipelines::oscardoc::types::Metadata;
+    use oscar_io::common::Identification;
+
+    use super::Document;
+
+    fn doc() -> Document {
+        let id = Identification::new(LanguageTag::parse("en".to_string()).unwrap(), 1.0);
+        let mut metadata = Metadata::new(&id, &[Some(id.clone())]);
+        metadata.add_annotation("tiny".to_string());
+        Document::new("foo".to_string(), HashMap::new(), metadata)
+    }
+
+    #[test]
+    fn test_add_get() {
+        let mut d = doc();
+        d.add_field("multi:en", 0.5);
+        d.add_field("url:domain", "example.com");
+        d.add_field("multi:en", 0.75);
+
+        assert_eq!(d.field("multi:en"), Some(&json!(0.75)));
+        assert_eq!(d.field("url:domain"), Some(&json!("example.com")));
+        assert_eq!(d.field("tiny"), None);
+        assert_eq!(d.fields().len(), 2);
+        assert_eq!(d.metadata().annotation(), Some(&vec!["tiny".to_string()]));
+    }
+
+    #[test]
+    fn test_scores() {
+        let mut d = doc();
+        d.add_score("letter_ratio", 2.0 / 3.0);
+        d.add_score("nb_lines", 12.0);
+        d.add_field("score:foo", "bar");
+
+        assert_eq!(d.score("letter_ratio"), Some(0.6667));
+        assert_eq!(d.score("nb_lines"), Some(12.0));
+        assert_eq!(d.score("foo"), None);
+        assert_eq!(d.score("baz"), None);
+    }
+
+    #[test]
+    fn test_serde() {
+        let mut d = doc();
+        // same layout as oscar_io documents
+        assert_eq!(
+            serde_json::to_string(&d).unwrap(),
+            serde_json::to_string(&*d).unwrap()
+        );
+
+        d.add_field("pii:email", 2);
+        d.add_score("perplexity", 120.5);
+        let ser = serde_json::to_string(&d).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&ser).unwrap();
+        assert_eq!(
+            value["metadata"]["fields"],
+            json!({"pii:email": 2, "score:perplexity": 120.5})
+        );
+        assert_eq!(value["metadata"]["quality_warnings"], json!(["tiny"]));
+
+        let d2: Document = serde_json::from_str(&ser).unwrap();
+        assert_eq!(d, d2);
+
+        // still readable as an oscar_io document
+        let d3: oscar_io::v3::Document = serde_json::from_str(&ser).unwrap();
+        assert_eq!(&d3, &*d);
+    }
+}
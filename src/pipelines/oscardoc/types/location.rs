@@ -1,7 +1,10 @@
 use std::convert::{TryFrom, TryInto};
+use std::ops::RangeInclusive;
 
 use serde::{Deserialize, Serialize};
 
+use crate::transformers::{Edit, Edits};
+
 /// Incomplete location error type.
 ///
 /// uses [LocationKind] to inform which field is missing.
@@ -17,8 +20,7 @@ pub struct IncompleteLocation {
 pub enum LocationKind {
     ShardId,
     RecordID,
-    LineStart,
-    LineEnd,
+    LineRanges,
     LocInShard,
 }
 
@@ -30,8 +32,8 @@ pub enum LocationKind {
 pub struct LocationBuilder {
     shard_id: Option<usize>,
     record_id: Option<String>,
-    line_start: Option<usize>,
-    line_end: Option<usize>,
+    line_ranges: Option<Vec<RangeInclusive<usize>>>,
+    edits: Vec<Edit>,
     loc_in_shard: Option<usize>,
 }
 
@@ -46,14 +48,81 @@ impl<'a> LocationBuilder {
         self.record_id = Some(record_id);
     }
 
-    /// Set the partial location's line start.
-    pub fn set_line_start(&mut self, line_start: usize) {
-        self.line_start = Some(line_start);
+    /// Set the partial location's kept line ranges.
+    pub fn set_line_ranges(&mut self, line_ranges: Vec<RangeInclusive<usize>>) {
+        self.line_ranges = Some(line_ranges);
+    }
+
+    /// Get the partial location's kept line ranges, if set.
+    pub fn line_ranges(&self) -> Option<&[RangeInclusive<usize>]> {
+        self.line_ranges.as_deref()
     }
 
-    /// Set the partial location's line end.
-    pub fn set_line_end(&mut self, line_end: usize) {
-        self.line_end = Some(line_end);
+    /// Get the partial location's replacements in kept content.
+    pub fn edits(&self) -> &[Edit] {
+        &self.edits
+    }
+
+    /// Record the [Edits] made by a transform on the kept content.
+    ///
+    /// Kept line ranges are mapped onto record lines (see [LocationBuilder::content_ranges]),
+    /// previous replacements on removed lines are discarded and the other ones are renumbered,
+    /// then the new replacements are added.
+    pub fn apply(&mut self, edits: Edits) {
+        let (lines, replacements) = edits.into_raw_parts();
+
+        let mut kept = Vec::with_capacity(self.edits.len() + replacements.len());
+        for edit in self.edits.drain(..) {
+            // get the new index of the edited line, if it is kept
+            let mut offset = 0;
+            for range in &lines {
+                if range.contains(&edit.line()) {
+                    let line = offset + edit.line() - range.start();
+                    kept.push(edit.with_line(line));
+                    break;
+                }
+                offset += range.end() - range.start() + 1;
+            }
+        }
+        kept.extend(replacements);
+
+        let line_ranges = lines
+            .iter()
+            .flat_map(|range| self.content_ranges(range))
+            .collect();
+        self.line_ranges = Some(line_ranges);
+        self.edits = kept;
+    }
+
+    /// Map a range of lines of the kept content to line ranges of the record.
+    ///
+    /// Kept content is the concatenation of the kept line ranges,
+    /// so a single content range can span several record ranges.
+    /// If line ranges are not set, content lines are record lines.
+    pub fn content_ranges(&self, range: &RangeInclusive<usize>) -> Vec<RangeInclusive<usize>> {
+        let line_ranges = match &self.line_ranges {
+            Some(line_ranges) => line_ranges,
+            None => return vec![range.clone()],
+        };
+
+        let mut ranges = Vec::new();
+        let mut offset = 0;
+        for line_range in line_ranges {
+            let len = line_range.end() - line_range.start() + 1;
+
+            // intersect with the content lines covered by this line range
+            let start = (*range.start()).max(offset);
+            let end = (*range.end()).min(offset + len - 1);
+            if start <= end {
+                ranges.push(
+                    line_range.start() + (start - offset)..=line_range.start() + (end - offset),
+                );
+            }
+
+            offset += len;
+        }
+
+        ranges
     }
 
     /// Set the partial location's loc in shard.
@@ -74,8 +143,8 @@ impl<'a> Default for LocationBuilder {
         Self {
             shard_id: None,
             record_id: None,
-            line_start: None,
-            line_end: None,
+            line_ranges: None,
+            edits: Vec::new(),
             loc_in_shard: None,
         }
     }
@@ -93,12 +162,12 @@ impl<'a> TryFrom<LocationBuilder> for Location {
             missing: LocationKind::RecordID,
         })?;
 
-        let line_start = value.line_start.ok_or(IncompleteLocation {
-            missing: LocationKind::LineStart,
-        })?;
-        let line_end = value.line_end.ok_or(IncompleteLocation {
-            missing: LocationKind::LineEnd,
-        })?;
+        let line_ranges = value
+            .line_ranges
+            .filter(|line_ranges| !line_ranges.is_empty())
+            .ok_or(IncompleteLocation {
+                missing: LocationKind::LineRanges,
+            })?;
         let loc_in_shard = value.loc_in_shard.ok_or(IncompleteLocation {
             missing: LocationKind::LocInShard,
         })?;
@@ -106,8 +175,8 @@ impl<'a> TryFrom<LocationBuilder> for Location {
         Ok(Location {
             shard_id,
             record_id,
-            line_start,
-            line_end,
+            line_ranges,
+            edits: value.edits,
             loc_in_shard,
         })
     }
@@ -115,24 +184,26 @@ impl<'a> TryFrom<LocationBuilder> for Location {
 /// Links a record id to a set location in a shard:
 /// - shard_id is the shard number (ex. 12345.txt.gz)
 /// - record_id is the record id :)
-/// - line_start/line_end are the boundaries of kept text (inclusive)
+/// - line_ranges are the boundaries of kept text (inclusive), in order.
+///   Document content is the concatenation of those lines.
+/// - edits are the replacements made on that content (see [Edit]), in order.
 /// - loc_in_shard is the record index _in_ shard.
 ///
 /// # Example
 /// If we're working on the 10th record of a shard that is shard 100,
 /// that the record has 10 lines and we only keep the first 5,
-/// We'd get `line_start=0, line_end=4, loc_in_shard=99`.
+/// We'd get `line_ranges=[0..=4], loc_in_shard=99`.
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
 pub struct Location {
     shard_id: usize,
     record_id: String,
-    line_start: usize,
-    line_end: usize,
+    line_ranges: Vec<RangeInclusive<usize>>,
+    edits: Vec<Edit>,
     loc_in_shard: usize,
 }
 
 impl Location {
-    /// Create a new [Location].
+    /// Create a new [Location] with a single line range.
     ///
     /// Depending on usage, [LocationBuilder] can be more convinient.
     pub fn new(
@@ -141,16 +212,36 @@ impl Location {
         line_start: usize,
         line_end: usize,
         loc_in_shard: usize,
+    ) -> Self {
+        Self::with_line_ranges(
+            shard_id,
+            record_id,
+            vec![line_start..=line_end],
+            loc_in_shard,
+        )
+    }
+
+    /// Create a new [Location] with multiple line ranges.
+    pub fn with_line_ranges(
+        shard_id: usize,
+        record_id: String,
+        line_ranges: Vec<RangeInclusive<usize>>,
+        loc_in_shard: usize,
     ) -> Self {
         Self {
             shard_id,
             record_id,
-            line_start,
-            line_end,
+            line_ranges,
+            edits: Vec::new(),
             loc_in_shard,
         }
     }
 
+    /// Set the replacements made on the kept content.
+    pub fn with_edits(self, edits: Vec<Edit>) -> Self {
+        Self { edits, ..self }
+    }
+
     /// Get a reference to the location's shard id.
     pub fn shard_id(&self) -> usize {
         self.shard_id
@@ -161,14 +252,30 @@ impl Location {
         self.record_id.as_ref()
     }
 
-    /// Get a reference to the location's line start.
+    /// Get the location's first kept line.
     pub fn line_start(&self) -> usize {
-        self.line_start
+        self.line_ranges
+            .first()
+            .map(|range| *range.start())
+            .unwrap_or_default()
     }
 
-    /// Get a reference to the location's line end.
+    /// Get the location's last kept line.
     pub fn line_end(&self) -> usize {
-        self.line_end
+        self.line_ranges
+            .last()
+            .map(|range| *range.end())
+            .unwrap_or_default()
+    }
+
+    /// Get a reference to the location's kept line ranges.
+    pub fn line_ranges(&self) -> &[RangeInclusive<usize>] {
+        &self.line_ranges
+    }
+
+    /// Get a reference to the location's replacements in kept content.
+    pub fn edits(&self) -> &[Edit] {
+        &self.edits
     }
 
     /// Get a reference to the location's loc in shard.
@@ -182,8 +289,8 @@ impl Default for Location {
         Self {
             shard_id: Default::default(),
             record_id: Default::default(),
-            line_start: Default::default(),
-            line_end: Default::default(),
+            line_ranges: vec![0..=0],
+            edits: Vec::new(),
             loc_in_shard: Default::default(),
         }
     }
@@ -196,6 +303,7 @@ mod tests {
 
     use super::Location;
     use super::LocationBuilder;
+    use super::{Edit, Edits};
 
     #[test]
     fn location_build_incomplete() {
@@ -208,8 +316,7 @@ mod tests {
         let mut lb = LocationBuilder::default();
         let (rid, ls, le, lis, si) = ("record_id", 0, 10, 1, 4);
         lb.set_record_id(rid.to_string());
-        lb.set_line_start(ls);
-        lb.set_line_end(le);
+        lb.set_line_ranges(vec![ls..=le]);
         lb.set_loc_in_shard(lis);
         lb.set_shard_id(si);
         let loc_built = lb.build();
@@ -220,4 +327,59 @@ mod tests {
 
         assert_eq!(location, loc_built);
     }
+
+    #[test]
+    fn location_build_empty_ranges() {
+        let mut lb = LocationBuilder::default();
+        lb.set_record_id("record_id".to_string());
+        lb.set_line_ranges(Vec::new());
+        lb.set_loc_in_shard(1);
+        lb.set_shard_id(4);
+        assert!(lb.build().is_err());
+    }
+
+    #[test]
+    fn content_ranges() {
+        let mut lb = LocationBuilder::default();
+        assert_eq!(lb.content_ranges(&(1..=2)), vec![1..=2]);
+
+        // content is made of record lines 2,3,4 then 10,11
+        lb.set_line_ranges(vec![2..=4, 10..=11]);
+        assert_eq!(lb.content_ranges(&(0..=1)), vec![2..=3]);
+        assert_eq!(lb.content_ranges(&(3..=4)), vec![10..=11]);
+        assert_eq!(lb.content_ranges(&(1..=3)), vec![3..=4, 10..=10]);
+        assert!(lb.content_ranges(&(5..=6)).is_empty());
+    }
+
+    #[test]
+    fn apply() {
+        let mut lb = LocationBuilder::default();
+        lb.set_record_id("record_id".to_string());
+        lb.set_loc_in_shard(1);
+        lb.set_shard_id(4);
+
+        // keep record lines 1..=4, replacing a span of the last one
+        lb.apply(Edits::new(
+            vec![1..=4],
+            vec![
+                Edit::new(0, 0, 1, "a".to_string()),
+                Edit::new(3, 0, 1, "b".to_string()),
+            ],
+        ));
+        // remove the first kept line
+        lb.apply(Edits::new(
+            vec![1..=3],
+            vec![Edit::new(2, 1, 1, "c".to_string())],
+        ));
+
+        let loc = lb.build().unwrap();
+        assert_eq!(loc.line_ranges(), &[2..=4]);
+        assert_eq!(
+            loc.edits(),
+            &[
+                Edit::new(2, 0, 1, "b".to_string()),
+                Edit::new(2, 1, 1, "c".to_string()),
+            ]
+        );
+    }
 }
@@ -1,14 +1,12 @@
 /*! OSCAR Document types.
 
 * !*/
-// mod document;
+mod document;
 mod location;
 mod rebuild;
 
-// pub use document::Document;
-// pub use document::Metadata;
+pub use document::{Document, Fields};
 pub use location::{IncompleteLocation, Location, LocationBuilder};
-pub use oscar_io::v3::Document;
 pub use oscar_io::v3::Metadata;
 pub use rebuild::RebuildInformation;
 pub use rebuild::RebuildWriters;
@@ -11,12 +11,18 @@ Those slim documents contain:
 - record id,
 - line start/end for each WARC Record. Note that `line_start and line_end` are _included_,
 so a document that has `(line_start, line_end) == (10, 10)` has a single line that is at offset 10.
+- kept line ranges (`line_ranges`, with the same inclusive bounds), for documents made of several chunks of the record.
+- replacements made on the kept lines (`edits`, see [Edit]), that are replayed in order after line selection.
+- keyed document fields (`fields`, see [Fields]), with JSON-encoded values.
+
+Empty line ranges (as in older rebuild files) mean that the document is made of lines `line_start..=line_end`.
 
 !*/
 
 use std::{
     collections::HashMap,
     fs::File,
+    ops::RangeInclusive,
     path::{Path, PathBuf},
     sync::{Arc, Mutex, RwLock},
 };
@@ -30,7 +36,8 @@ use structopt::lazy_static::lazy_static;
 
 use crate::error::Error;
 
-use crate::pipelines::oscardoc::types::{Location, Metadata};
+use crate::pipelines::oscardoc::types::{Fields, Location, Metadata};
+use crate::transformers::Edit;
 
 lazy_static! {
     static ref SCHEMA: Schema = {
@@ -70,8 +77,27 @@ lazy_static! {
     {"name": "record_id", "type":"string"},
     {"name": "line_start", "type":"long"},
     {"name": "line_end", "type":"long"},
+    {"name": "line_ranges", "type": {"type": "array", "items": {
+      "type": "record",
+      "name": "line_range",
+      "fields": [
+        {"name": "start", "type": "long"},
+        {"name": "end", "type": "long"}
+      ]
+    }}, "default": []},
+    {"name": "edits", "type": {"type": "array", "items": {
+      "type": "record",
+      "name": "edit",
+      "fields": [
+        {"name": "line", "type": "long"},
+        {"name": "start", "type": "long"},
+        {"name": "end", "type": "long"},
+        {"name": "text", "type": "string"}
+      ]
+    }}, "default": []},
     {"name": "loc_in_shard", "type":"long"},
-    {"name":"metadata", "type":"metadata_record"}
+    {"name":"metadata", "type":"metadata_record"},
+    {"name": "fields", "type": {"type": "map", "values": "string"}, "default": {}}
   ]
 }
 "#;
@@ -98,7 +124,32 @@ lazy_static! {
     };
 }
 
-/// Holds the same fields as [Location], adding [Metadata].
+/// Inclusive line range, as stored in rebuild files.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+pub struct LineRange {
+    start: usize,
+    end: usize,
+}
+
+impl From<&RangeInclusive<usize>> for LineRange {
+    fn from(range: &RangeInclusive<usize>) -> Self {
+        Self {
+            start: *range.start(),
+            end: *range.end(),
+        }
+    }
+}
+
+impl From<LineRange> for RangeInclusive<usize> {
+    fn from(range: LineRange) -> Self {
+        range.start..=range.end
+    }
+}
+
+/// Holds the same fields as [Location], adding [Metadata] and document [Fields].
+///
+/// `line_start` and `line_end` are the first and last kept lines,
+/// and are kept alongside `line_ranges` so that older rebuild files can still be read.
 ///
 /// Should be transformed into a struct that holds two attributes rather than copying some.
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
@@ -107,8 +158,14 @@ pub struct RebuildInformation {
     record_id: String,
     line_start: usize,
     line_end: usize,
+    #[serde(default)]
+    line_ranges: Vec<LineRange>,
+    #[serde(default)]
+    edits: Vec<Edit>,
     loc_in_shard: usize,
     metadata: Metadata,
+    #[serde(default)]
+    fields: HashMap<String, String>,
 }
 
 impl RebuildInformation {
@@ -119,21 +176,34 @@ impl RebuildInformation {
             record_id: location.record_id().to_owned(),
             line_start: location.line_start(),
             line_end: location.line_end(),
+            line_ranges: location.line_ranges().iter().map(LineRange::from).collect(),
+            edits: location.edits().to_vec(),
             loc_in_shard: location.loc_in_shard(),
             metadata,
+            fields: HashMap::new(),
         }
     }
 
+    /// Set the document fields.
+    pub fn with_fields(mut self, fields: &Fields) -> Self {
+        self.fields = fields
+            .iter()
+            .map(|(key, value)| (key.clone(), value.to_string()))
+            .collect();
+        self
+    }
+
     /// Convert into a ([Location], [Metadata]) tuple.
     pub fn into_raw_parts(self) -> (Location, Metadata) {
+        let line_ranges = self.line_ranges();
         (
-            Location::new(
+            Location::with_line_ranges(
                 self.shard_id,
                 self.record_id,
-                self.line_start,
-                self.line_end,
+                line_ranges,
                 self.loc_in_shard,
-            ),
+            )
+            .with_edits(self.edits),
             self.metadata,
         )
     }
@@ -157,11 +227,41 @@ impl RebuildInformation {
         self.line_end
     }
 
+    /// Get the rebuild information's kept line ranges.
+    ///
+    /// Falls back on `line_start..=line_end` for rebuild information that has no line ranges.
+    pub fn line_ranges(&self) -> Vec<RangeInclusive<usize>> {
+        if self.line_ranges.is_empty() {
+            vec![self.line_start..=self.line_end]
+        } else {
+            self.line_ranges.iter().copied().map(Into::into).collect()
+        }
+    }
+
+    /// Get a reference to the rebuild information's replacements, to apply after line selection.
+    pub fn edits(&self) -> &[Edit] {
+        &self.edits
+    }
+
     /// Get a reference to the rebuild information's metadata.
     pub fn metadata(&self) -> &Metadata {
         &self.metadata
     }
 
+    /// Get the rebuild information's document fields.
+    ///
+    /// Values that are not valid JSON are kept as strings.
+    pub fn fields(&self) -> Fields {
+        self.fields
+            .iter()
+            .map(|(key, value)| {
+                let value = serde_json::from_str(value)
+                    .unwrap_or_else(|_| serde_json::Value::String(value.clone()));
+                (key.clone(), value)
+            })
+            .collect()
+    }
+
     /// Get a reference to the rebuild information's shard id.
     pub fn shard_id(&self) -> usize {
         self.shard_id
@@ -176,13 +276,7 @@ pub struct ShardResult {
 }
 
 impl ShardResult {
-    /// Merges `locations` and `metadata` into [RebuildInformation].
-    pub fn new(shard_id: i64, locations: Vec<Location>, metadata: Vec<Metadata>) -> Self {
-        let rebuild_info = locations
-            .into_iter()
-            .zip(metadata.into_iter())
-            .map(|(loc, meta)| RebuildInformation::new(loc, meta))
-            .collect();
+    pub fn new(shard_id: i64, rebuild_info: Vec<RebuildInformation>) -> Self {
         Self {
             shard_id,
             rebuild_info,
@@ -344,7 +438,8 @@ mod tests {
 
     use oxilangtag::LanguageTag;
 
-    use crate::pipelines::oscardoc::types::{Location, Metadata};
+    use crate::pipelines::oscardoc::types::{Fields, Location, Metadata};
+    use crate::transformers::Edit;
 
     use super::{RebuildInformation, RebuildWriter, RebuildWriters, ShardResult};
 
@@ -360,7 +455,7 @@ mod tests {
     }
     #[test]
     fn test_ser_empty() {
-        let sr = ShardResult::new(0, Vec::new(), Vec::new());
+        let sr = ShardResult::new(0, Vec::new());
         println!("{:#?}", sr);
         let buf = Vec::new();
         let mut rw = RebuildWriter::new(&super::SCHEMA, buf);
@@ -378,9 +473,12 @@ mod tests {
             let loc = Location::new(1, id.to_string(), 0, 10, loc);
             locs.push(loc);
         }
-        let metas = vec![Metadata::default(); 3];
+        let rebuild_info = locs
+            .into_iter()
+            .map(|loc| RebuildInformation::new(loc, Metadata::default()))
+            .collect();
 
-        let mut sr = ShardResult::new(1, locs, metas);
+        let mut sr = ShardResult::new(1, rebuild_info);
 
         // unsorted, will be sorted manually
         let mut locs_unsorted: Vec<_> = sr
@@ -418,9 +516,12 @@ mod tests {
             let loc = Location::new(1, id.to_string(), 0, 10, loc);
             locs.push(loc);
         }
-        let metas = vec![Metadata::default(); 3];
+        let rebuild_info = locs
+            .into_iter()
+            .map(|loc| RebuildInformation::new(loc, Metadata::default()))
+            .collect();
 
-        let mut sr = ShardResult::new(1, locs, metas);
+        let mut sr = ShardResult::new(1, rebuild_info);
 
         let (shard_id, rebuild_info) = sr.clone().into_raw_parts();
 
@@ -434,9 +535,8 @@ mod tests {
 
     #[test]
     fn test_ser() {
-        let meta = vec![Metadata::default()];
-        let loc = vec![Location::default()];
-        let sr = ShardResult::new(0, loc, meta);
+        let ri = RebuildInformation::new(Location::default(), Metadata::default());
+        let sr = ShardResult::new(0, vec![ri]);
         println!("{:#?}", sr);
         println!("{:#?}", *super::SCHEMA);
         let mut buf = Vec::new();
@@ -453,6 +553,62 @@ mod tests {
         assert_eq!(result[0], sr);
     }
 
+    #[test]
+    fn test_ser_line_ranges() {
+        let loc = Location::with_line_ranges(0, "record".to_string(), vec![1..=2, 5..=5], 3)
+            .with_edits(vec![Edit::new(2, 0, 3, "café".to_string())]);
+        let ri = RebuildInformation::new(loc.clone(), Metadata::default());
+        let sr = ShardResult::new(0, vec![ri]);
+        let mut buf = Vec::new();
+        let mut rw = RebuildWriter::new(&super::SCHEMA, &mut buf);
+
+        rw.append_ser(&sr).unwrap();
+        rw.flush().unwrap();
+
+        let ar = avro_rs::Reader::new(&buf[..]).unwrap();
+        let result: Vec<ShardResult> = ar
+            .map(|r| avro_rs::from_value::<ShardResult>(&r.unwrap()).unwrap())
+            .collect();
+        let (_, rebuild_info) = result[0].clone().into_raw_parts();
+        let (loc2, _) = rebuild_info[0].clone().into_raw_parts();
+
+        assert_eq!(loc2, loc);
+        assert_eq!((loc2.line_start(), loc2.line_end()), (1, 5));
+    }
+
+    #[test]
+    fn test_ser_fields() {
+        let mut fields = Fields::new();
+        fields.insert("url:domain".to_string(), serde_json::json!("example.com"));
+        fields.insert("score:perplexity".to_string(), serde_json::json!(120.5));
+        let ri =
+            RebuildInformation::new(Location::default(), Metadata::default()).with_fields(&fields);
+        let sr = ShardResult::new(0, vec![ri]);
+        let mut buf = Vec::new();
+        let mut rw = RebuildWriter::new(&super::SCHEMA, &mut buf);
+
+        rw.append_ser(&sr).unwrap();
+        rw.flush().unwrap();
+
+        let ar = avro_rs::Reader::new(&buf[..]).unwrap();
+        let result: Vec<ShardResult> = ar
+            .map(|r| avro_rs::from_value::<ShardResult>(&r.unwrap()).unwrap())
+            .collect();
+        assert_eq!(result[0], sr);
+        assert_eq!(result[0].rebuild_info()[0].fields(), fields);
+    }
+
+    #[test]
+    fn test_line_ranges_fallback() {
+        let mut ri = RebuildInformation::new(
+            Location::new(0, "r".to_string(), 2, 4, 0),
+            Metadata::default(),
+        );
+        ri.line_ranges.clear();
+
+        assert_eq!(ri.line_ranges(), vec![2..=4]);
+    }
+
     #[test]
     fn test_rebuild_writers_contains() {
         let rbw = RebuildWriters::<usize> {
@@ -0,0 +1,466 @@
+//! Language identification evaluation.
+//!
+//! Runs a language identification model on gold-labelled lines or documents,
+//! and reports per-language precision/recall/F1, a confusion matrix and a reliability (calibration) curve.
+//!
+//! Identification is done the way the pipeline does it, so that reported scores apply to the pipeline:
+//! lines are identified with the pipeline line threshold by default (see [OscarDoc::LINE_THRESHOLD]),
+//! and documents go through head/foot trimming then the pipeline document identification, multilingual detection
+//! and document confidence threshold included. Documents the pipeline would discard count as missing predictions.
+//!
+//! The labelled file is a TSV file holding one `<gold label>\t<text>` pair per line.
+//! Gold labels have to use the same tags as the ones emitted by the model (after label conversion).
+//! In document mode, newlines in text are escaped as `\n`.
+use std::{
+    collections::{BTreeSet, HashMap},
+    fs::File,
+    io::{BufRead, BufReader, Write},
+    path::Path,
+};
+
+use log::warn;
+
+use warc::{BufferedBody, Record};
+
+use crate::{
+    error::Error,
+    identifiers::{
+        model::{FastText, Predict},
+        MultilingualKind,
+    },
+    pipelines::oscardoc::OscarDoc,
+    transformers::{Transform, TrimmingKind},
+};
+
+/// Label used for missing predictions in reports.
+const NO_PREDICTION: &str = "-";
+
+/// Confidence thresholds reported in the threshold sweep.
+const SWEEP_THRESHOLDS: [f32; 10] = [0.0, 0.1, 0.2, 0.3, 0.4, 0.5, 0.6, 0.7, 0.8, 0.9];
+
+/// Evaluation granularity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Granularity {
+    /// Each example is a single line, identified with [Predict::predict_one].
+    Line,
+    /// Each example is a document, identified as the pipeline does (see [OscarDoc]).
+    Document,
+}
+
+/// Precision, recall and F1 of a single language.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LanguageScore {
+    pub lang: String,
+    pub precision: f32,
+    pub recall: f32,
+    pub f1: f32,
+    /// number of gold examples
+    pub support: usize,
+}
+
+/// A bin of the reliability curve.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReliabilityBin {
+    /// lower (inclusive) and upper confidence bounds
+    pub bounds: (f32, f32),
+    pub count: usize,
+    pub mean_confidence: f32,
+    pub accuracy: f32,
+}
+
+/// Accumulates predictions against gold labels.
+pub struct Evaluation {
+    /// (gold, predicted) -> count
+    confusion: HashMap<(String, Option<String>), usize>,
+    /// per-bin (count, sum of confidences, correct predictions)
+    bins: Vec<(usize, f32, usize)>,
+    /// (confidence, correct) for each prediction, used by the threshold sweep
+    predictions: Vec<(f32, bool)>,
+    nb_examples: usize,
+}
+
+impl Evaluation {
+    /// Create a new evaluation with `nb_bins` reliability bins.
+    pub fn new(nb_bins: usize) -> Self {
+        Self {
+            confusion: HashMap::new(),
+            bins: vec![(0, 0.0, 0); nb_bins.max(1)],
+            predictions: Vec::new(),
+            nb_examples: 0,
+        }
+    }
+
+    /// Add a prediction (label, confidence) for an example labelled `gold`.
+    pub fn add(&mut self, gold: &str, prediction: Option<(&str, f32)>) {
+        self.nb_examples += 1;
+        *self
+            .confusion
+            .entry((
+                gold.to_string(),
+                prediction.map(|(label, _)| label.to_string()),
+            ))
+            .or_insert(0) += 1;
+
+        if let Some((label, confidence)) = prediction {
+            let correct = label == gold;
+            let nb_bins = self.bins.len();
+            let bin_idx = ((confidence * nb_bins as f32) as usize).min(nb_bins - 1);
+            let bin = &mut self.bins[bin_idx];
+            bin.0 += 1;
+            bin.1 += confidence;
+            if correct {
+                bin.2 += 1;
+            }
+            self.predictions.push((confidence, correct));
+        }
+    }
+
+    /// Get the number of evaluated examples.
+    pub fn nb_examples(&self) -> usize {
+        self.nb_examples
+    }
+
+    /// Get the overall accuracy (missing predictions count as errors).
+    pub fn accuracy(&self) -> f32 {
+        let correct: usize = self
+            .confusion
+            .iter()
+            .filter(|((gold, pred), _)| pred.as_deref() == Some(gold.as_str()))
+            .map(|(_, count)| count)
+            .sum();
+        ratio(correct, self.nb_examples)
+    }
+
+    /// Get every label seen either as a gold label or as a prediction, sorted.
+    fn labels(&self) -> BTreeSet<&str> {
+        self.confusion
+            .keys()
+            .flat_map(|(gold, pred)| std::iter::once(gold.as_str()).chain(pred.as_deref()))
+            .collect()
+    }
+
+    /// Get precision, recall and F1 for each language.
+    pub fn language_scores(&self) -> Vec<LanguageScore> {
+        self.labels()
+            .into_iter()
+            .map(|lang| {
+                let (mut tp, mut gold_count, mut pred_count) = (0, 0, 0);
+                for ((gold, pred), count) in &self.confusion {
+                    let is_gold = gold == lang;
+                    let is_pred = pred.as_deref() == Some(lang);
+                    if is_gold {
+                        gold_count += count;
+                    }
+                    if is_pred {
+                        pred_count += count;
+                    }
+                    if is_gold && is_pred {
+                        tp += count;
+                    }
+                }
+
+                let precision = ratio(tp, pred_count);
+                let recall = ratio(tp, gold_count);
+                let f1 = if precision + recall == 0.0 {
+                    0.0
+                } else {
+                    2.0 * precision * recall / (precision + recall)
+                };
+
+                LanguageScore {
+                    lang: lang.to_string(),
+                    precision,
+                    recall,
+                    f1,
+                    support: gold_count,
+                }
+            })
+            .collect()
+    }
+
+    /// Get the reliability curve, skipping empty bins.
+    pub fn reliability(&self) -> Vec<ReliabilityBin> {
+        let nb_bins = self.bins.len() as f32;
+        self.bins
+            .iter()
+            .enumerate()
+            .filter(|(_, (count, _, _))| *count > 0)
+            .map(|(idx, (count, confidence_sum, correct))| ReliabilityBin {
+                bounds: (idx as f32 / nb_bins, (idx + 1) as f32 / nb_bins),
+                count: *count,
+                mean_confidence: confidence_sum / *count as f32,
+                accuracy: ratio(*correct, *count),
+            })
+            .collect()
+    }
+
+    /// Get the expected calibration error, that is the count-weighted mean of
+    /// `|accuracy - mean confidence|` over reliability bins.
+    pub fn expected_calibration_error(&self) -> f32 {
+        let nb_predictions = self.predictions.len();
+        self.reliability()
+            .iter()
+            .map(|bin| {
+                ratio(bin.count, nb_predictions) * (bin.accuracy - bin.mean_confidence).abs()
+            })
+            .sum()
+    }
+
+    /// For each threshold, get the share of examples that would be kept (coverage)
+    /// and the accuracy over those examples.
+    pub fn threshold_sweep(&self) -> Vec<(f32, f32, f32)> {
+        SWEEP_THRESHOLDS
+            .iter()
+            .map(|threshold| {
+                let (kept, correct) = self
+                    .predictions
+                    .iter()
+                    .filter(|(confidence, _)| confidence >= threshold)
+                    .fold((0, 0), |(kept, correct), (_, is_correct)| {
+                        (kept + 1, correct + usize::from(*is_correct))
+                    });
+                (
+                    *threshold,
+                    ratio(kept, self.nb_examples),
+                    ratio(correct, kept),
+                )
+            })
+            .collect()
+    }
+
+    /// Write a human readable report.
+    pub fn report<W: Write>(&self, mut w: W) -> Result<(), Error> {
+        writeln!(w, "examples: {}", self.nb_examples())?;
+        writeln!(w, "accuracy: {:.4}", self.accuracy())?;
+        writeln!(
+            w,
+            "expected calibration error: {:.4}",
+            self.expected_calibration_error()
+        )?;
+
+        writeln!(w, "\n# per-language scores")?;
+        writeln!(w, "lang\tprecision\trecall\tf1\tsupport")?;
+        for score in self.language_scores() {
+            writeln!(
+                w,
+                "{}\t{:.4}\t{:.4}\t{:.4}\t{}",
+                score.lang, score.precision, score.recall, score.f1, score.support
+            )?;
+        }
+
+        // rows are gold labels, columns are predictions
+        let labels = self.labels();
+        writeln!(w, "\n# confusion matrix (rows: gold, columns: predicted)")?;
+        write!(w, "gold\\pred")?;
+        for label in labels.iter().chain(std::iter::once(&NO_PREDICTION)) {
+            write!(w, "\t{label}")?;
+        }
+        writeln!(w)?;
+        for gold in &labels {
+            write!(w, "{gold}")?;
+            let predictions = labels.iter().map(|pred| Some(pred.to_string()));
+            for pred in predictions.chain(std::iter::once(None)) {
+                let count = self.confusion.get(&(gold.to_string(), pred)).unwrap_or(&0);
+                write!(w, "\t{count}")?;
+            }
+            writeln!(w)?;
+        }
+
+        writeln!(w, "\n# reliability curve")?;
+        writeln!(w, "confidence\tcount\tmean confidence\taccuracy")?;
+        for bin in self.reliability() {
+            writeln!(
+                w,
+                "[{:.2}, {:.2})\t{}\t{:.4}\t{:.4}",
+                bin.bounds.0, bin.bounds.1, bin.count, bin.mean_confidence, bin.accuracy
+            )?;
+        }
+
+        writeln!(w, "\n# threshold sweep")?;
+        writeln!(w, "threshold\tcoverage\taccuracy")?;
+        for (threshold, coverage, accuracy) in self.threshold_sweep() {
+            writeln!(w, "{threshold:.1}\t{coverage:.4}\t{accuracy:.4}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for Evaluation {
+    /// 10 reliability bins.
+    fn default() -> Self {
+        Self::new(10)
+    }
+}
+
+/// `num / den`, or 0 if `den` is 0.
+fn ratio(num: usize, den: usize) -> f32 {
+    if den == 0 {
+        0.0
+    } else {
+        num as f32 / den as f32
+    }
+}
+
+/// Identify a line the way the pipeline does, ignoring null chars.
+fn predict_line(identifier: &FastText, line: &str) -> Result<Option<(String, f32)>, Error> {
+    Ok(identifier
+        .predict_lines(&[line])?
+        .pop()
+        .flatten()
+        .map(|id| (id.label().to_string(), *id.prob())))
+}
+
+/// Settings of document identification, matching the pipeline ones.
+#[derive(Debug, Default)]
+pub struct DocumentSettings {
+    pub trimming: TrimmingKind,
+    pub multilingual: MultilingualKind,
+}
+
+/// Identify a document the way the pipeline does:
+/// lines at start/end are trimmed, then the document is identified by [OscarDoc]
+/// (`multi` for multilingual documents, no prediction for documents the pipeline discards).
+fn predict_document(
+    identifier: &FastText,
+    settings: &DocumentSettings,
+    text: &str,
+) -> Result<Option<(String, f32)>, Error> {
+    let mut record: Record<BufferedBody> = Record::default().add_body(text.replace("\\n", "\n"));
+    if settings.trimming.transform(&mut record).is_empty() {
+        return Ok(None);
+    }
+
+    let doc = OscarDoc::process_record(record, identifier, &settings.multilingual, None)?;
+    Ok(doc.map(|doc| {
+        (
+            doc.identification().label().to_string(),
+            *doc.identification().prob(),
+        )
+    }))
+}
+
+/// Evaluate `identifier` on the labelled TSV file at `src`, writing the report on stdout.
+pub fn eval_lid(
+    identifier: &FastText,
+    src: &Path,
+    granularity: Granularity,
+    settings: &DocumentSettings,
+    nb_bins: usize,
+) -> Result<(), Error> {
+    let reader = BufReader::new(File::open(src)?);
+    let mut evaluation = Evaluation::new(nb_bins);
+
+    for (idx, line) in reader.lines().enumerate() {
+        let line = line?;
+        let (gold, text) = match line.split_once('\t') {
+            Some(example) => example,
+            None => {
+                warn!("{:?}:{}: no tab separator, skipping", src, idx + 1);
+                continue;
+            }
+        };
+
+        let prediction = match granularity {
+            Granularity::Line => predict_line(identifier, text)?,
+            Granularity::Document => predict_document(identifier, settings, text)?,
+        };
+
+        evaluation.add(
+            gold.trim(),
+            prediction
+                .as_ref()
+                .map(|(label, confidence)| (label.as_str(), *confidence)),
+        );
+    }
+
+    evaluation.report(std::io::stdout().lock())?;
+    identifier.labels().report_unknown();
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Evaluation;
+
+    fn gen_eval() -> Evaluation {
+        let mut e = Evaluation::new(2);
+        e.add("en", Some(("en", 0.9)));
+        e.add("en", Some(("en", 0.8)));
+        e.add("en", Some(("fr", 0.3)));
+        e.add("fr", Some(("fr", 0.7)));
+        e.add("fr", None);
+        e
+    }
+
+    #[test]
+    fn test_scores() {
+        let e = gen_eval();
+        assert_eq!(e.nb_examples(), 5);
+        assert_eq!(e.accuracy(), 3.0 / 5.0);
+
+        let scores = e.language_scores();
+        assert_eq!(scores.len(), 2);
+
+        let en = &scores[0];
+        assert_eq!(en.lang, "en");
+        assert_eq!(en.precision, 1.0);
+        assert_eq!(en.recall, 2.0 / 3.0);
+        assert_eq!(en.support, 3);
+
+        let fr = &scores[1];
+        assert_eq!(fr.lang, "fr");
+        assert_eq!(fr.precision, 0.5);
+        assert_eq!(fr.recall, 0.5);
+        assert_eq!(fr.f1, 0.5);
+    }
+
+    #[test]
+    fn test_reliability() {
+        let e = gen_eval();
+        let bins = e.reliability();
+        assert_eq!(bins.len(), 2);
+
+        // [0, 0.5): one wrong prediction at 0.3
+        assert_eq!(bins[0].count, 1);
+        assert_eq!(bins[0].accuracy, 0.0);
+        assert!((bins[0].mean_confidence - 0.3).abs() < 1e-6);
+
+        // [0.5, 1): three right predictions
+        assert_eq!(bins[1].count, 3);
+        assert_eq!(bins[1].accuracy, 1.0);
+        assert!((bins[1].mean_confidence - 0.8).abs() < 1e-6);
+
+        // (1 * 0.3 + 3 * 0.2) / 4
+        assert!((e.expected_calibration_error() - 0.225).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_threshold_sweep() {
+        let e = gen_eval();
+        let sweep = e.threshold_sweep();
+        let (threshold, coverage, accuracy) = sweep[5];
+        assert_eq!(threshold, 0.5);
+        assert_eq!(coverage, 3.0 / 5.0);
+        assert_eq!(accuracy, 1.0);
+    }
+
+    #[test]
+    fn test_report() {
+        let e = gen_eval();
+        let mut out = Vec::new();
+        e.report(&mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("en\t2\t1\t0"));
+        assert!(out.contains("fr\t0\t1\t1"));
+    }
+
+    #[test]
+    fn test_empty() {
+        let e = Evaluation::default();
+        assert_eq!(e.accuracy(), 0.0);
+        assert_eq!(e.expected_calibration_error(), 0.0);
+        assert!(e.language_scores().is_empty());
+    }
+}
@@ -0,0 +1,93 @@
+//! Sentence length calibration.
+//!
+//! First pass of the length outlier detection: sentence length distributions are learnt per language
+//! on a sample of shards, then saved as JSON so that the pipeline can use them frozen
+//! (see [crate::transformers::LengthOutliers]).
+//!
+//! Records go through the same preprocessing as in the pipeline (short sentences removal at start/end),
+//! and each line is counted in the distribution of its identified language.
+//! Unidentified lines are ignored.
+use std::path::Path;
+
+use log::{error, info};
+use rayon::prelude::*;
+
+use crate::{
+    error::Error,
+    filtering::sentence::MeanLengths,
+    identifiers::model::{FastText, Predict},
+    sources::commoncrawl::Wet,
+    transformers::{RemoveShortSentences, Transform},
+};
+
+/// Identify the lines of a shard, returning identified lines along with their language.
+fn identify_shard(
+    shard_path: &Path,
+    identifier: &FastText,
+) -> Result<Vec<(String, String)>, Error> {
+    let shard = Wet::from_path_gzip(shard_path)?;
+    let length_filter = RemoveShortSentences::default();
+
+    let lines = shard
+        .iter
+        .par_bridge()
+        .filter_map(|record| match record {
+            Ok(r) => Some(r),
+            Err(e) => {
+                error!("{:?}", e);
+                None
+            }
+        })
+        .map(|mut record| {
+            length_filter.transform(&mut record);
+            let body = String::from_utf8_lossy(record.body());
+            let lines: Vec<&str> = body.lines().collect();
+
+            let ids = identifier.predict_lines(&lines)?;
+            Ok(lines
+                .into_iter()
+                .zip(ids)
+                .filter_map(|(line, id)| id.map(|id| (id.label().to_string(), line.to_string())))
+                .collect::<Vec<_>>())
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    Ok(lines.into_iter().flatten().collect())
+}
+
+/// Learn per-language sentence length distributions on the first `nb_shards` shards (by name) of `src`,
+/// and save them in `dst`.
+pub fn calibrate_lengths(
+    src: &Path,
+    dst: &Path,
+    identifier: &FastText,
+    nb_shards: usize,
+) -> Result<(), Error> {
+    let mut shards = std::fs::read_dir(src)?
+        .map(|shard| shard.map(|shard| shard.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    shards.sort();
+
+    let mut lengths = MeanLengths::default();
+    for shard_path in shards.iter().take(nb_shards) {
+        info!("calibrating on shard: {:?}", shard_path);
+        for (lang, line) in identify_shard(shard_path, identifier)? {
+            lengths.detect_mut(&lang, &line);
+        }
+    }
+
+    for (lang, length) in lengths.iter() {
+        info!(
+            "[{}]: {} lines, mean {:.1}, std {:.1}",
+            lang,
+            length.nb_measures(),
+            length.mean(),
+            length.std()
+        );
+    }
+
+    lengths.to_path(dst)?;
+    identifier.labels().report_unknown();
+
+    Ok(())
+}
@@ -0,0 +1,110 @@
+//! Corpus-wide line frequency counting.
+//!
+//! First pass of the line-level boilerplate removal: lines are counted per domain (or globally) on shards,
+//! then saved as JSON so that the pipeline can use them frozen (see [crate::transformers::Boilerplate]).
+//!
+//! Records are counted raw, since boilerplate removal happens before any other transformation in the pipeline.
+//! Lines that appear in less than `min_count` documents are pruned to keep the file small.
+//!
+//! To bound memory, the least frequent lines are dropped when more than `max_lines` lines are counted
+//! (see [LineFrequencies::shrink]). Counts of lines that are dropped then counted again are underestimated:
+//! the maximum error is logged, and is zero if memory never had to be bounded.
+use std::path::Path;
+
+use log::{error, info, warn};
+use rayon::prelude::*;
+use warc::WarcHeader;
+
+use crate::{
+    error::Error,
+    filtering::line_frequency::{self, LineFrequencies},
+    sources::commoncrawl::Wet,
+};
+
+/// Count the lines of a shard.
+fn count_shard(shard_path: &Path, per_domain: bool) -> Result<LineFrequencies, Error> {
+    let shard = Wet::from_path_gzip(shard_path)?;
+
+    let frequencies = shard
+        .iter
+        .par_bridge()
+        .filter_map(|record| match record {
+            Ok(r) => Some(r),
+            Err(e) => {
+                error!("{:?}", e);
+                None
+            }
+        })
+        .fold(
+            || LineFrequencies::new(per_domain),
+            |mut frequencies, record| {
+                let domain = record
+                    .header(WarcHeader::TargetURI)
+                    .and_then(|url| line_frequency::domain(&url));
+                let body = String::from_utf8_lossy(record.body());
+                frequencies.count_mut(domain.as_deref(), body.lines());
+                frequencies
+            },
+        )
+        .reduce(
+            || LineFrequencies::new(per_domain),
+            |mut a, b| {
+                a.merge(b);
+                a
+            },
+        );
+
+    Ok(frequencies)
+}
+
+/// Count lines on the first `nb_shards` shards (by name) of `src` (or all of them),
+/// and save frequencies of lines appearing in at least `min_count` documents in `dst`.
+///
+/// Once a shard is counted, the least frequent lines are dropped if more than `max_lines` lines are counted.
+pub fn count_lines(
+    src: &Path,
+    dst: &Path,
+    nb_shards: Option<usize>,
+    per_domain: bool,
+    min_count: u32,
+    max_lines: usize,
+) -> Result<(), Error> {
+    let mut shards = std::fs::read_dir(src)?
+        .map(|shard| shard.map(|shard| shard.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    shards.sort();
+
+    let mut frequencies = LineFrequencies::new(per_domain);
+    // maximum underestimation of counts, due to dropped lines
+    let mut max_error = 0;
+    for shard_path in shards.iter().take(nb_shards.unwrap_or(shards.len())) {
+        info!("counting lines of shard: {:?}", shard_path);
+        frequencies.merge(count_shard(shard_path, per_domain)?);
+
+        if frequencies.nb_lines() > max_lines {
+            // shrink further than needed, so that it doesn't happen on every shard
+            max_error += frequencies.shrink(max_lines / 2);
+            info!(
+                "dropped least frequent lines, keeping {} lines ({} MB)",
+                frequencies.nb_lines(),
+                frequencies.memory_size() / 1_000_000
+            );
+        }
+    }
+
+    frequencies.prune(min_count);
+    info!(
+        "{} lines appear in at least {} documents ({} MB)",
+        frequencies.nb_lines(),
+        min_count,
+        frequencies.memory_size() / 1_000_000
+    );
+    if max_error > 0 {
+        warn!(
+            "line counts may be underestimated by up to {} documents (see --max-lines)",
+            max_error
+        );
+    }
+
+    frequencies.to_path(dst)
+}
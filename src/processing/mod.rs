@@ -7,6 +7,10 @@ This module is for now only compatible with CommonCrawl extracted content, but w
 pub mod check;
 //pub mod compress;
 //pub mod dedup;
+pub mod eval_lid;
+pub mod length_calibration;
+pub mod line_frequency;
+pub mod perplexity_calibration;
 //pub mod package;
 pub mod rebuild;
 //pub mod split;
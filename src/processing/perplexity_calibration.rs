@@ -0,0 +1,183 @@
+//! Perplexity cutoffs calibration.
+//!
+//! Second pass of the perplexity-based quality bucketing: document perplexities (`score:perplexity` fields)
+//! are sampled per language on a corpus produced by the pipeline with quality models,
+//! then head/tail percentile cutoffs are saved as JSON so that the pipeline can use them frozen
+//! (see [crate::filtering::perplexity]).
+//!
+//! Every scored document of `<lang>_meta*.jsonl` files is read, and up to `sample` documents per language are kept
+//! by reservoir sampling, so that cutoffs are not biased towards the start of each file.
+//! Sampling is seeded (see [SEED]), so that calibrating the same corpus twice gives the same cutoffs.
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+use log::{info, warn};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{
+    error::Error,
+    filtering::perplexity::{PerplexityCutoffs, PERPLEXITY_SCORE},
+    pipelines::oscardoc::types::Document,
+};
+
+/// Seed of the reservoir sampling.
+pub const SEED: u64 = 0;
+
+/// Per-language reservoir of perplexities, along with the number of scored documents seen.
+#[derive(Default)]
+struct Reservoir {
+    perplexities: Vec<f32>,
+    seen: usize,
+}
+
+impl Reservoir {
+    /// Add a perplexity, keeping at most `sample` of them, each seen perplexity having the same probability to be kept.
+    fn add(&mut self, perplexity: f32, sample: usize, rng: &mut impl Rng) {
+        self.seen += 1;
+        if self.perplexities.len() < sample {
+            self.perplexities.push(perplexity);
+        } else {
+            let idx = rng.gen_range(0..self.seen);
+            if let Some(kept) = self.perplexities.get_mut(idx) {
+                *kept = perplexity;
+            }
+        }
+    }
+}
+
+/// Sample up to `sample` document perplexities per language from the JSONL files of `src`.
+fn sample_perplexities(src: &Path, sample: usize) -> Result<HashMap<String, Vec<f32>>, Error> {
+    let mut files = std::fs::read_dir(src)?
+        .map(|file| file.map(|file| file.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    files.retain(|path| {
+        path.extension().is_some_and(|ext| ext == "jsonl")
+            && path
+                .file_name()
+                .is_some_and(|name| name.to_string_lossy().contains("_meta"))
+    });
+    files.sort();
+
+    let mut rng = StdRng::seed_from_u64(SEED);
+    let mut reservoirs: HashMap<String, Reservoir> = HashMap::new();
+    for path in files {
+        info!("sampling perplexities from {:?}", path);
+        let reader = BufReader::new(File::open(&path)?);
+        for line in reader.lines() {
+            let doc: Document = match serde_json::from_str(&line?) {
+                Ok(doc) => doc,
+                Err(e) => {
+                    warn!("skipping invalid document in {:?}: {}", path, e);
+                    continue;
+                }
+            };
+
+            let reservoir = reservoirs
+                .entry(doc.identification().label().to_string())
+                .or_default();
+            if let Some(perplexity) = doc.score(PERPLEXITY_SCORE) {
+                reservoir.add(perplexity as f32, sample, &mut rng);
+            }
+        }
+    }
+
+    Ok(reservoirs
+        .into_iter()
+        .map(|(lang, reservoir)| (lang, reservoir.perplexities))
+        .collect())
+}
+
+/// Compute per-language perplexity cutoffs on up to `sample` documents per language of the corpus in `src`,
+/// and save them in `dst`.
+pub fn calibrate_perplexity(src: &Path, dst: &Path, sample: usize) -> Result<(), Error> {
+    let samples = sample_perplexities(src, sample)?;
+    for lang in samples
+        .iter()
+        .filter(|(_, pp)| pp.is_empty())
+        .map(|(lang, _)| lang)
+    {
+        warn!("[{}]: no scored documents", lang);
+    }
+
+    let cutoffs = PerplexityCutoffs::from_samples(samples);
+    for (lang, (head, tail)) in cutoffs.iter() {
+        info!("[{}]: head <= {:.1}, tail > {:.1}", lang, head, tail);
+    }
+
+    cutoffs.to_path(dst)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::fs::File;
+    use std::io::Write;
+
+    use oxilangtag::LanguageTag;
+
+    use crate::filtering::perplexity::{PerplexityCutoffs, PERPLEXITY_SCORE};
+    use crate::identifiers::identification::Identification;
+    use crate::pipelines::oscardoc::types::{Document, Metadata};
+
+    use super::{calibrate_perplexity, sample_perplexities};
+
+    fn write_docs(path: &std::path::Path, docs: &[Document]) {
+        let mut f = File::create(path).unwrap();
+        for doc in docs {
+            writeln!(f, "{}", serde_json::to_string(doc).unwrap()).unwrap();
+        }
+    }
+
+    fn doc(lang: &str, perplexity: Option<f32>) -> Document {
+        let id = Identification::new(LanguageTag::parse(lang.to_string()).unwrap(), 1.0);
+        let mut doc = Document::new("foo".to_string(), HashMap::new(), Metadata::new(&id, &[]));
+        if let Some(perplexity) = perplexity {
+            doc.add_score(PERPLEXITY_SCORE, perplexity as f64);
+        }
+        doc
+    }
+
+    #[test]
+    fn test_calibrate() {
+        let src = tempfile::tempdir().unwrap();
+        let dst = tempfile::tempdir().unwrap();
+        let dst = dst.path().join("cutoffs.json");
+
+        let mut docs: Vec<_> = [50.0, 10.0, 40.0, 20.0, 30.0]
+            .into_iter()
+            .map(|pp| doc("en", Some(pp)))
+            .collect();
+        docs.push(doc("en", None));
+        write_docs(&src.path().join("en_meta.jsonl"), &docs);
+        // not a corpus file
+        File::create(src.path().join("rejected.jsonl")).unwrap();
+
+        calibrate_perplexity(src.path(), &dst, 10).unwrap();
+        let cutoffs = PerplexityCutoffs::from_path(&dst).unwrap();
+
+        let expected = PerplexityCutoffs::from_samples(HashMap::from([(
+            "en".to_string(),
+            vec![10.0, 20.0, 30.0, 40.0, 50.0],
+        )]));
+        assert_eq!(cutoffs, expected);
+        assert_eq!(cutoffs.get("en"), Some((20.0, 30.0)));
+    }
+
+    #[test]
+    fn test_sample_whole_file() {
+        let src = tempfile::tempdir().unwrap();
+        let docs: Vec<_> = (0..1000).map(|pp| doc("en", Some(pp as f32))).collect();
+        write_docs(&src.path().join("en_meta.jsonl"), &docs);
+
+        let samples = sample_perplexities(src.path(), 100).unwrap();
+        let sample = &samples["en"];
+        assert_eq!(sample.len(), 100);
+        // not only the first documents are kept
+        assert!(sample.iter().any(|pp| *pp >= 500.0));
+
+        // sampling is seeded
+        assert_eq!(&sample_perplexities(src.path(), 100).unwrap()["en"], sample);
+    }
+}
@@ -8,15 +8,16 @@
  * [SRIterator] iteratively returns [RecordIterator]s from a **single** avro file (which corresponds to several shards).
  * [todo] calls [Iterator::next] on [SRIterator] and uses `n` threads to retrieve [Document]s and do IO to recreate the corpus.
 * !*/
+use crate::io::Writer;
 use crate::pipelines::oscardoc::types::Document;
 use crate::pipelines::oscardoc::types::RebuildInformation;
 use crate::pipelines::oscardoc::types::ShardResult;
 use crate::sources::commoncrawl::Wet;
-use oscar_io::v3::Writer;
 use oscar_io::v3::WriterTrait;
 use std::fs::File;
 use std::io::BufRead;
 use std::io::BufReader;
+use std::iter::Peekable;
 use std::path::Path;
 use std::path::PathBuf;
 use std::sync::Arc;
@@ -24,12 +25,13 @@ use std::sync::Mutex;
 use std::vec::IntoIter;
 
 use flate2::read::MultiGzDecoder;
-use itertools::Itertools;
 use log::debug;
 use log::error;
 use oxilangtag::LanguageTag;
 use rayon::iter::ParallelBridge;
 use rayon::iter::ParallelIterator;
+use warc::BufferedBody;
+use warc::Record;
 use warc::RecordIter;
 use warc::WarcHeader;
 
@@ -43,11 +45,12 @@ where
     T: BufRead,
     I: Iterator<Item = RebuildInformation>,
 {
-    rebuild_iter: I,
+    rebuild_iter: Peekable<I>,
     shard_iter: RecordIter<T>,
     shard_id: usize,
 
     prev_loc: usize,
+    prev_record: Option<Record<BufferedBody>>,
 }
 
 impl<T, I> RecordIterator<T, I>
@@ -58,10 +61,11 @@ where
     fn new(rebuild_iter: I, shard_iter: RecordIter<T>, shard_id: usize) -> Self {
         debug!("opening iterator on shard {}", shard_id);
         Self {
-            rebuild_iter,
+            rebuild_iter: rebuild_iter.peekable(),
             shard_iter,
             shard_id,
             prev_loc: 0,
+            prev_record: None,
         }
     }
 
@@ -84,19 +88,26 @@ where
             let loc = rb_info.loc_in_shard();
             let rid = rb_info.record_id();
 
-            // We skip loc-prev_loc records (since we have absolute loc counts, we need to compute the delta)
-            if loc < self.prev_loc {
-                // technically we could "go back" using the bufreader and rewinding.
-                // TODO: implement this? We could also go from line-based to byte-based offset
-                // to enable faster retrieval.
-                error!("It looks like the rebuild file is not ordered. Rebuilding can't work from there, aborting.");
-                return None;
-            }
-            let record = match self.shard_iter.nth(loc - self.prev_loc) {
-                Some(Ok(r)) => r,
-                //uj: should we really "just" return some error or return None (with error logging)
-                Some(Err(e)) => return Some(Err(e.into())),
-                None => return None,
+            // Several documents can come from the same record (see [crate::transformers::LanguageSplit]),
+            // in which case we reuse the previous one.
+            let record = match self.prev_record.take() {
+                Some(prev_record) if loc + 1 == self.prev_loc => prev_record,
+                _ => {
+                    // We skip loc-prev_loc records (since we have absolute loc counts, we need to compute the delta)
+                    if loc < self.prev_loc {
+                        // technically we could "go back" using the bufreader and rewinding.
+                        // TODO: implement this? We could also go from line-based to byte-based offset
+                        // to enable faster retrieval.
+                        error!("It looks like the rebuild file is not ordered. Rebuilding can't work from there, aborting.");
+                        return None;
+                    }
+                    match self.shard_iter.nth(loc - self.prev_loc) {
+                        Some(Ok(r)) => r,
+                        //uj: should we really "just" return some error or return None (with error logging)
+                        Some(Err(e)) => return Some(Err(e.into())),
+                        None => return None,
+                    }
+                }
             };
 
             // ensure that we got the right record
@@ -111,20 +122,39 @@ where
                 return None;
             }
 
+            // keep the record if the next document comes from it too
+            self.prev_record = match self.rebuild_iter.peek() {
+                Some(next) if next.loc_in_shard() == loc => Some(record.clone()),
+                _ => None,
+            };
+
             // separate raw parts
             let (mut headers, body) = record.into_raw_parts();
 
-            // compute line bounds and get them
-            let nb_skip = rb_info.line_start();
-
-            // Since bounds are inclusive, for a document that starts at x and ends at y we have to skip to x
+            // get lines of each kept range
+            // Since bounds are inclusive, for a range that starts at x and ends at y we have to skip to x
             // and then take y-x+1.
-            let nb_take = rb_info.line_end() - rb_info.line_start() + 1;
-            let body = String::from_utf8_lossy(&body)
-                .lines()
-                .skip(nb_skip)
-                .take(nb_take)
-                .join("\n");
+            let body = String::from_utf8_lossy(&body);
+            let lines: Vec<&str> = body.lines().collect();
+            let mut lines: Vec<String> = rb_info
+                .line_ranges()
+                .into_iter()
+                .flat_map(|range| {
+                    lines
+                        .iter()
+                        .skip(*range.start())
+                        .take(range.end() - range.start() + 1)
+                        .map(|line| line.to_string())
+                })
+                .collect();
+
+            // then replay replacements, in order
+            for edit in rb_info.edits() {
+                if let Err(e) = edit.apply(&mut lines) {
+                    return Some(Err(e));
+                }
+            }
+            let body = lines.join("\n");
 
             // compute body length to update content-length
             *headers
@@ -133,7 +163,8 @@ where
                 .or_default() = body.len().to_string().as_bytes().to_owned(); //convert usize to its string repr, then in a vec of bytes.
 
             // create document and update prev_loc
-            let document = Document::new(body, headers.headers, rb_info.metadata().clone());
+            let document = Document::new(body, headers.headers, rb_info.metadata().clone())
+                .with_fields(rb_info.fields());
             self.prev_loc = loc + 1;
 
             Some(Ok(document))
@@ -315,9 +346,106 @@ mod tests {
     use oxilangtag::LanguageTag;
     use warc::WarcReader;
 
-    use crate::pipelines::oscardoc::types::{Document, Metadata};
+    use crate::pipelines::oscardoc::types::{Document, Location, Metadata, RebuildInformation};
+    use crate::transformers::Edit;
 
     use oscar_io::common::Identification;
+
+    use super::RecordIterator;
+
+    #[test]
+    fn test_same_record() {
+        let raw = b"\
+            WARC/1.0\r\n\
+            WARC-Type: conversion\r\n\
+            WARC-Record-ID: <urn:test:record-0>\r\n\
+            WARC-Date: 2020-07-08T02:52:55Z\r\n\
+            Content-Length: 7\r\n\
+            \r\n\
+            foo\nbar\r\n\
+            \r\n\
+            WARC/1.0\r\n\
+            WARC-Type: conversion\r\n\
+            WARC-Record-ID: <urn:test:record-1>\r\n\
+            WARC-Date: 2020-07-08T02:52:55Z\r\n\
+            Content-Length: 11\r\n\
+            \r\n\
+            baz\nquux\nqu\r\n\
+            \r\n\
+        ";
+
+        let shard_iter = WarcReader::new(BufReader::new(Cursor::new(raw))).iter_records();
+        let rebuild_info: Vec<_> = [
+            ("<urn:test:record-0>", 0, 1, 0),
+            ("<urn:test:record-1>", 0, 0, 1),
+            ("<urn:test:record-1>", 2, 2, 1),
+        ]
+        .into_iter()
+        .map(|(rid, start, end, loc)| {
+            RebuildInformation::new(
+                Location::new(0, rid.to_string(), start, end, loc),
+                Metadata::default(),
+            )
+        })
+        .collect();
+
+        let ri = RecordIterator::new(rebuild_info.into_iter(), shard_iter, 0);
+        let contents: Vec<_> = ri.map(|doc| doc.unwrap().content().clone()).collect();
+
+        assert_eq!(contents, vec!["foo\nbar", "baz", "qu"]);
+    }
+
+    #[test]
+    fn test_line_ranges() {
+        let raw = b"\
+            WARC/1.0\r\n\
+            WARC-Type: conversion\r\n\
+            WARC-Record-ID: <urn:test:record-0>\r\n\
+            WARC-Date: 2020-07-08T02:52:55Z\r\n\
+            Content-Length: 19\r\n\
+            \r\n\
+            foo\nbar\nbaz\nquux\nqu\r\n\
+            \r\n\
+        ";
+
+        let shard_iter = WarcReader::new(BufReader::new(Cursor::new(raw))).iter_records();
+        let rebuild_info = vec![RebuildInformation::new(
+            Location::with_line_ranges(0, "<urn:test:record-0>".to_string(), vec![0..=1, 3..=3], 0),
+            Metadata::default(),
+        )];
+
+        let ri = RecordIterator::new(rebuild_info.into_iter(), shard_iter, 0);
+        let contents: Vec<_> = ri.map(|doc| doc.unwrap().content().clone()).collect();
+
+        assert_eq!(contents, vec!["foo\nbar\nquux"]);
+    }
+
+    #[test]
+    fn test_edits() {
+        let raw = b"\
+            WARC/1.0\r\n\
+            WARC-Type: conversion\r\n\
+            WARC-Record-ID: <urn:test:record-0>\r\n\
+            WARC-Date: 2020-07-08T02:52:55Z\r\n\
+            Content-Length: 20\r\n\
+            \r\n\
+            foo\nbar  baz\nquux\nqu\r\n\
+            \r\n\
+        ";
+
+        let shard_iter = WarcReader::new(BufReader::new(Cursor::new(raw))).iter_records();
+        let loc = Location::with_line_ranges(0, "<urn:test:record-0>".to_string(), vec![1..=2], 0)
+            .with_edits(vec![
+                Edit::new(0, 3, 5, " ".to_string()),
+                Edit::new(1, 0, 1, "Q".to_string()),
+            ]);
+        let rebuild_info = vec![RebuildInformation::new(loc, Metadata::default())];
+
+        let ri = RecordIterator::new(rebuild_info.into_iter(), shard_iter, 0);
+        let contents: Vec<_> = ri.map(|doc| doc.unwrap().content().clone()).collect();
+
+        assert_eq!(contents, vec!["bar baz\nQuux"]);
+    }
     fn test_from_loc_meta() {
         let raw = b"\
             WARC/1.0\r\n\
@@ -0,0 +1,190 @@
+//! Line-level boilerplate removal.
+//!
+//! Uses corpus-wide [LineFrequencies] (see [crate::processing::line_frequency]) to find lines
+//! that recur across many documents of the same domain (or of the whole corpus).
+//!
+//! [Boilerplate] can either remove those lines (see [Transform]) or flag them (see [Annotate]),
+//! depending on its [BoilerplateMode].
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use log::debug;
+use warc::{BufferedBody, Record, WarcHeader};
+
+use crate::filtering::line_frequency::{self, LineFrequencies};
+use crate::pipelines::oscardoc::types::Document;
+
+use super::{Annotate, Edits, Transform};
+
+/// What to do with boilerplate lines.
+///
+/// Can be parsed from `remove` or `annotate`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BoilerplateMode {
+    /// Remove boilerplate lines before identification.
+    Remove,
+    /// Store the number of boilerplate lines in the `boilerplate_lines` field.
+    Annotate,
+}
+
+impl FromStr for BoilerplateMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "remove" => Ok(Self::Remove),
+            "annotate" => Ok(Self::Annotate),
+            other => Err(format!(
+                "unknown boilerplate mode {other} (expected remove or annotate)"
+            )),
+        }
+    }
+}
+
+/// Finds lines that appear in at least `threshold` documents of the same domain.
+///
+/// Frequencies are shared, so that cloning is cheap.
+#[derive(Debug, Clone)]
+pub struct Boilerplate {
+    frequencies: Arc<LineFrequencies>,
+    threshold: u32,
+    mode: BoilerplateMode,
+}
+
+impl Boilerplate {
+    pub fn new(frequencies: LineFrequencies, threshold: u32, mode: BoilerplateMode) -> Self {
+        Self {
+            frequencies: Arc::new(frequencies),
+            threshold,
+            mode,
+        }
+    }
+
+    /// Get the boilerplate mode.
+    pub fn mode(&self) -> BoilerplateMode {
+        self.mode
+    }
+
+    /// Check if `line` is boilerplate for `domain`.
+    pub fn is_boilerplate(&self, domain: Option<&str>, line: &str) -> bool {
+        self.frequencies.frequency(domain, line) >= self.threshold
+    }
+
+    /// Get the ranges of non-boilerplate lines, along with the content made of those lines.
+    fn kept(&self, domain: Option<&str>, content: &str) -> (String, Vec<RangeInclusive<usize>>) {
+        let mut ranges: Vec<RangeInclusive<usize>> = Vec::new();
+        let mut kept = Vec::new();
+        for (idx, line) in content.lines().enumerate() {
+            if self.is_boilerplate(domain, line) {
+                continue;
+            }
+
+            // extend the last range if lines are contiguous
+            match ranges.last_mut() {
+                Some(range) if range.end() + 1 == idx => *range = *range.start()..=idx,
+                _ => ranges.push(idx..=idx),
+            }
+            kept.push(line);
+        }
+
+        (kept.join("\n"), ranges)
+    }
+}
+
+impl Transform<Record<BufferedBody>> for Boilerplate {
+    /// Remove boilerplate lines, returning the ranges of kept lines.
+    fn transform(&self, doc: &mut Record<BufferedBody>) -> Edits {
+        let domain = doc
+            .header(WarcHeader::TargetURI)
+            .and_then(|url| line_frequency::domain(&url));
+        let body = String::from_utf8_lossy(doc.body()).into_owned();
+        let (content, ranges) = self.kept(domain.as_deref(), &body);
+
+        if content.len() != body.len() {
+            debug!("record {} has boilerplate lines", doc.warc_id());
+        }
+        doc.replace_body(content);
+        ranges.into()
+    }
+}
+
+impl Annotate<Document> for Boilerplate {
+    fn annotate(&self, doc: &mut Document) {
+        let domain = doc.url().and_then(|url| line_frequency::domain(&url));
+        let boilerplate = doc
+            .content()
+            .lines()
+            .filter(|line| self.is_boilerplate(domain.as_deref(), line))
+            .count();
+
+        if boilerplate > 0 {
+            debug!("record {} has boilerplate lines", doc.warc_id());
+            doc.add_field("boilerplate_lines", boilerplate);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use warc::{BufferedBody, Record, WarcHeader};
+
+    use crate::filtering::line_frequency::LineFrequencies;
+    use crate::pipelines::oscardoc::types::{Document, Metadata};
+    use crate::transformers::{Annotate, Transform};
+
+    use super::{Boilerplate, BoilerplateMode};
+
+    fn frequencies() -> LineFrequencies {
+        let mut f = LineFrequencies::new(true);
+        for idx in 0..5 {
+            let content = format!("Home | About\nunique content {idx}\nAccept cookies");
+            f.count_mut(Some("example.com"), content.lines());
+        }
+        f
+    }
+
+    #[test]
+    fn test_transform() {
+        let b = Boilerplate::new(frequencies(), 3, BoilerplateMode::Remove);
+        let mut record = Record::default();
+        record
+            .set_header(WarcHeader::TargetURI, "https://example.com/page")
+            .unwrap();
+        let mut record =
+            record.add_body("Home | About\nfoo\nbar\naccept  cookies\nbaz\nAccept cookies");
+
+        let ranges = b.transform(&mut record);
+        assert_eq!(ranges.lines(), &[1..=2, 4..=4]);
+        assert_eq!(record.body(), b"foo\nbar\nbaz");
+
+        // other domains are not affected
+        let mut record: Record<BufferedBody> = Record::default().add_body("Home | About\nfoo");
+        assert_eq!(b.transform(&mut record).lines(), &[0..=1]);
+    }
+
+    #[test]
+    fn test_annotate() {
+        let b = Boilerplate::new(frequencies(), 3, BoilerplateMode::Annotate);
+        let mut headers = HashMap::new();
+        headers.insert(WarcHeader::TargetURI, b"https://example.com/page".to_vec());
+        let mut doc = Document::new(
+            "foo\nHome | About\nunique content 1\nbar".to_string(),
+            headers,
+            Metadata::default(),
+        );
+
+        b.annotate(&mut doc);
+        // numbers are normalized, so every "unique content <n>" line is counted as the same one
+        assert_eq!(doc.field("boilerplate_lines"), Some(&serde_json::json!(2)));
+    }
+
+    #[test]
+    fn test_mode_from_str() {
+        assert_eq!("remove".parse(), Ok(BoilerplateMode::Remove));
+        assert_eq!("annotate".parse(), Ok(BoilerplateMode::Annotate));
+        assert!("foo".parse::<BoilerplateMode>().is_err());
+    }
+}
@@ -0,0 +1,346 @@
+/*! Content-based classification.
+
+Unlike [ContentDetector](super::ContentDetector) that only checks the URL against blocklists,
+[ContentClassifier] scores the text itself against per-language weighted lexicons, so that harmful content
+on non-blocklisted domains can be caught.
+
+Lexicons are loaded from a folder laid out like the UT1 blocklists, one subfolder per category:
+
+```text
+lexicons/
+├── adult
+│   ├── en.txt
+│   └── fr.txt
+├── hate
+│   └── en.txt
+└── violence
+    └── en.txt
+```
+
+Each `<lang>.txt` file holds one term per line (single words or phrases), optionally followed by a tab and a weight
+(defaults to `1`). Empty lines and lines starting with `#` are ignored. Terms are matched case-insensitively on words,
+so languages that are not whitespace-delimited are not supported.
+
+For each category that has a lexicon in the document language, the weighted term density `d` (weight of matches per word)
+is mapped to a `[0, 1]` score `d / (d + midpoint)`, `midpoint` being the density that scores `0.5`.
+This mapping is monotonic but not calibrated: scores are not probabilities, and comparable thresholds
+across categories and languages have to be chosen on labelled data.
+
+Non-zero scores are added as `score:content:<category>` fields, and `content:<category>` is added to the document categories
+when its score reaches the threshold, so that classifier categories can't be mistaken for blocklist ones.
+!*/
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Error, ErrorKind};
+use std::path::Path;
+
+use log::{info, warn};
+
+use crate::pipelines::oscardoc::types::Document;
+
+use super::Annotate;
+
+/// Default density that scores `0.5` (one matched term per 100 words).
+pub const DEFAULT_MIDPOINT: f32 = 0.01;
+
+/// Prefix of classifier categories (and scores), distinguishing them from blocklist categories.
+const CATEGORY_PREFIX: &str = "content:";
+
+/// Split text in lowercase words.
+fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// Weighted terms of a category in a given language.
+#[derive(Debug, Clone, Default)]
+pub struct Lexicon {
+    /// space-joined words of terms and their weights
+    terms: HashMap<String, f32>,
+    /// number of words of the longest term
+    max_words: usize,
+}
+
+impl Lexicon {
+    /// Load a lexicon from a file (see [module](self) documentation for the format).
+    pub fn from_path(path: &Path) -> Result<Self, Error> {
+        Self::from_reader(BufReader::new(File::open(path)?))
+    }
+
+    /// Load a lexicon from a reader (see [module](self) documentation for the format).
+    pub fn from_reader(reader: impl BufRead) -> Result<Self, Error> {
+        let mut lexicon = Self::default();
+        for (line_nb, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (term, weight) = match line.split_once('\t') {
+                Some((term, weight)) => {
+                    let weight = weight.trim().parse().map_err(|_| {
+                        Error::new(
+                            ErrorKind::InvalidData,
+                            format!("line {}: invalid weight {:?}", line_nb + 1, weight),
+                        )
+                    })?;
+                    (term, weight)
+                }
+                None => (line.as_str(), 1.0),
+            };
+            lexicon.insert(term, weight);
+        }
+
+        Ok(lexicon)
+    }
+
+    /// Add a term, replacing its weight if it's already present.
+    pub fn insert(&mut self, term: &str, weight: f32) {
+        let words = words(term);
+        if words.is_empty() {
+            return;
+        }
+        self.max_words = self.max_words.max(words.len());
+        self.terms.insert(words.join(" "), weight);
+    }
+
+    /// Get the summed weight of terms found in `words`. Overlapping matches are all counted.
+    pub fn weight(&self, words: &[String]) -> f32 {
+        let mut total = 0.0;
+        for start in 0..words.len() {
+            let mut term = String::new();
+            for word in words.iter().skip(start).take(self.max_words) {
+                if !term.is_empty() {
+                    term.push(' ');
+                }
+                term.push_str(word);
+                if let Some(weight) = self.terms.get(&term) {
+                    total += weight;
+                }
+            }
+        }
+        total
+    }
+}
+
+/// Lexicon-based content classifier (see [module](self) documentation).
+#[derive(Debug, Clone)]
+pub struct ContentClassifier {
+    /// `(category, lexicon)` by language
+    lexicons: HashMap<String, Vec<(String, Lexicon)>>,
+    threshold: f32,
+    midpoint: f32,
+}
+
+impl ContentClassifier {
+    /// Create a new [ContentClassifier] adding categories whose score is at least `threshold`.
+    pub fn new(threshold: f32) -> Self {
+        Self {
+            lexicons: HashMap::new(),
+            threshold,
+            midpoint: DEFAULT_MIDPOINT,
+        }
+    }
+
+    /// Load lexicons from a folder (see [module](self) documentation for the layout).
+    pub fn from_dir(path: &Path, threshold: f32) -> Result<Self, Error> {
+        let mut classifier = Self::new(threshold);
+        for category_dir in std::fs::read_dir(path)? {
+            let category_dir = category_dir?.path();
+            let category = match category_dir.file_name().and_then(|name| name.to_str()) {
+                Some(category) if category_dir.is_dir() => category.to_string(),
+                _ => continue,
+            };
+
+            for lexicon_path in std::fs::read_dir(&category_dir)? {
+                let lexicon_path = lexicon_path?.path();
+                if lexicon_path.extension() != Some(OsStr::new("txt")) {
+                    continue;
+                }
+                let lang = match lexicon_path.file_stem().and_then(|stem| stem.to_str()) {
+                    Some(lang) => lang.to_string(),
+                    None => continue,
+                };
+                let lexicon = Lexicon::from_path(&lexicon_path)
+                    .map_err(|e| Error::new(e.kind(), format!("{:?}: {}", lexicon_path, e)))?;
+                classifier.insert(&lang, &category, lexicon);
+            }
+        }
+
+        if classifier.lexicons.is_empty() {
+            warn!("No lexicons found in {:?}", path);
+        }
+        info!(
+            "Loaded content lexicons for {} languages from {:?}",
+            classifier.lexicons.len(),
+            path
+        );
+        Ok(classifier)
+    }
+
+    /// Add (or replace) the lexicon of `category` for `lang`.
+    pub fn insert(&mut self, lang: &str, category: &str, lexicon: Lexicon) {
+        let lexicons = self.lexicons.entry(lang.to_string()).or_default();
+        lexicons.retain(|(c, _)| c != category);
+        lexicons.push((category.to_string(), lexicon));
+        lexicons.sort_by(|(a, _), (b, _)| a.cmp(b));
+    }
+
+    /// Set the density that scores `0.5`.
+    pub fn set_midpoint(&mut self, midpoint: f32) {
+        self.midpoint = midpoint;
+    }
+
+    /// Get the `(category, score)` of each category that has a lexicon for `lang`, sorted by category.
+    /// Returns an empty [Vec] if there are no lexicons for `lang` or `content` has no words.
+    pub fn scores(&self, lang: &str, content: &str) -> Vec<(&str, f32)> {
+        let lexicons = match self.lexicons.get(lang) {
+            Some(lexicons) => lexicons,
+            None => return Vec::new(),
+        };
+        let words = words(content);
+        if words.is_empty() {
+            return Vec::new();
+        }
+
+        lexicons
+            .iter()
+            .map(|(category, lexicon)| {
+                let density = lexicon.weight(&words) / words.len() as f32;
+                let score = if density > 0.0 {
+                    density / (density + self.midpoint)
+                } else {
+                    0.0
+                };
+                (category.as_str(), score)
+            })
+            .collect()
+    }
+}
+
+impl Annotate<Document> for ContentClassifier {
+    /// Adds non-zero category scores in `score:content:<category>` fields, and `content:<category>` categories
+    /// whose score reaches the threshold.
+    fn annotate(&self, doc: &mut Document) {
+        let lang = doc.identification().label().to_string();
+        let scores = self.scores(&lang, doc.content());
+
+        for (category, score) in scores {
+            if score <= 0.0 {
+                continue;
+            }
+            let category = format!("{CATEGORY_PREFIX}{category}");
+            doc.add_score(&category, score as f64);
+
+            let metadata = doc.metadata_mut();
+            let present = metadata
+                .categories()
+                .is_some_and(|categories| categories.contains(&category));
+            if score >= self.threshold && !present {
+                metadata.add_category(category);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use oxilangtag::LanguageTag;
+
+    use crate::identifiers::identification::Identification;
+    use crate::pipelines::oscardoc::types::{Document, Metadata};
+    use crate::transformers::Annotate;
+
+    use super::{words, ContentClassifier, Lexicon};
+
+    fn doc(lang: &str, content: &str) -> Document {
+        let id = Identification::new(LanguageTag::parse(lang.to_string()).unwrap(), 1.0);
+        Document::new(content.to_string(), HashMap::new(), Metadata::new(&id, &[]))
+    }
+
+    fn classifier() -> ContentClassifier {
+        let adult = Lexicon::from_reader("# comment\nfoo\nbar baz\t2\n\n".as_bytes()).unwrap();
+        let violence = Lexicon::from_reader("qux\t0.5".as_bytes()).unwrap();
+        let mut classifier = ContentClassifier::new(0.5);
+        classifier.insert("en", "violence", violence);
+        classifier.insert("en", "adult", adult);
+        classifier
+    }
+
+    #[test]
+    fn test_lexicon() {
+        let lexicon = Lexicon::from_reader("Foo\nbar baz\t2\n".as_bytes()).unwrap();
+        assert_eq!(lexicon.terms.len(), 2);
+
+        assert_eq!(lexicon.weight(&words("FOO, bar-baz. baz bar")), 3.0);
+        assert_eq!(lexicon.weight(&words("bar")), 0.0);
+        assert!(Lexicon::from_reader("foo\tnope".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_scores() {
+        let mut classifier = classifier();
+        classifier.set_midpoint(0.1);
+
+        // adult: 1/4 density, violence: 0.5/4 density
+        let scores = classifier.scores("en", "foo qux lorem ipsum");
+        assert_eq!(scores.len(), 2);
+        assert_eq!(scores[0].0, "adult");
+        assert!((scores[0].1 - 0.25 / 0.35).abs() < 1e-6);
+        assert_eq!(scores[1].0, "violence");
+        assert!((scores[1].1 - 0.125 / 0.225).abs() < 1e-6);
+
+        assert!(classifier.scores("fr", "foo qux").is_empty());
+        assert!(classifier.scores("en", " ... ").is_empty());
+    }
+
+    #[test]
+    fn test_annotate() {
+        let classifier = classifier();
+
+        let mut d = doc("en", "foo lorem ipsum");
+        classifier.annotate(&mut d);
+        assert_eq!(
+            d.metadata().categories(),
+            Some(&vec!["content:adult".to_string()])
+        );
+        assert!(d.score("content:adult").unwrap() > 0.9);
+        assert_eq!(d.score("content:violence"), None);
+
+        // blocklist categories are kept apart
+        let mut d = doc("en", "foo lorem ipsum");
+        d.metadata_mut().add_category("adult".to_string());
+        d.metadata_mut().add_category("content:adult".to_string());
+        classifier.annotate(&mut d);
+        assert_eq!(
+            d.metadata().categories(),
+            Some(&vec!["adult".to_string(), "content:adult".to_string()])
+        );
+
+        let mut d = doc("en", &"lorem ".repeat(1000));
+        classifier.annotate(&mut d);
+        assert_eq!(d.metadata().categories(), None);
+        assert!(d.fields().is_empty());
+    }
+
+    #[test]
+    fn test_from_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("hate")).unwrap();
+        std::fs::write(dir.path().join("hate/en.txt"), "foo\n").unwrap();
+        std::fs::write(dir.path().join("hate/README"), "not a lexicon").unwrap();
+        std::fs::write(dir.path().join("notes.txt"), "foo").unwrap();
+
+        let classifier = ContentClassifier::from_dir(dir.path(), 0.5).unwrap();
+        assert_eq!(classifier.scores("en", "foo").len(), 1);
+        assert_eq!(classifier.scores("en", "foo")[0].0, "hate");
+
+        std::fs::write(dir.path().join("hate/fr.txt"), "foo\tnope\n").unwrap();
+        assert!(ContentClassifier::from_dir(dir.path(), 0.5).is_err());
+    }
+}
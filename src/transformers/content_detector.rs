@@ -1,40 +1,277 @@
 /*! Content annotators.
 
-Adds an `annotation` tag in [Document] depending on possibly harmful/specific content in document.
+Adds categories in [Document] depending on possibly harmful/specific content in document.
 
 Currently the approach is to use the [UT1 blocklist](https://dsi.ut-capitole.fr/blacklists/) and to annotate flagged URLs.
+Blocklists can be used in two modes (see [BlocklistKind]):
+
+- [BlocklistKind::Single] uses a single category (ex. `adult`),
+- [BlocklistKind::Multiple] uses every category of a UT1 blocklist folder.
+
+In both modes, a URL is flagged if:
+
+- its domain or one of its parent domains (ex. `foo.bar.com` for `baz.foo.bar.com`) is in a `domains` list.
+  Top-level domains alone are never matched,
+- its host and path (ex. `foo.bar/baz` for `https://foo.bar/baz?quux=true`) are in a `urls` list,
+  whatever the scheme of the URL and of the list entry.
+
+An [Allowlist] can be provided, overriding blocks for the domains and URLs it contains (using the same matching rules).
  * !*/
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+use log::{debug, info};
+use url::{Position, Url};
+use ut1_blocklist::{Blocklist, MultipleBlocklist};
 
-use log::info;
-use ut1_blocklist::MultipleBlocklist as Blocklist;
+use crate::pipelines::oscardoc::types::Document;
 
-// use crate::pipelines::oscardoc::types::Document;
-use oscar_io::v3::Document;
+use crate::error::Error;
 
 use super::Annotate;
 
+/// Iterate over the domain and its parent domains, skipping the top-level domain.
+fn domain_suffixes(domain: &str) -> impl Iterator<Item = &str> {
+    let nb_suffixes = domain.matches('.').count().max(1);
+    std::iter::successors(Some(domain), |domain| {
+        domain.split_once('.').map(|(_, parent)| parent)
+    })
+    .take(nb_suffixes)
+}
+
+/// Parse a URL, adding a scheme if there's none (ex. `foo.bar/baz`).
+fn parse_url(url: &str) -> Option<Url> {
+    Url::parse(url)
+        .ok()
+        .filter(|url| url.has_host())
+        .or_else(|| Url::parse(&format!("https://{url}")).ok())
+}
+
+/// Get the host and path of a URL (ex. `foo.bar/baz` for `https://foo.bar/baz?quux=true`).
+fn host_path(url: &Url) -> &str {
+    &url[Position::BeforeHost..Position::AfterPath]
+}
+
+/// Blocklists used by [ContentDetector].
+pub enum BlocklistKind {
+    /// Single category blocklist.
+    Single(Blocklist),
+    /// Multiple categories blocklist matching domains, along with the categories of URLs by host and path
+    /// (see [BlocklistKind::multiple]).
+    Multiple(MultipleBlocklist, HashMap<String, Vec<String>>),
+}
+
+impl BlocklistKind {
+    /// Create a multiple categories blocklist from the categories of domains and URLs.
+    /// Invalid domains and URLs are ignored.
+    pub fn multiple(
+        domains: HashMap<String, Vec<String>>,
+        urls: HashMap<String, Vec<String>>,
+    ) -> Self {
+        let mut domain_categories: HashMap<String, Vec<String>> = HashMap::new();
+        for (domain, categories) in domains {
+            if let Some(domain) = parse_url(domain.trim())
+                .as_ref()
+                .and_then(|url| url.host_str())
+            {
+                domain_categories
+                    .entry(domain.to_string())
+                    .or_default()
+                    .extend(categories);
+            }
+        }
+
+        let mut url_categories: HashMap<String, Vec<String>> = HashMap::new();
+        for (url, categories) in urls {
+            if let Some(url) = parse_url(url.trim()) {
+                url_categories
+                    .entry(host_path(&url).to_string())
+                    .or_default()
+                    .extend(categories);
+            }
+        }
+
+        Self::Multiple(
+            MultipleBlocklist::new(domain_categories, HashMap::new()),
+            url_categories,
+        )
+    }
+
+    /// Load every category of a UT1 blocklist folder (`<category>/domains` and `<category>/urls` lists).
+    pub fn multiple_from_dir(path: &Path) -> Result<Self, std::io::Error> {
+        info!("Building blocklist from {:?}", path);
+        let mut domains: HashMap<String, Vec<String>> = HashMap::new();
+        let mut urls: HashMap<String, Vec<String>> = HashMap::new();
+        for category_dir in std::fs::read_dir(path)? {
+            let category_dir = category_dir?.path();
+            let category = match category_dir.file_name() {
+                Some(category) if category_dir.is_dir() => category.to_string_lossy().to_string(),
+                _ => continue,
+            };
+            debug!("Reading lists for category {:?}", category);
+
+            for (list, entries) in [("domains", &mut domains), ("urls", &mut urls)] {
+                let list_path = category_dir.join(list);
+                if !list_path.is_file() {
+                    continue;
+                }
+                for entry in BufReader::new(File::open(&list_path)?)
+                    .lines()
+                    .map_while(Result::ok)
+                {
+                    entries.entry(entry).or_default().push(category.clone());
+                }
+            }
+        }
+
+        Ok(Self::multiple(domains, urls))
+    }
+
+    /// Get the categories the URL is flagged as, sorted.
+    pub fn detect(&self, url: &Url) -> Vec<String> {
+        let mut categories: Vec<String> = match self {
+            Self::Single(bl) => {
+                let domain_match = url.host_str().is_some_and(|domain| {
+                    domain_suffixes(domain).any(|d| bl.domains().contains(d))
+                });
+                if domain_match || bl.detect_url(url) {
+                    vec![bl.kind().to_string()]
+                } else {
+                    vec![]
+                }
+            }
+            Self::Multiple(bl, urls) => {
+                let mut categories = bl.detect(url.as_str()).unwrap_or_default();
+                if let Some(url_categories) = urls.get(host_path(url)) {
+                    categories.extend(url_categories);
+                }
+                categories.into_iter().cloned().collect()
+            }
+        };
+        categories.sort();
+        categories
+    }
+}
+
+/// Domains and URLs that are never flagged.
+#[derive(Debug, Clone, Default)]
+pub struct Allowlist {
+    domains: HashSet<String>,
+    /// host and path of URLs (ex. `foo.bar/baz`)
+    urls: HashSet<String>,
+}
+
+impl Allowlist {
+    /// Create a new [Allowlist] from domains and URLs. Invalid URLs are ignored.
+    pub fn new(
+        domains: impl IntoIterator<Item = String>,
+        urls: impl IntoIterator<Item = String>,
+    ) -> Self {
+        Self {
+            domains: domains
+                .into_iter()
+                .map(|domain| domain.trim().to_lowercase())
+                .collect(),
+            urls: urls
+                .into_iter()
+                .filter_map(|url| parse_url(url.trim()))
+                .map(|url| host_path(&url).to_string())
+                .collect(),
+        }
+    }
+
+    /// Load an allowlist from a file, one domain or URL per line.
+    /// Entries with a path (ex. `foo.bar/baz`) are considered as URLs, others as domains.
+    /// Empty lines and lines starting with `#` are ignored.
+    pub fn from_path(path: &Path) -> Result<Self, std::io::Error> {
+        let (urls, domains): (Vec<_>, Vec<_>) = BufReader::new(File::open(path)?)
+            .lines()
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .map(|line| line.trim().to_string())
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .partition(|line| {
+                let without_scheme = line.split_once("://").map_or(line.as_str(), |(_, l)| l);
+                without_scheme.contains('/')
+            });
+
+        info!(
+            "Loaded allowlist with {} domains and {} urls from {:?}",
+            domains.len(),
+            urls.len(),
+            path
+        );
+        Ok(Self::new(domains, urls))
+    }
+
+    /// Check if the domain (or a parent domain) or the URL is allowed.
+    pub fn contains(&self, url: &Url) -> bool {
+        let domain_match = url
+            .host_str()
+            .is_some_and(|domain| domain_suffixes(domain).any(|d| self.domains.contains(d)));
+        domain_match || self.urls.contains(host_path(url))
+    }
+}
+
 pub struct ContentDetector {
-    bl: Blocklist,
+    bl: BlocklistKind,
+    allowlist: Option<Allowlist>,
 }
 
 impl ContentDetector {
-    /// Create a new [ContentDetector] based on a specified [Blocklist].
-    pub fn new(bl: Blocklist) -> Self {
+    /// Create a new [ContentDetector] based on a specified `BlocklistKind`.
+    pub fn new(bl: BlocklistKind) -> Self {
         info!("Creating a new ContentDetector");
-        Self { bl }
+        Self {
+            bl,
+            allowlist: None,
+        }
+    }
+
+    /// Use the `category` list of a UT1 blocklist folder (see [Blocklist::with_folder]),
+    /// or all of them if `category` is [None].
+    pub fn from_dir(path: &Path, category: Option<&str>) -> Result<Self, Error> {
+        let bl = match category {
+            Some(category) => {
+                BlocklistKind::Single(Blocklist::with_folder(category.to_string(), path)?)
+            }
+            None => BlocklistKind::multiple_from_dir(path)?,
+        };
+        Ok(Self::new(bl))
+    }
+
+    /// Never flag domains and URLs present in the allowlist.
+    pub fn set_allowlist(&mut self, allowlist: Option<Allowlist>) {
+        self.allowlist = allowlist;
+    }
+
+    /// Get the categories the URL is flagged as, sorted.
+    /// Returns an empty [Vec] if the URL is allowed.
+    pub fn detect(&self, url: &Url) -> Vec<String> {
+        if self
+            .allowlist
+            .as_ref()
+            .is_some_and(|allowlist| allowlist.contains(url))
+        {
+            return vec![];
+        }
+        self.bl.detect(url)
     }
 }
 
 impl Annotate<Document> for ContentDetector {
-    /// Checks if domain/url is present in provided blocklist, and adds a tag
-    /// corresponding to blocklist kind if true.
+    /// Checks if domain/url is present in provided blocklist and absent from the allowlist,
+    /// and sets categories corresponding to blocklist kinds if true.
     fn annotate(&self, doc: &mut Document) {
-        if let Some(url) = doc.url() {
-            let categories: Option<Vec<String>> = self
-                .bl
-                .detect(&url)
-                .map(|categories| categories.into_iter().map(String::from).collect());
-            doc.metadata_mut().set_categories(categories);
+        if let Some(url) = doc.url().and_then(|url| parse_url(&url)) {
+            let categories = self.detect(&url);
+            if !categories.is_empty() {
+                debug!("Document {} flagged as {:?}", doc.warc_id(), categories);
+            }
+            doc.metadata_mut()
+                .set_categories(Some(categories).filter(|categories| !categories.is_empty()));
         }
     }
 }
@@ -46,7 +283,8 @@ mod tests {
         path::Path,
     };
 
-    use ut1_blocklist::MultipleBlocklist as Blocklist;
+    use url::Url;
+    use ut1_blocklist::Blocklist;
     use warc::WarcHeader;
 
     use crate::{
@@ -54,7 +292,7 @@ mod tests {
         transformers::Annotate,
     };
 
-    use super::ContentDetector;
+    use super::{Allowlist, BlocklistKind, ContentDetector};
 
     fn gen_document(url: &str) -> Document {
         let content = String::new();
@@ -65,6 +303,149 @@ mod tests {
         Document::new(content, headers, metadata)
     }
 
+    fn single() -> ContentDetector {
+        let domains = HashSet::from(["foo.bar".to_string()]);
+        let urls = HashSet::from(["baz.quux/adult/page".to_string()]);
+        ContentDetector::new(BlocklistKind::Single(Blocklist::new(
+            "adult".to_string(),
+            domains,
+            urls,
+        )))
+    }
+
+    fn multiple() -> ContentDetector {
+        let mut domains = HashMap::new();
+        domains.insert("foo.bar".to_string(), vec!["adult".to_string()]);
+        domains.insert(
+            "baz.quux".to_string(),
+            vec!["gambling".to_string(), "adult".to_string()],
+        );
+        let mut urls = HashMap::new();
+        urls.insert(
+            "https://lorem.ipsum/casino".to_string(),
+            vec!["gambling".to_string()],
+        );
+        urls.insert("dolor.sit/poker".to_string(), vec!["gambling".to_string()]);
+        ContentDetector::new(BlocklistKind::multiple(domains, urls))
+    }
+
+    fn detect(cd: &ContentDetector, url: &str) -> Vec<String> {
+        cd.detect(&Url::parse(url).unwrap())
+    }
+
+    #[test]
+    fn test_from_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("adult")).unwrap();
+        std::fs::write(dir.path().join("adult/domains"), "foo.bar\n").unwrap();
+        std::fs::write(dir.path().join("adult/urls"), "baz.quux/page\n").unwrap();
+
+        let cd = ContentDetector::from_dir(dir.path(), Some("adult")).unwrap();
+        assert_eq!(detect(&cd, "https://foo.bar"), vec!["adult"]);
+        let cd = ContentDetector::from_dir(dir.path(), None).unwrap();
+        assert_eq!(detect(&cd, "https://foo.bar"), vec!["adult"]);
+        assert_eq!(detect(&cd, "http://baz.quux/page"), vec!["adult"]);
+        assert!(detect(&cd, "http://baz.quux/").is_empty());
+        assert!(ContentDetector::from_dir(dir.path(), Some("gambling")).is_err());
+        assert!(ContentDetector::from_dir(Path::new("fezlfzej"), None).is_err());
+    }
+
+    #[test]
+    fn test_domain() {
+        for cd in [single(), multiple()] {
+            assert_eq!(detect(&cd, "https://foo.bar/baz?quux=true"), vec!["adult"]);
+            assert!(detect(&cd, "https://notfoo.bar").is_empty());
+        }
+    }
+
+    #[test]
+    fn test_subdomain() {
+        for cd in [single(), multiple()] {
+            assert_eq!(detect(&cd, "https://www.foo.bar"), vec!["adult"]);
+            assert_eq!(detect(&cd, "http://a.b.foo.bar/baz"), vec!["adult"]);
+        }
+
+        // top-level domains alone are never matched
+        let cd = ContentDetector::new(BlocklistKind::Single(Blocklist::new(
+            "adult".to_string(),
+            HashSet::from(["bar".to_string()]),
+            HashSet::new(),
+        )));
+        assert!(detect(&cd, "https://foo.bar").is_empty());
+    }
+
+    #[test]
+    fn test_url() {
+        let cd = single();
+        assert_eq!(
+            detect(&cd, "https://baz.quux/adult/page?a=b"),
+            vec!["adult"]
+        );
+        assert!(detect(&cd, "https://baz.quux/other").is_empty());
+
+        let cd = multiple();
+        assert_eq!(detect(&cd, "https://lorem.ipsum/casino"), vec!["gambling"]);
+        assert!(detect(&cd, "https://lorem.ipsum/").is_empty());
+        // urls are matched whatever their scheme
+        assert_eq!(
+            detect(&cd, "http://lorem.ipsum/casino?a=b"),
+            vec!["gambling"]
+        );
+        assert_eq!(detect(&cd, "http://dolor.sit/poker"), vec!["gambling"]);
+        assert_eq!(detect(&cd, "https://dolor.sit/poker"), vec!["gambling"]);
+        // domain and url categories are merged and sorted
+        assert_eq!(
+            detect(&cd, "https://baz.quux/"),
+            vec!["adult".to_string(), "gambling".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_allowlist() {
+        let allowlist = Allowlist::new(
+            vec!["safe.foo.bar".to_string()],
+            vec![
+                "foo.bar/safe".to_string(),
+                "https://lorem.ipsum/casino".to_string(),
+            ],
+        );
+
+        for mut cd in [single(), multiple()] {
+            cd.set_allowlist(Some(allowlist.clone()));
+            // allowed subdomain, and its subdomains
+            assert!(detect(&cd, "https://safe.foo.bar").is_empty());
+            assert!(detect(&cd, "https://www.safe.foo.bar/baz").is_empty());
+            // allowed url
+            assert!(detect(&cd, "https://foo.bar/safe?a=b").is_empty());
+            // still blocked
+            assert_eq!(detect(&cd, "https://foo.bar/unsafe"), vec!["adult"]);
+            assert_eq!(detect(&cd, "https://www.foo.bar/safe"), vec!["adult"]);
+        }
+
+        let mut cd = multiple();
+        cd.set_allowlist(Some(allowlist));
+        assert!(detect(&cd, "https://lorem.ipsum/casino").is_empty());
+    }
+
+    #[test]
+    fn test_allowlist_from_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("allowlist");
+        std::fs::write(
+            &path,
+            "# comment\nFoo.bar\n\nhttps://baz.quux/page\nlorem.ipsum/a\n",
+        )
+        .unwrap();
+
+        let allowlist = Allowlist::from_path(&path).unwrap();
+        let url = |url: &str| Url::parse(url).unwrap();
+        assert!(allowlist.contains(&url("https://www.foo.bar/")));
+        assert!(allowlist.contains(&url("http://baz.quux/page")));
+        assert!(allowlist.contains(&url("https://lorem.ipsum/a")));
+        assert!(!allowlist.contains(&url("https://baz.quux/")));
+        assert!(!allowlist.contains(&url("https://lorem.ipsum/")));
+    }
+
     #[test]
     fn test_annotation() {
         let mut doc = gen_document("https://foo.bar");
@@ -72,8 +453,7 @@ mod tests {
         let mut domains = HashMap::new();
         domains.insert("foo.bar".to_string(), vec!["adult".to_string()]);
 
-        let bl = Blocklist::new(domains, HashMap::new());
-        let cd = ContentDetector::new(bl);
+        let cd = ContentDetector::new(BlocklistKind::multiple(domains, HashMap::new()));
 
         cd.annotate(&mut doc);
 
@@ -90,11 +470,11 @@ mod tests {
         let mut domains = HashMap::new();
         domains.insert("baz.quux".to_string(), vec!["adult".to_string()]);
 
-        let bl = Blocklist::new(domains, HashMap::new());
-        let cd = ContentDetector::new(bl);
+        let cd = ContentDetector::new(BlocklistKind::multiple(domains, HashMap::new()));
 
         cd.annotate(&mut doc);
 
         assert!(doc.metadata().annotation().is_none());
+        assert!(doc.metadata().categories().is_none());
     }
 }
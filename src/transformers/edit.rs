@@ -0,0 +1,200 @@
+/*! Content edits.
+
+[Transform]s report the changes they make to the content as [Edits], so that they can be replayed
+on the original record at rebuild time:
+
+1. the kept line ranges are selected (ranges are inclusive and relative to the content before the transform),
+1. then each [Edit] replaces a span of bytes of a kept line, in order.
+
+Edits never add nor remove lines, so that line-based selections and replacements can be composed
+(see [crate::pipelines::oscardoc::types::LocationBuilder::apply]).
+
+[Transform]: super::Transform
+!*/
+use std::ops::RangeInclusive;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+
+/// Replacement of the bytes `start..end` of a line by `text`.
+///
+/// `line` is relative to the content once line ranges are selected.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Edit {
+    line: usize,
+    start: usize,
+    end: usize,
+    text: String,
+}
+
+impl Edit {
+    pub fn new(line: usize, start: usize, end: usize, text: String) -> Self {
+        Self {
+            line,
+            start,
+            end,
+            text,
+        }
+    }
+
+    /// Get the smallest edit that turns `old` into `new`, or [None] if they are equal.
+    pub fn diff(line: usize, old: &str, new: &str) -> Option<Self> {
+        if old == new {
+            return None;
+        }
+
+        let prefix: usize = old
+            .chars()
+            .zip(new.chars())
+            .take_while(|(a, b)| a == b)
+            .map(|(a, _)| a.len_utf8())
+            .sum();
+        let suffix: usize = old[prefix..]
+            .chars()
+            .rev()
+            .zip(new[prefix..].chars().rev())
+            .take_while(|(a, b)| a == b)
+            .map(|(a, _)| a.len_utf8())
+            .sum();
+
+        Some(Self::new(
+            line,
+            prefix,
+            old.len() - suffix,
+            new[prefix..new.len() - suffix].to_string(),
+        ))
+    }
+
+    /// Get the edited line.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// Get the same edit on another line.
+    pub fn with_line(self, line: usize) -> Self {
+        Self { line, ..self }
+    }
+
+    /// Apply the edit on `lines`.
+    ///
+    /// Errors if the edited line or span does not exist.
+    pub fn apply(&self, lines: &mut [String]) -> Result<(), Error> {
+        let line = lines
+            .get_mut(self.line)
+            .filter(|line| {
+                self.start <= self.end
+                    && line.is_char_boundary(self.start)
+                    && line.is_char_boundary(self.end)
+            })
+            .ok_or_else(|| Error::Custom(format!("invalid edit {:?}", self)))?;
+
+        line.replace_range(self.start..self.end, &self.text);
+        Ok(())
+    }
+}
+
+/// Changes made by a [Transform](super::Transform): kept line ranges, then replacements in kept lines.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Edits {
+    lines: Vec<RangeInclusive<usize>>,
+    replacements: Vec<Edit>,
+}
+
+impl Edits {
+    pub fn new(lines: Vec<RangeInclusive<usize>>, replacements: Vec<Edit>) -> Self {
+        Self {
+            lines,
+            replacements,
+        }
+    }
+
+    /// Get the edits that turn `old` into `new`, keeping every line.
+    ///
+    /// Both contents should have the same number of lines.
+    pub fn replacing(old: &str, new: &str) -> Self {
+        debug_assert_eq!(old.lines().count(), new.lines().count());
+        let nb_lines = old.lines().count();
+        let replacements = old
+            .lines()
+            .zip(new.lines())
+            .enumerate()
+            .filter_map(|(idx, (old, new))| Edit::diff(idx, old, new))
+            .collect();
+
+        let lines = if nb_lines > 0 {
+            vec![0..=nb_lines - 1]
+        } else {
+            Vec::new()
+        };
+        Self::new(lines, replacements)
+    }
+
+    /// Get the kept line ranges.
+    pub fn lines(&self) -> &[RangeInclusive<usize>] {
+        &self.lines
+    }
+
+    /// Get the replacements.
+    pub fn replacements(&self) -> &[Edit] {
+        &self.replacements
+    }
+
+    /// Check if no line is kept.
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    /// extract owned parts of struct: (kept line ranges, replacements)
+    pub fn into_raw_parts(self) -> (Vec<RangeInclusive<usize>>, Vec<Edit>) {
+        (self.lines, self.replacements)
+    }
+}
+
+/// Line removal only.
+impl From<Vec<RangeInclusive<usize>>> for Edits {
+    fn from(lines: Vec<RangeInclusive<usize>>) -> Self {
+        Self::new(lines, Vec::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff() {
+        assert_eq!(Edit::diff(0, "foo", "foo"), None);
+        assert_eq!(
+            Edit::diff(1, "foo  bar", "foo bar"),
+            Some(Edit::new(1, 4, 5, String::new()))
+        );
+        assert_eq!(
+            Edit::diff(0, "cafÃ©", "café"),
+            Some(Edit::new(0, 3, 7, "é".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_replay() {
+        let old = "foo\u{200b}bar\nbaz\n cafÃ©  ";
+        let new = "foobar\nbaz\n café ";
+        let edits = Edits::replacing(old, new);
+        assert_eq!(edits.lines(), &[0..=2]);
+        assert_eq!(edits.replacements().len(), 2);
+
+        let mut lines: Vec<String> = old.lines().map(String::from).collect();
+        for edit in edits.replacements() {
+            edit.apply(&mut lines).unwrap();
+        }
+        assert_eq!(lines.join("\n"), new);
+    }
+
+    #[test]
+    fn test_apply_invalid() {
+        let mut lines = vec!["café".to_string()];
+        assert!(Edit::new(1, 0, 0, String::new()).apply(&mut lines).is_err());
+        assert!(Edit::new(0, 4, 5, String::new()).apply(&mut lines).is_err());
+        assert!(Edit::new(0, 2, 1, String::new()).apply(&mut lines).is_err());
+    }
+}
@@ -2,6 +2,7 @@
 
 Annotator that watches for short lines at the beginning/end of documents, adding `footer` and/or `header` annotations.
 !*/
+use crate::filtering::sentence::Length;
 use crate::pipelines::oscardoc::types::Document;
 
 use super::Annotate;
@@ -12,19 +13,21 @@ use super::Annotate;
 pub struct Header {
     header_pctg: f64,
     threshold_pctg: f64,
-    min_length: usize,
+    filter: Length,
+    scores: bool,
 }
 
 impl Default for Header {
     /// Default values are:
     /// - 20% of the document for the header/footer
     /// - flagging if >50% of the sentences are short
-    /// - and < 100 lines = short sentence.
+    /// - and < 100 codepoints = short sentence (see [Length] for script-aware thresholds).
     fn default() -> Self {
         Self {
             header_pctg: 0.2,
             threshold_pctg: 0.5,
-            min_length: 100,
+            filter: Length::default(),
+            scores: false,
         }
     }
 }
@@ -40,18 +43,25 @@ impl Annotate<Document> for Header {
         let nb_lines_header = nb_lines_header as usize;
 
         // iterate over the header, counting short lines
-        let short_lines_count = self.count_short_lines(doc.content().lines().take(nb_lines_header));
-        if short_lines_count > treshold_lines {
+        let header_short_lines =
+            self.count_short_lines(doc.content().lines().take(nb_lines_header));
+        if header_short_lines > treshold_lines {
             doc.metadata_mut().add_annotation("header".to_string());
         }
 
         // do the same in reverse order (to get footer)
-        let short_lines_count =
+        let footer_short_lines =
             self.count_short_lines(doc.content().lines().rev().take(nb_lines_header));
 
-        if short_lines_count > treshold_lines {
+        if footer_short_lines > treshold_lines {
             doc.metadata_mut().add_annotation("footer".to_string());
         }
+
+        if self.scores {
+            doc.add_score("header_lines", nb_lines_header as f64);
+            doc.add_score("header_short_lines", header_short_lines as f64);
+            doc.add_score("footer_short_lines", footer_short_lines as f64);
+        }
     }
 }
 
@@ -62,23 +72,32 @@ impl Header {
     ///    A 100 line document with a `header_pctg` at 0.20 will consider the header is lines 0..20.
     /// * `threshold_pctg`: percentage of short lines required to be annotated.
     ///    A 100 line document with 20 header lines will get annotated if there's 10 or more short lines, if we're using 0.50 as a threshold.
-    /// * `min_length` is the minimum length of a sentence. If a sentence is shorter than this, it is considered a short one.
+    /// * `min_length` is the minimum length of a sentence. If a sentence is shorter than this, it is considered a short one (scaled for dense scripts, see [Length]).
     fn new(header_pctg: f64, threshold_pctg: f64, min_length: usize) -> Self {
         Self {
             header_pctg,
             threshold_pctg,
-            min_length,
+            filter: Length::with_min_size(min_length),
+            scores: false,
         }
     }
 
+    /// Also record the number of lines in the header/footer, and the number of short lines in each of them
+    /// (`score:header_lines`, `score:header_short_lines` and `score:footer_short_lines` fields).
+    pub fn set_scores(&mut self, scores: bool) {
+        self.scores = scores;
+    }
+
     /// counts the number of short lines at the beginning of a string iterator.
     #[inline]
+    ///
+    /// Lines are short if they have less codepoints than their (script-aware) minimum length.
     fn count_short_lines<'a>(&self, lines: impl Iterator<Item = &'a str>) -> u64 {
         // reset counter
         let mut short_lines_count = 0;
 
         for line in lines {
-            if line.len() < self.min_length {
+            if self.filter.length(line) < self.filter.min_size_for(line) {
                 short_lines_count += 1;
             }
         }
@@ -243,6 +262,28 @@ This is a lengthy enough sentence! Or at least I hope :)";
         let short_count = h.count_short_lines(text.lines().take(10));
         assert_eq!(short_count, 5);
     }
+
+    #[test]
+    fn test_short_lines_dense_script() {
+        // 45 Han characters are a full sentence, while 45 latin ones aren't.
+        let zh: String = ['字'; 45].iter().collect();
+        let latin: String = ['z'; 45].iter().collect();
+        let text = [zh.as_str(), zh.as_str(), latin.as_str()].join("\n");
+
+        let h = Header::default();
+        let short_count = h.count_short_lines(text.lines());
+        assert_eq!(short_count, 1);
+    }
+
+    #[test]
+    fn test_short_lines_codepoints() {
+        // lengths are in codepoints rather than bytes: 29 "é" are 58 bytes long
+        let text = ["é".repeat(29), "é".repeat(30), "e".repeat(30)].join("\n");
+
+        // lines of exactly the minimum length aren't short
+        let h = Header::new(0.10, 0.50, 30);
+        assert_eq!(h.count_short_lines(text.lines()), 1);
+    }
     #[test]
     fn test_short_nblines_valid_doc() {
         let text = "Lorem ipsum dolor sit amet, consectetur adipiscing elit. Sed a diam mollis, scelerisque arcu sed, bibendum ligula. Curabitur convallis urna auctor mi varius, 
@@ -274,4 +315,26 @@ Etiam ornare ligula sollicitudin scelerisque finibus. Suspendisse orci odio, lao
         h.annotate(&mut d);
         assert_eq!(d.metadata().annotation(), None);
     }
+
+    #[test]
+    fn test_scores() {
+        let mut annotator = Header::new(0.30, 0.60, 30);
+        annotator.set_scores(true);
+        let text = r"This is a lengthy enough sentence! Or at least I hope :)
+short one but it's ok
+short one but it's ok
+This is a lengthy enough sentence! Or at least I hope :)
+This is a lengthy enough sentence! Or at least I hope :)
+This is a lengthy enough sentence! Or at least I hope :)
+This is a lengthy enough sentence! Or at least I hope :)
+This is a lengthy enough sentence! Or at least I hope :)
+This is a lengthy enough sentence! Or at least I hope :)
+short one but it's ok";
+
+        let mut doc = Document::new(text.to_string(), HashMap::new(), Metadata::default());
+        annotator.annotate(&mut doc);
+        assert_eq!(doc.score("header_lines"), Some(3.0));
+        assert_eq!(doc.score("header_short_lines"), Some(2.0));
+        assert_eq!(doc.score("footer_short_lines"), Some(1.0));
+    }
 }
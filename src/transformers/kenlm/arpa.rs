@@ -0,0 +1,343 @@
+//! Pure-Rust ARPA n-gram language model.
+//!
+//! [NgramModel] loads backoff n-gram models in the ARPA text format (as produced by KenLM's `lmplz`)
+//! and scores sentences the same way KenLM does (`log10` probabilities, `<s>`/`</s>` markers,
+//! unknown words mapped to `<unk>`), without native dependencies.
+//!
+//! Models are fully loaded in memory, so this is best suited to small/pruned models
+//! (see [NgramModel::size] for their estimated memory usage).
+//! Invalid model files are reported as [std::io::Error]s of kind [std::io::ErrorKind::InvalidData].
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Error, ErrorKind};
+use std::path::Path;
+
+/// Probability of unknown words when the model has no `<unk>` unigram (same as KenLM).
+const UNK_PROB: f32 = -100.0;
+/// Id of unknown words when the model has no `<unk>` unigram. Never part of an n-gram.
+const NO_UNK: u32 = u32::MAX;
+/// Maximum number of n-grams allocated upfront per order, since header counts can't be trusted.
+const MAX_PREALLOCATED: usize = 1 << 20;
+/// Estimated overhead of a [HashMap] entry (control byte and load factor).
+const ENTRY_OVERHEAD: usize = 8;
+
+/// `(log10 probability, log10 backoff weight)` of an n-gram.
+type Weights = (f32, f32);
+
+/// Backoff n-gram language model.
+#[derive(Debug, Clone)]
+pub struct NgramModel {
+    vocab: HashMap<String, u32>,
+    /// n-grams by order (`ngrams[0]` are unigrams).
+    ngrams: Vec<HashMap<Vec<u32>, Weights>>,
+}
+
+fn invalid(line_nb: usize, msg: &str) -> Error {
+    Error::new(
+        ErrorKind::InvalidData,
+        format!("line {}: {}", line_nb + 1, msg),
+    )
+}
+
+impl NgramModel {
+    /// Load a model from an ARPA file.
+    pub fn from_path(path: &Path) -> Result<Self, Error> {
+        Self::from_reader(BufReader::new(File::open(path)?))
+    }
+
+    /// Load a model from an ARPA content.
+    pub fn from_reader(reader: impl BufRead) -> Result<Self, Error> {
+        let mut vocab = HashMap::new();
+        let mut counts: Vec<usize> = Vec::new();
+        let mut ngrams: Vec<HashMap<Vec<u32>, Weights>> = Vec::new();
+
+        // current section: None is the header, Some(n) is the n-grams section
+        let mut order = None;
+        let mut ended = false;
+        for (line_nb, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || ended {
+                continue;
+            }
+
+            if line == "\\data\\" {
+                continue;
+            } else if line == "\\end\\" {
+                ended = true;
+                continue;
+            } else if let Some(n) = line
+                .strip_prefix('\\')
+                .and_then(|section| section.strip_suffix("-grams:"))
+            {
+                let n: usize = n
+                    .parse()
+                    .map_err(|_| invalid(line_nb, "invalid n-grams section"))?;
+                if n != ngrams.len() + 1 || n > counts.len() {
+                    return Err(invalid(line_nb, "unexpected n-grams section"));
+                }
+                ngrams.push(HashMap::with_capacity(counts[n - 1].min(MAX_PREALLOCATED)));
+                order = Some(n);
+                continue;
+            }
+
+            let n = match order {
+                Some(n) => n,
+                None => {
+                    // header: ngram <n>=<count>
+                    let count = line
+                        .strip_prefix("ngram ")
+                        .and_then(|count| count.split_once('='))
+                        .and_then(|(n, count)| {
+                            Some((
+                                n.trim().parse::<usize>().ok()?,
+                                count.trim().parse::<usize>().ok()?,
+                            ))
+                        });
+                    match count {
+                        Some((n, count)) if n == counts.len() + 1 => counts.push(count),
+                        _ => return Err(invalid(line_nb, "invalid n-gram count")),
+                    }
+                    continue;
+                }
+            };
+
+            // <log10 prob> <word 1> ... <word n> [<log10 backoff>]
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() != n + 1 && fields.len() != n + 2 {
+                return Err(invalid(line_nb, "invalid n-gram"));
+            }
+            let prob: f32 = fields[0]
+                .parse()
+                .map_err(|_| invalid(line_nb, "invalid probability"))?;
+            let backoff: f32 = match fields.get(n + 1) {
+                Some(backoff) => backoff
+                    .parse()
+                    .map_err(|_| invalid(line_nb, "invalid backoff weight"))?,
+                None => 0.0,
+            };
+
+            let ids = fields[1..=n]
+                .iter()
+                .map(|word| {
+                    if n == 1 {
+                        let next_id = vocab.len() as u32;
+                        Some(*vocab.entry(word.to_string()).or_insert(next_id))
+                    } else {
+                        vocab.get(*word).copied()
+                    }
+                })
+                .collect::<Option<Vec<u32>>>()
+                .ok_or_else(|| invalid(line_nb, "word missing from unigrams"))?;
+            ngrams[n - 1].insert(ids, (prob, backoff));
+        }
+
+        if !ended || ngrams.is_empty() || ngrams.len() != counts.len() {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "incomplete ARPA file".to_string(),
+            ));
+        }
+        if ngrams
+            .iter()
+            .zip(&counts)
+            .any(|(ngrams, count)| ngrams.len() != *count)
+        {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "n-gram counts don't match the header".to_string(),
+            ));
+        }
+        if !vocab.contains_key("<s>") || !vocab.contains_key("</s>") {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "missing <s> or </s> unigram".to_string(),
+            ));
+        }
+
+        Ok(Self { vocab, ngrams })
+    }
+
+    /// Get the order of the model.
+    pub fn order(&self) -> usize {
+        self.ngrams.len()
+    }
+
+    /// Get the estimated memory usage of the model, in bytes.
+    pub fn size(&self) -> u64 {
+        let vocab: usize = self
+            .vocab
+            .keys()
+            .map(|word| std::mem::size_of::<(String, u32)>() + ENTRY_OVERHEAD + word.capacity())
+            .sum();
+        let ngrams: usize = self
+            .ngrams
+            .iter()
+            .flat_map(|ngrams| ngrams.keys())
+            .map(|ngram| {
+                std::mem::size_of::<(Vec<u32>, Weights)>()
+                    + ENTRY_OVERHEAD
+                    + ngram.capacity() * std::mem::size_of::<u32>()
+            })
+            .sum();
+        (vocab + ngrams) as u64
+    }
+
+    /// Get the id of a word, unknown words being mapped to `<unk>`.
+    fn index(&self, word: &str) -> u32 {
+        self.vocab
+            .get(word)
+            .or_else(|| self.vocab.get("<unk>"))
+            .copied()
+            .unwrap_or(NO_UNK)
+    }
+
+    /// Get the `log10` probability of `word` after `context` (oldest word first), backing off to shorter contexts.
+    fn score(&self, context: &[u32], word: u32) -> f32 {
+        if word == NO_UNK {
+            return UNK_PROB;
+        }
+
+        // only the last order-1 words are used
+        let context = &context[context.len().saturating_sub(self.order() - 1)..];
+        let mut backoff = 0.0;
+        for start in 0..=context.len() {
+            let context = &context[start..];
+            let mut ngram = context.to_vec();
+            ngram.push(word);
+            if let Some((prob, _)) = self.ngrams[context.len()].get(&ngram) {
+                return prob + backoff;
+            }
+            if let Some((_, weight)) = context
+                .len()
+                .checked_sub(1)
+                .and_then(|idx| self.ngrams[idx].get(context))
+            {
+                backoff += weight;
+            }
+        }
+
+        // only reached for words that have been mapped to <unk> but have no unigram, which can't happen
+        UNK_PROB
+    }
+
+    /// Get the `log10` probability of a sentence (whitespace-tokenized), including the end of sentence.
+    pub fn sentence_score(&self, sentence: &str) -> f32 {
+        let max_context = self.order() - 1;
+        let mut context = vec![self.index("<s>")];
+        let mut total = 0.0;
+
+        let tokens = sentence
+            .split_whitespace()
+            .map(|token| self.index(token))
+            .chain(std::iter::once(self.index("</s>")));
+        for token in tokens {
+            total += self.score(&context, token);
+            context.push(token);
+            if context.len() > max_context {
+                context.remove(0);
+            }
+        }
+
+        total
+    }
+
+    /// Get the perplexity of a sentence, counting the end of sentence as a word (same as KenLM).
+    pub fn perplexity(&self, sentence: &str) -> f32 {
+        let nb_words = sentence.split_whitespace().count() as f32 + 1f32;
+        10f32.powf(-self.sentence_score(sentence) / nb_words)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::ErrorKind;
+
+    use super::NgramModel;
+
+    const ARPA: &str = "
+\\data\\
+ngram 1=4
+ngram 2=2
+
+\\1-grams:
+-1.0\t<s>\t-0.5
+-0.5\t</s>
+-0.7\tfoo\t-0.3
+-2.0\t<unk>
+
+\\2-grams:
+-0.2\t<s> foo
+-0.1\tfoo </s>
+
+\\end\\
+";
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-5, "{a} != {b}");
+    }
+
+    #[test]
+    fn test_score() {
+        let model = NgramModel::from_reader(ARPA.as_bytes()).unwrap();
+        assert_eq!(model.order(), 2);
+
+        // bigrams only
+        assert_close(model.sentence_score("foo"), -0.3);
+        assert_close(model.perplexity("foo"), 10f32.powf(0.15));
+        // foo foo backs off to unigram foo: -0.3 + -0.7
+        assert_close(model.sentence_score("foo  foo"), -1.3);
+        // unknown word: backoff of <s> + <unk>, then unigram </s>
+        assert_close(model.sentence_score("bar"), -3.0);
+    }
+
+    #[test]
+    fn test_size() {
+        let model = NgramModel::from_reader(ARPA.as_bytes()).unwrap();
+        let bigger = ARPA
+            .replace("ngram 1=4", "ngram 1=5")
+            .replace("-2.0\t<unk>", "-2.0\t<unk>\n-1.5\tbar");
+        let bigger = NgramModel::from_reader(bigger.as_bytes()).unwrap();
+        assert!(model.size() > 0);
+        assert!(bigger.size() > model.size());
+    }
+
+    #[test]
+    fn test_trigram() {
+        let arpa = ARPA
+            .replace("ngram 2=2", "ngram 2=2\nngram 3=1")
+            .replace("-0.2\t<s> foo", "-0.2\t<s> foo\t-0.4")
+            .replace("\\end\\", "\\3-grams:\n-0.05\t<s> foo </s>\n\n\\end\\");
+        let model = NgramModel::from_reader(arpa.as_bytes()).unwrap();
+        assert_eq!(model.order(), 3);
+
+        assert_close(model.sentence_score("foo"), -0.25);
+        // <s> foo foo: backoff of <s> foo, then backoff of foo + unigram foo
+        // foo foo </s>: no trigram nor foo foo bigram, then foo </s>
+        assert_close(
+            model.sentence_score("foo foo"),
+            -0.2 - 0.4 - 0.3 - 0.7 - 0.1,
+        );
+    }
+
+    #[test]
+    fn test_invalid() {
+        for arpa in [
+            String::new(),
+            // missing end
+            ARPA.replace("\\end\\", ""),
+            // unknown word in bigram
+            ARPA.replace("foo </s>", "bar </s>"),
+            // invalid probability
+            ARPA.replace("-0.7", "nope"),
+            // missing section
+            ARPA.replace("ngram 2=2", "ngram 2=2\nngram 3=1"),
+            // wrong counts
+            ARPA.replace("ngram 1=4", "ngram 1=99999999999999"),
+            ARPA.replace("ngram 2=2", "ngram 2=3"),
+        ] {
+            let err = NgramModel::from_reader(arpa.as_bytes()).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidData);
+        }
+    }
+}
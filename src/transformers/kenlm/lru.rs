@@ -1,19 +1,26 @@
 use std::{
-    collections::{hash_map::Entry, HashMap, VecDeque},
+    collections::{hash_map::Entry, HashMap, HashSet, VecDeque},
     ffi::OsStr,
-    path::Path,
-    sync::{Arc, RwLock, RwLockReadGuard},
+    fmt,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex, MutexGuard, RwLock,
+    },
 };
 
-use log::{debug, error, warn};
+use log::{debug, info, warn};
 use oxilangtag::LanguageTag;
 
-use crate::pipelines::oscardoc::types::{Document, Location};
-
+#[cfg(feature = "kenlm")]
 use super::{
-    adult_content::{self, AdultDetectorBuilder},
+    adult_content::AdultDetectorBuilder,
+    quality::{QualityModel, QualityModelBuilder},
     AdultDetector,
 };
+use super::{
+    NgramAdultDetector, NgramAdultDetectorBuilder, NgramQualityModel, NgramQualityModelBuilder,
+};
 
 use lazy_static::lazy_static;
 
@@ -26,79 +33,325 @@ lazy_static! {
 pub enum Error {
     NoBuilder(String),
     Building(std::io::Error),
+    /// The model already failed to build (see [Models::get]).
+    Failed(String),
 }
 impl From<std::io::Error> for Error {
     fn from(err: std::io::Error) -> Self {
         Error::Building(err)
     }
 }
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::NoBuilder(msg) => write!(f, "{msg}"),
+            Error::Building(err) => write!(f, "could not build model: {err}"),
+            Error::Failed(msg) => write!(f, "{msg}"),
+        }
+    }
+}
+/// Builder of a KenLM-backed model, from a model file.
+///
+/// Implemented for each KenLM role (adult content detection and quality),
+/// with (`AdultDetectorBuilder`, `QualityModelBuilder`) or without (see [NgramAdultDetectorBuilder]) the `kenlm` feature.
+pub trait ModelBuilder {
+    type Model;
+    /// Whether binary models can be built. If not, only ARPA models are considered.
+    const BINARY: bool = true;
+
+    fn new(path: PathBuf) -> Self;
+    fn path(&self) -> &Path;
+    fn set_path(&mut self, path: &Path);
+    fn build(&self) -> Result<Self::Model, std::io::Error>;
+    /// Estimated memory usage of a built model, if known. Otherwise, the size of the model file is used.
+    fn size(_model: &Self::Model) -> Option<u64> {
+        None
+    }
+}
+
+#[cfg(feature = "kenlm")]
+impl ModelBuilder for AdultDetectorBuilder {
+    type Model = AdultDetector;
+
+    fn new(path: PathBuf) -> Self {
+        AdultDetectorBuilder::new(path)
+    }
+    fn path(&self) -> &Path {
+        AdultDetectorBuilder::path(self)
+    }
+    fn set_path(&mut self, path: &Path) {
+        AdultDetectorBuilder::set_path(self, path)
+    }
+    fn build(&self) -> Result<AdultDetector, std::io::Error> {
+        AdultDetectorBuilder::build(self)
+    }
+}
+
+#[cfg(feature = "kenlm")]
+impl ModelBuilder for QualityModelBuilder {
+    type Model = QualityModel;
+
+    fn new(path: PathBuf) -> Self {
+        QualityModelBuilder::new(path)
+    }
+    fn path(&self) -> &Path {
+        QualityModelBuilder::path(self)
+    }
+    fn set_path(&mut self, path: &Path) {
+        QualityModelBuilder::set_path(self, path)
+    }
+    fn build(&self) -> Result<QualityModel, std::io::Error> {
+        QualityModelBuilder::build(self)
+    }
+}
+
+impl ModelBuilder for NgramAdultDetectorBuilder {
+    type Model = NgramAdultDetector;
+    const BINARY: bool = false;
+
+    fn new(path: PathBuf) -> Self {
+        NgramAdultDetectorBuilder::new(path)
+    }
+    fn path(&self) -> &Path {
+        NgramAdultDetectorBuilder::path(self)
+    }
+    fn set_path(&mut self, path: &Path) {
+        NgramAdultDetectorBuilder::set_path(self, path)
+    }
+    fn build(&self) -> Result<NgramAdultDetector, std::io::Error> {
+        NgramAdultDetectorBuilder::build(self)
+    }
+    fn size(model: &NgramAdultDetector) -> Option<u64> {
+        Some(model.size())
+    }
+}
+
+impl ModelBuilder for NgramQualityModelBuilder {
+    type Model = NgramQualityModel;
+    const BINARY: bool = false;
+
+    fn new(path: PathBuf) -> Self {
+        NgramQualityModelBuilder::new(path)
+    }
+    fn path(&self) -> &Path {
+        NgramQualityModelBuilder::path(self)
+    }
+    fn set_path(&mut self, path: &Path) {
+        NgramQualityModelBuilder::set_path(self, path)
+    }
+    fn build(&self) -> Result<NgramQualityModel, std::io::Error> {
+        NgramQualityModelBuilder::build(self)
+    }
+    fn size(model: &NgramQualityModel) -> Option<u64> {
+        Some(model.size())
+    }
+}
+
+/// Bounds on loaded models. Unbounded by default.
+///
+/// Pure-Rust models are sized from their loaded n-grams (see [ModelBuilder::size]).
+/// KenLM-backed ones are sized from their files, which is close to their memory usage for binary models
+/// and only a rough estimate for ARPA ones.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Capacity {
+    max_models: Option<usize>,
+    max_bytes: Option<u64>,
+}
+
+impl Capacity {
+    pub fn new(max_models: Option<usize>, max_bytes: Option<u64>) -> Self {
+        Self {
+            max_models,
+            max_bytes,
+        }
+    }
+
+    /// Check if `nb_models` models weighting `bytes` exceed capacity.
+    fn exceeded(&self, nb_models: usize, bytes: u64) -> bool {
+        self.max_models.is_some_and(|max| nb_models > max)
+            || self.max_bytes.is_some_and(|max| bytes > max)
+    }
+}
+
+/// Load/eviction counts.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ModelStats {
+    /// Requested models that were already loaded.
+    pub hits: usize,
+    /// Loaded models.
+    pub loads: usize,
+    /// Models that could not be built.
+    pub failures: usize,
+    /// Models unloaded to stay within capacity.
+    pub evictions: usize,
+}
+
+/// Loaded models along with their size, and languages from least to most recently used.
+struct Cache<M> {
+    models: HashMap<LanguageTag<String>, (Arc<RwLock<M>>, u64)>,
+    recency: VecDeque<LanguageTag<String>>,
+    bytes: u64,
+}
+
+impl<M> Default for Cache<M> {
+    fn default() -> Self {
+        Self {
+            models: HashMap::new(),
+            recency: VecDeque::new(),
+            bytes: 0,
+        }
+    }
+}
+
+impl<M> Cache<M> {
+    /// Get a model, marking it as the most recently used.
+    fn get(&mut self, lang: &LanguageTag<String>) -> Option<Arc<RwLock<M>>> {
+        let model = self.models.get(lang).map(|(model, _)| model.clone())?;
+        self.recency.retain(|l| l != lang);
+        self.recency.push_back(lang.to_owned());
+        Some(model)
+    }
+
+    /// Insert (or replace) a model, marking it as the most recently used.
+    fn insert(&mut self, lang: &LanguageTag<String>, model: Arc<RwLock<M>>, size: u64) {
+        self.remove(lang);
+        self.models.insert(lang.to_owned(), (model, size));
+        self.recency.push_back(lang.to_owned());
+        self.bytes += size;
+    }
+
+    /// Remove a model, returning true if it was loaded.
+    fn remove(&mut self, lang: &str) -> bool {
+        match self.models.remove(lang) {
+            Some((_, size)) => {
+                self.recency.retain(|l| l.as_str() != lang);
+                self.bytes -= size;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Shared builders, by language.
+type Builders<B> = Arc<RwLock<HashMap<LanguageTag<String>, Arc<RwLock<B>>>>>;
+/// Shared model built by `B`.
+type Model<B> = Arc<RwLock<<B as ModelBuilder>::Model>>;
+
 /// Model holder.
 /// Internally has two [HashMap]: One with builders and one with the actual models.
 ///
-/// This is to lazily load models as we need them, and it should help build a LRU-like caching system so that RAM usage doesn't explode.
-#[derive(Default)]
-pub struct Models {
-    models: Arc<RwLock<HashMap<LanguageTag<String>, Arc<RwLock<AdultDetector>>>>>,
-    builders: Arc<RwLock<HashMap<LanguageTag<String>, Arc<RwLock<AdultDetectorBuilder>>>>>,
+/// Models are lazily loaded as we need them (see [Models::get]), and least recently used ones are unloaded
+/// when loading another one would exceed [Capacity], so that RAM usage doesn't explode.
+/// Evicted models that are still in use are only dropped once every user is done with them.
+///
+/// Models are built by `B` (ex. `Models<AdultDetectorBuilder>` holds adult content detectors).
+pub struct Models<B: ModelBuilder> {
+    models: Mutex<Cache<B::Model>>,
+    builders: Builders<B>,
+    capacity: Capacity,
+    dir: Option<PathBuf>,
+    appendable: bool,
+    missing: Mutex<HashSet<LanguageTag<String>>>,
+    /// languages whose model could not be built
+    failed: Mutex<HashSet<LanguageTag<String>>>,
+    /// per-language locks, held while a model is being built
+    loading: Mutex<HashMap<LanguageTag<String>, Arc<Mutex<()>>>>,
+    hits: AtomicUsize,
+    loads: AtomicUsize,
+    failures: AtomicUsize,
+    evictions: AtomicUsize,
+}
+
+impl<B: ModelBuilder> Default for Models<B> {
+    fn default() -> Self {
+        Self {
+            models: Default::default(),
+            builders: Default::default(),
+            capacity: Capacity::default(),
+            dir: None,
+            appendable: false,
+            missing: Default::default(),
+            failed: Default::default(),
+            loading: Default::default(),
+            hits: AtomicUsize::new(0),
+            loads: AtomicUsize::new(0),
+            failures: AtomicUsize::new(0),
+            evictions: AtomicUsize::new(0),
+        }
+    }
 }
 
-impl Models {
-    /// Get a read lock on loaded models.
-    pub fn models(
-        &self,
-    ) -> RwLockReadGuard<HashMap<LanguageTag<String>, Arc<RwLock<AdultDetector>>>> {
-        self.models.read().unwrap()
+impl<B: ModelBuilder> Models<B> {
+    /// Lock loaded models.
+    fn cache(&self) -> MutexGuard<'_, Cache<B::Model>> {
+        self.models.lock().expect("Problem locking models")
+    }
+
+    /// Set the bounds on loaded models. Models already loaded are not evicted until the next load.
+    pub fn set_capacity(&mut self, capacity: Capacity) {
+        self.capacity = capacity;
+    }
+
+    /// Look for models of languages without builder in the models directory when they are requested,
+    /// so that models can be added while the pipeline runs.
+    ///
+    /// This has no effect on models that are not created with [Models::from_dir].
+    pub fn set_appendable(&mut self, appendable: bool) {
+        self.appendable = appendable;
     }
 
     /// Create a new `Models` struct already populated by builders for langs/models present in provided directory.
     pub fn from_dir(dir: &Path) -> std::io::Result<Self> {
-        let mut builders: HashMap<LanguageTag<_>, AdultDetectorBuilder> = HashMap::new();
+        let mut builders: HashMap<LanguageTag<_>, B> = HashMap::new();
 
         // iterate over entries in kenlms path
-        for direntry in std::fs::read_dir(&dir)? {
-            if let Ok(direntry) = direntry {
-                let model_path = direntry.path();
-                if !model_path.is_file() {
-                    debug!("{model_path:?} is not a file, skipping");
-                    continue;
-                }
+        for direntry in std::fs::read_dir(dir)?.flatten() {
+            let model_path = direntry.path();
+            if !model_path.is_file() {
+                debug!("{model_path:?} is not a file, skipping");
+                continue;
+            }
 
-                // skip files that are not arpa or binary
-                if !KENLM_EXTS.contains(&model_path.extension()) {
-                    warn!("{model_path:?} is not a KenLM model file, skipping");
-                    continue;
-                }
+            // skip files that are not arpa or binary
+            if !KENLM_EXTS.contains(&model_path.extension()) {
+                warn!("{model_path:?} is not a KenLM model file, skipping");
+                continue;
+            }
+            if !B::BINARY && model_path.extension() == KENLM_EXTS[1] {
+                warn!("{model_path:?} is a binary KenLM model, which needs the kenlm feature, skipping");
+                continue;
+            }
 
-                //  get model name
-                let model_name = model_path.file_stem();
+            //  get model name
+            let model_name = model_path.file_stem();
 
-                if model_name.is_none() {
-                    warn!("Couldn't find a model name for {model_path:?}, skipping");
-                    continue;
-                }
+            if model_name.is_none() {
+                warn!("Couldn't find a model name for {model_path:?}, skipping");
+                continue;
+            }
 
-                let model_name = model_name.unwrap().to_string_lossy().to_string();
-
-                // try to parse the model name into a language
-                if let Ok(model_name) = LanguageTag::parse(model_name.to_owned()) {
-                    match builders.entry(model_name) {
-                        // if we already have one, check file extension
-                        Entry::Occupied(mut o) => {
-                            // if we have a builder on arpa model, replace by binary model.
-                            if o.get().path().extension() == KENLM_EXTS[0] {
-                                o.get_mut().set_path(&model_path);
-                            }
-                        }
+            let model_name = model_name.unwrap().to_string_lossy().to_string();
 
-                        // insert
-                        Entry::Vacant(v) => {
-                            v.insert(AdultDetectorBuilder::new(model_path.to_path_buf()));
+            // try to parse the model name into a language
+            if let Ok(model_name) = LanguageTag::parse(model_name.to_owned()) {
+                match builders.entry(model_name) {
+                    // if we already have one, check file extension
+                    Entry::Occupied(mut o) => {
+                        // if we have a builder on arpa model, replace by binary model.
+                        if o.get().path().extension() == KENLM_EXTS[0] {
+                            o.get_mut().set_path(&model_path);
                         }
                     }
-                } else {
-                    warn!("Couldn't parse {model_name:?} into a proper language tag, skipping");
-                    continue;
+
+                    // insert
+                    Entry::Vacant(v) => {
+                        v.insert(B::new(model_path.to_path_buf()));
+                    }
                 }
+            } else {
+                warn!("Couldn't parse {model_name:?} into a proper language tag, skipping");
+                continue;
             }
         }
 
@@ -109,8 +362,6 @@ impl Models {
         );
 
         // wrap into arc+rwlock.
-        // TODO: maybe remove arc+rwlock on the HM and/or on the models, since after this step there's no write access,
-        //       or keep it for LRU cache impl later on.
         let builders = builders
             .into_iter()
             .map(|(name, builder)| (name, Arc::new(RwLock::new(builder))))
@@ -118,10 +369,19 @@ impl Models {
 
         Ok(Models {
             builders: Arc::new(RwLock::new(builders)),
+            dir: Some(dir.to_path_buf()),
             ..Default::default()
         })
     }
 
+    /// Check if there are no builders.
+    pub fn is_empty(&self) -> bool {
+        self.builders
+            .read()
+            .expect("Problem locking builders (in read)")
+            .is_empty()
+    }
+
     /// Check if there is a builder for a given language.
     pub fn contains(&self, lang: &LanguageTag<String>) -> bool {
         self.builders
@@ -137,41 +397,387 @@ impl Models {
     ///
     /// Use [Models::contains] to check for the presence of a builder specifically.
     pub fn is_loaded(&self, lang: &str) -> bool {
-        self.models
-            .read()
-            .expect("Problem locking models (in read)")
-            .contains_key(lang)
+        self.cache().models.contains_key(lang)
     }
 
-    /// Insert a new builder for a given language.
-    /// Behaves like [HashMap::insert].
-    ///
-    /// Be aware that you'll have to call [Models::get] to actually build the model.
-    pub fn insert_builder(&self, lang: &LanguageTag<String>, builder: AdultDetectorBuilder) {
-        debug!("Creating builder for {lang}");
-        let mut builders_lock = self.builders.write().unwrap();
-        builders_lock.insert(lang.to_owned(), Arc::new(RwLock::new(builder)));
+    /// Get the loaded languages, from least to most recently used.
+    pub fn loaded(&self) -> Vec<LanguageTag<String>> {
+        self.cache().recency.iter().cloned().collect()
     }
 
-    /// Load a model by using this language's builder.
-    pub fn load(&self, lang: &LanguageTag<String>) -> Result<(), Error> {
+    /// Build a model by using this language's builder, returning it along with its estimated size.
+    ///
+    /// Models are built without locking loaded models, so that other languages can still be used meanwhile.
+    fn build(&self, lang: &LanguageTag<String>) -> Result<(Model<B>, u64), Error> {
+        let builder = self.builder(lang).ok_or_else(|| {
+            // only warn once per language, since it happens for each shard
+            if self.missing.lock().unwrap().insert(lang.to_owned()) {
+                warn!("No KenLM for lang {lang}, skipping");
+            }
+            Error::NoBuilder(format!("No builder found for {lang:?}"))
+        })?;
+
+        let builder = builder.read().unwrap();
         debug!("Loading model {lang} in memory");
-        let builders = self.builders.read().unwrap();
-        if let Some(builder) = builders.get(lang) {
-            let builder = builder.write().unwrap();
-            let mut models = self.models.write().unwrap();
-            models.insert(lang.to_owned(), Arc::new(RwLock::new(builder.build()?)));
-            Ok(())
+        let model = builder.build().inspect_err(|e| {
+            self.failures.fetch_add(1, Ordering::Relaxed);
+            self.failed.lock().unwrap().insert(lang.to_owned());
+            warn!("Could not load KenLM for lang {lang}, skipping: {e}");
+        })?;
+        let size = B::size(&model).unwrap_or_else(|| {
+            std::fs::metadata(builder.path())
+                .map(|metadata| metadata.len())
+                .unwrap_or_default()
+        });
+        self.loads.fetch_add(1, Ordering::Relaxed);
+
+        Ok((Arc::new(RwLock::new(model)), size))
+    }
+
+    /// Get the builder of a language, looking for a model file in the models directory if appendable.
+    fn builder(&self, lang: &LanguageTag<String>) -> Option<Arc<RwLock<B>>> {
+        if let Some(builder) = self.builders.read().unwrap().get(lang) {
+            return Some(builder.clone());
+        }
+
+        let dir = self.dir.as_ref().filter(|_| self.appendable)?;
+        // prefer binary models, as in from_dir
+        let model_path = KENLM_EXTS
+            .iter()
+            .take(if B::BINARY { 2 } else { 1 })
+            .rev()
+            .flatten()
+            .map(|ext| dir.join(lang.as_str()).with_extension(ext))
+            .find(|path| path.is_file())?;
+
+        debug!("Found new model {model_path:?}");
+        let builder = Arc::new(RwLock::new(B::new(model_path)));
+        let builder = self
+            .builders
+            .write()
+            .unwrap()
+            .entry(lang.to_owned())
+            .or_insert(builder)
+            .clone();
+        Some(builder)
+    }
+
+    /// Evict least recently used models (except `lang`) until capacity is respected.
+    fn evict(&self, cache: &mut Cache<B::Model>, lang: &LanguageTag<String>) {
+        while self.capacity.exceeded(cache.models.len(), cache.bytes) {
+            let lru = cache.recency.iter().find(|l| *l != lang).cloned();
+            match lru {
+                Some(lru) => {
+                    debug!("Evicting model {lru} from memory");
+                    cache.remove(&lru);
+                    self.evictions.fetch_add(1, Ordering::Relaxed);
+                }
+                // a single model over capacity is kept
+                None => break,
+            }
+        }
+    }
+
+    /// Check if the model of a language already failed to build.
+    fn check_failed(&self, lang: &LanguageTag<String>) -> Result<(), Error> {
+        if self.failed.lock().unwrap().contains(lang) {
+            Err(Error::Failed(format!("Model for {lang:?} failed to build")))
         } else {
-            debug!("Could not load model for lang {lang}");
-            Err(Error::NoBuilder(format!("No builder found for {lang:?}")))
+            Ok(())
         }
     }
 
-    /// Unload a model.
-    fn unload(&self, lang: &str) {
+    /// Load a model by using this language's builder, replacing the loaded one if any.
+    ///
+    /// Contrary to [Models::get], models that failed to build are built again.
+    pub fn load(&self, lang: &LanguageTag<String>) -> Result<(), Error> {
+        self.failed.lock().unwrap().remove(lang);
+        let (model, size) = self.build(lang)?;
+        let mut cache = self.cache();
+        cache.insert(lang, model, size);
+        self.evict(&mut cache, lang);
+        Ok(())
+    }
+
+    /// Get the model of a given language, loading it if needed.
+    ///
+    /// A model is only built by one thread at a time: other threads requesting the same language wait for it
+    /// (while other languages can still be used), then get the loaded model.
+    /// The returned model stays usable even if it is evicted afterwards.
+    ///
+    /// Models that fail to build are not built again (see [Models::load]), so that failures are only paid
+    /// (and reported) once.
+    pub fn get(&self, lang: &LanguageTag<String>) -> Result<Model<B>, Error> {
+        if let Some(model) = self.cache().get(lang) {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(model);
+        }
+        self.check_failed(lang)?;
+
+        let loading = self
+            .loading
+            .lock()
+            .unwrap()
+            .entry(lang.to_owned())
+            .or_default()
+            .clone();
+        let _loading = loading.lock().unwrap();
+
+        // another thread may have loaded the model (or failed to) while we were waiting
+        if let Some(model) = self.cache().get(lang) {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(model);
+        }
+        self.check_failed(lang)?;
+
+        let (model, size) = self.build(lang)?;
+        let mut cache = self.cache();
+        cache.insert(lang, model.clone(), size);
+        self.evict(&mut cache, lang);
+        Ok(model)
+    }
+
+    /// Unload a model, returning true if it was loaded.
+    pub fn unload(&self, lang: &str) -> bool {
         debug!("Unloading model {lang} from memory");
-        let mut models = self.models.write().unwrap();
-        models.remove(lang);
+        self.cache().remove(lang)
+    }
+
+    /// Get load/eviction counts.
+    pub fn stats(&self) -> ModelStats {
+        ModelStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            loads: self.loads.load(Ordering::Relaxed),
+            failures: self.failures.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Log load/eviction counts.
+    pub fn report(&self) {
+        let stats = self.stats();
+        info!(
+            "kenlms: {} hits, {} loads, {} failures, {} evictions",
+            stats.hits, stats.loads, stats.failures, stats.evictions
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+    use std::sync::{Arc, Barrier};
+    use std::time::Duration;
+
+    use oxilangtag::LanguageTag;
+
+    use super::{Capacity, Error, ModelBuilder, ModelStats, Models, NgramAdultDetectorBuilder};
+
+    /// Builds the model file content, without needing an actual KenLM.
+    ///
+    /// Building takes some time, so that concurrent loads overlap.
+    struct FakeBuilder {
+        path: PathBuf,
+    }
+
+    impl ModelBuilder for FakeBuilder {
+        type Model = String;
+
+        fn new(path: PathBuf) -> Self {
+            Self { path }
+        }
+        fn path(&self) -> &Path {
+            &self.path
+        }
+        fn set_path(&mut self, path: &Path) {
+            self.path = path.to_path_buf()
+        }
+        fn build(&self) -> Result<String, std::io::Error> {
+            std::thread::sleep(Duration::from_millis(20));
+            std::fs::read_to_string(&self.path)
+        }
+    }
+
+    fn lang(lang: &str) -> LanguageTag<String> {
+        LanguageTag::parse(lang.to_string()).unwrap()
+    }
+
+    /// Create models for `en`, `fr` and `de`, with 10 bytes model files.
+    fn models(dir: &Path, capacity: Capacity) -> Models<FakeBuilder> {
+        for l in ["en", "fr", "de"] {
+            std::fs::write(dir.join(format!("{l}.binary")), format!("{l} model..")).unwrap();
+        }
+        std::fs::write(dir.join("notamodel.txt"), "").unwrap();
+
+        let mut models = Models::from_dir(dir).unwrap();
+        models.set_capacity(capacity);
+        models
+    }
+
+    #[test]
+    fn test_get() {
+        let dir = tempfile::tempdir().unwrap();
+        let models = models(dir.path(), Capacity::default());
+
+        assert!(models.contains(&lang("en")));
+        assert!(!models.is_loaded("en"));
+        assert_eq!(
+            *models.get(&lang("en")).unwrap().read().unwrap(),
+            "en model.."
+        );
+        assert!(models.is_loaded("en"));
+        assert!(models.get(&lang("it")).is_err());
+
+        models.get(&lang("en")).unwrap();
+        assert_eq!(
+            models.stats(),
+            ModelStats {
+                hits: 1,
+                loads: 1,
+                failures: 0,
+                evictions: 0
+            }
+        );
+
+        assert!(models.unload("en"));
+        assert!(!models.unload("en"));
+        assert!(models.loaded().is_empty());
+    }
+
+    #[test]
+    fn test_failure() {
+        let dir = tempfile::tempdir().unwrap();
+        let models = models(dir.path(), Capacity::default());
+        std::fs::remove_file(dir.path().join("en.binary")).unwrap();
+
+        assert!(matches!(models.get(&lang("en")), Err(Error::Building(_))));
+        // failures are remembered
+        assert!(matches!(models.get(&lang("en")), Err(Error::Failed(_))));
+        assert_eq!(models.stats().failures, 1);
+
+        // explicit loads build again
+        std::fs::write(dir.path().join("en.binary"), "en model..").unwrap();
+        models.load(&lang("en")).unwrap();
+        assert_eq!(
+            *models.get(&lang("en")).unwrap().read().unwrap(),
+            "en model.."
+        );
+    }
+
+    #[test]
+    fn test_evict_count() {
+        let dir = tempfile::tempdir().unwrap();
+        let models = models(dir.path(), Capacity::new(Some(2), None));
+
+        models.get(&lang("en")).unwrap();
+        models.get(&lang("fr")).unwrap();
+        // en is now the most recently used
+        let en = models.get(&lang("en")).unwrap();
+        models.get(&lang("de")).unwrap();
+
+        assert_eq!(models.loaded(), vec![lang("en"), lang("de")]);
+        assert_eq!(models.stats().evictions, 1);
+
+        // evicted models stay usable by their users
+        models.get(&lang("fr")).unwrap();
+        assert!(!models.is_loaded("en"));
+        assert_eq!(*en.read().unwrap(), "en model..");
+    }
+
+    #[test]
+    fn test_evict_bytes() {
+        let dir = tempfile::tempdir().unwrap();
+        let models = models(dir.path(), Capacity::new(None, Some(15)));
+
+        models.load(&lang("en")).unwrap();
+        models.load(&lang("fr")).unwrap();
+        assert_eq!(models.loaded(), vec![lang("fr")]);
+
+        // a single model over capacity is kept
+        let models = self::models(dir.path(), Capacity::new(None, Some(5)));
+        models.load(&lang("en")).unwrap();
+        assert_eq!(models.loaded(), vec![lang("en")]);
+    }
+
+    #[test]
+    fn test_appendable() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut models = models(dir.path(), Capacity::default());
+        assert!(models.get(&lang("it")).is_err());
+
+        // models added after creation are only found when appendable
+        std::fs::write(dir.path().join("it.arpa"), "it arpa").unwrap();
+        assert!(models.get(&lang("it")).is_err());
+        models.set_appendable(true);
+        assert_eq!(*models.get(&lang("it")).unwrap().read().unwrap(), "it arpa");
+        assert!(models.contains(&lang("it")));
+        assert!(models.get(&lang("es")).is_err());
+
+        assert!(Models::<FakeBuilder>::from_dir(&dir.path().join("it.arpa")).is_err());
+    }
+
+    #[test]
+    fn test_arpa_only() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("en.binary"), "").unwrap();
+        std::fs::write(dir.path().join("fr.binary"), "").unwrap();
+        std::fs::write(dir.path().join("fr.arpa"), "").unwrap();
+
+        let models = Models::<NgramAdultDetectorBuilder>::from_dir(dir.path()).unwrap();
+        assert!(!models.contains(&lang("en")));
+        assert!(models.contains(&lang("fr")));
+    }
+
+    #[test]
+    fn test_concurrent_get() {
+        let dir = tempfile::tempdir().unwrap();
+        let models = Arc::new(models(dir.path(), Capacity::new(Some(1), None)));
+
+        let handles: Vec<_> = (0..8)
+            .map(|i| {
+                let models = models.clone();
+                std::thread::spawn(move || {
+                    let l = ["en", "fr", "de"][i % 3];
+                    for _ in 0..20 {
+                        let model = models.get(&lang(l)).unwrap();
+                        assert!(model.read().unwrap().starts_with(l));
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        assert_eq!(models.loaded().len(), 1);
+        // each call either hits or loads
+        let stats = models.stats();
+        assert_eq!(stats.hits + stats.loads, 8 * 20);
+    }
+
+    #[test]
+    fn test_single_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let models = Arc::new(models(dir.path(), Capacity::default()));
+        let barrier = Arc::new(Barrier::new(2));
+
+        // two shards needing the same model at the same time
+        let handles: Vec<_> = (0..2)
+            .map(|_| {
+                let models = models.clone();
+                let barrier = barrier.clone();
+                std::thread::spawn(move || {
+                    barrier.wait();
+                    models.get(&lang("en")).unwrap();
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let stats = models.stats();
+        assert_eq!(stats.loads, 1);
+        assert_eq!(stats.hits, 1);
     }
 }
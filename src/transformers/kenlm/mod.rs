@@ -1,5 +1,27 @@
+//! KenLM-backed models, and their pure-Rust counterparts.
+//!
+//! With the `kenlm` feature, models are backed by KenLM (binary or ARPA models).
+//! Otherwise, ARPA models are loaded by a pure-Rust n-gram model (see [NgramModel]).
+#[cfg(feature = "kenlm")]
 mod adult_content;
+mod arpa;
 mod lru;
+mod ngram;
+#[cfg(feature = "kenlm")]
+mod quality;
+#[cfg(feature = "kenlm")]
 pub use adult_content::AdultDetector;
+#[cfg(feature = "kenlm")]
 pub use adult_content::AdultDetectorBuilder;
+pub use arpa::NgramModel;
+pub use lru::Capacity;
+pub use lru::ModelBuilder;
 pub use lru::Models;
+pub use ngram::NgramAdultDetector;
+pub use ngram::NgramAdultDetectorBuilder;
+pub use ngram::NgramQualityModel;
+pub use ngram::NgramQualityModelBuilder;
+#[cfg(feature = "kenlm")]
+pub use quality::QualityModel;
+#[cfg(feature = "kenlm")]
+pub use quality::QualityModelBuilder;
@@ -0,0 +1,214 @@
+//! Pure-Rust counterparts of [the KenLM-backed models](super), using [NgramModel].
+//!
+//! Only ARPA models can be loaded. They are used by the pipeline when the `kenlm` feature is off.
+use std::path::{Path, PathBuf};
+
+use log::debug;
+
+use crate::{
+    filtering::perplexity::{self, PERPLEXITY_SCORE},
+    pipelines::oscardoc::types::Document,
+    transformers::Annotate,
+};
+
+use super::NgramModel;
+
+/// Load an ARPA model, erroring on missing or binary models.
+fn build(path: &Path) -> Result<NgramModel, std::io::Error> {
+    debug!("Building new n-gram model from path {:?}", path);
+    if !path.exists() {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("{:?} not found.", path),
+        ))
+    } else if path.extension().is_some_and(|ext| ext == "binary") {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            format!("{:?}: binary models need the kenlm feature.", path),
+        ))
+    } else {
+        NgramModel::from_path(path)
+    }
+}
+
+pub struct NgramAdultDetectorBuilder {
+    path: PathBuf,
+}
+
+impl NgramAdultDetectorBuilder {
+    pub fn new(path: PathBuf) -> NgramAdultDetectorBuilder {
+        debug!("New builder: {:?}", path);
+        Self { path }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+    pub fn set_path(&mut self, path: &Path) {
+        self.path = path.to_path_buf()
+    }
+    pub fn build(&self) -> Result<NgramAdultDetector, std::io::Error> {
+        Ok(NgramAdultDetector {
+            model: build(&self.path)?,
+        })
+    }
+}
+
+/// Same as [AdultDetector](super::AdultDetector), using a pure-Rust [NgramModel].
+pub struct NgramAdultDetector {
+    model: NgramModel,
+}
+
+impl NgramAdultDetector {
+    /// Get the estimated memory usage of the model (see [NgramModel::size]).
+    pub fn size(&self) -> u64 {
+        self.model.size()
+    }
+}
+
+impl Annotate<Document> for NgramAdultDetector {
+    fn annotate(&self, doc: &mut Document) {
+        let content = doc.content().replace('\n', " ");
+        doc.metadata_mut()
+            .set_harmful_pp(Some(self.model.perplexity(&content)));
+    }
+}
+
+pub struct NgramQualityModelBuilder {
+    path: PathBuf,
+}
+
+impl NgramQualityModelBuilder {
+    pub fn new(path: PathBuf) -> NgramQualityModelBuilder {
+        debug!("New builder: {:?}", path);
+        Self { path }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+    pub fn set_path(&mut self, path: &Path) {
+        self.path = path.to_path_buf()
+    }
+    pub fn build(&self) -> Result<NgramQualityModel, std::io::Error> {
+        Ok(NgramQualityModel {
+            model: build(&self.path)?,
+        })
+    }
+}
+
+/// Same as [QualityModel](super::QualityModel), using a pure-Rust [NgramModel].
+pub struct NgramQualityModel {
+    model: NgramModel,
+}
+
+impl NgramQualityModel {
+    /// Get the estimated memory usage of the model (see [NgramModel::size]).
+    pub fn size(&self) -> u64 {
+        self.model.size()
+    }
+
+    /// Get the perplexity of the content, scoring each non-blank line as a sentence.
+    /// Returns [None] if content is blank.
+    pub fn perplexity(&self, content: &str) -> Option<f32> {
+        let line_scores: Vec<_> = content
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                (
+                    self.model.sentence_score(line),
+                    line.split_whitespace().count() + 1,
+                )
+            })
+            .collect();
+
+        perplexity::doc_perplexity(&line_scores)
+    }
+}
+
+/// Adds the document perplexity in the `score:perplexity` field.
+impl Annotate<Document> for NgramQualityModel {
+    fn annotate(&self, doc: &mut Document) {
+        if let Some(perplexity) = self.perplexity(doc.content()) {
+            doc.add_score(PERPLEXITY_SCORE, perplexity as f64);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::io::ErrorKind;
+
+    use oxilangtag::LanguageTag;
+
+    use crate::identifiers::identification::Identification;
+    use crate::pipelines::oscardoc::types::Metadata;
+
+    use super::*;
+
+    const ARPA: &str = "\\data\\
+ngram 1=4
+ngram 2=2
+
+\\1-grams:
+-1.0\t<s>\t-0.5
+-0.5\t</s>
+-0.7\tfoo\t-0.3
+-2.0\t<unk>
+
+\\2-grams:
+-0.2\t<s> foo
+-0.1\tfoo </s>
+
+\\end\\
+";
+
+    fn doc(content: &str) -> Document {
+        let id = Identification::new(LanguageTag::parse("en".to_string()).unwrap(), 1.0);
+        Document::new(content.to_string(), HashMap::new(), Metadata::new(&id, &[]))
+    }
+
+    #[test]
+    fn test_build() {
+        let dir = tempfile::tempdir().unwrap();
+        let arpa = dir.path().join("en.arpa");
+        std::fs::write(&arpa, ARPA).unwrap();
+        let binary = dir.path().join("en.binary");
+        std::fs::write(&binary, ARPA).unwrap();
+
+        assert!(NgramAdultDetectorBuilder::new(arpa.clone()).build().is_ok());
+        assert!(NgramQualityModelBuilder::new(arpa).build().is_ok());
+        let err = NgramAdultDetectorBuilder::new(binary)
+            .build()
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), ErrorKind::Unsupported);
+        let err = NgramQualityModelBuilder::new(PathBuf::from("fezlfzej"))
+            .build()
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn test_annotate() {
+        let model = NgramModel::from_reader(ARPA.as_bytes()).unwrap();
+
+        // whole document as a single sentence: foo foo
+        let mut d = doc("foo\nfoo");
+        let adult = NgramAdultDetector {
+            model: model.clone(),
+        };
+        adult.annotate(&mut d);
+        let expected = 10f32.powf(1.3 / 3.0);
+        assert!((d.metadata().harmful_pp().unwrap() - expected).abs() < 1e-4);
+
+        // two foo lines: 2 * -0.3 over 4 tokens
+        let mut d = doc("foo\n\nfoo");
+        NgramQualityModel { model }.annotate(&mut d);
+        let expected = 10f32.powf(0.6 / 4.0);
+        let pp = d.score(PERPLEXITY_SCORE).unwrap() as f32;
+        assert!((pp - expected).abs() < 1e-2);
+    }
+}
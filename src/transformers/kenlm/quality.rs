@@ -0,0 +1,118 @@
+//! Perplexity-based quality scoring.
+//!
+//! Unlike [super::AdultDetector] that scores the whole document as a single sentence,
+//! [QualityModel] scores each line as a sentence, and computes the document perplexity from line scores
+//! (see [crate::filtering::perplexity]).
+use std::path::{Path, PathBuf};
+
+use ctclib_pp::{Dict, KenLM};
+use log::debug;
+
+use crate::{
+    filtering::perplexity::{self, PERPLEXITY_SCORE},
+    pipelines::oscardoc::types::Document,
+    transformers::Annotate,
+};
+
+pub struct QualityModelBuilder {
+    path: PathBuf,
+}
+
+impl QualityModelBuilder {
+    pub fn new(path: PathBuf) -> QualityModelBuilder {
+        debug!("New builder: {:?}", path);
+        Self { path }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+    pub fn set_path(&mut self, path: &Path) {
+        self.path = path.to_path_buf()
+    }
+    pub fn build(&self) -> Result<QualityModel, std::io::Error> {
+        debug!("Building new KenLM from path {:?}", self.path);
+        if !self.path.exists() {
+            Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("{:?} not found.", self.path),
+            ))
+        } else {
+            QualityModel::new(&self.path)
+        }
+    }
+}
+
+/// Language model trained on "quality" content (ex. Wikipedia) for a given language.
+pub struct QualityModel {
+    kenlm: KenLM,
+}
+
+impl QualityModel {
+    pub fn new(model_path: &Path) -> Result<Self, std::io::Error> {
+        Ok(Self {
+            kenlm: KenLM::new(model_path, &Dict::new())?,
+        })
+    }
+
+    /// Get the `(log10 probability, number of tokens)` of each non-blank line.
+    ///
+    /// The end of sentence is counted as a token.
+    pub fn line_scores(&self, content: &str) -> Vec<(f32, usize)> {
+        content
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                (
+                    self.kenlm.sentence_score(line),
+                    line.split_whitespace().count() + 1,
+                )
+            })
+            .collect()
+    }
+
+    /// Get the perplexity of each non-blank line.
+    pub fn line_perplexities(&self, content: &str) -> Vec<f32> {
+        self.line_scores(content)
+            .into_iter()
+            .map(|(score, nb_tokens)| perplexity::perplexity(score, nb_tokens))
+            .collect()
+    }
+
+    /// Get the perplexity of the content. Returns [None] if content is blank.
+    pub fn perplexity(&self, content: &str) -> Option<f32> {
+        perplexity::doc_perplexity(&self.line_scores(content))
+    }
+}
+
+/// Adds the document perplexity in the `score:perplexity` field.
+impl Annotate<Document> for QualityModel {
+    fn annotate(&self, doc: &mut Document) {
+        if let Some(perplexity) = self.perplexity(doc.content()) {
+            doc.add_score(PERPLEXITY_SCORE, perplexity as f64);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::QualityModelBuilder;
+
+    #[test]
+    fn test_nonexisting() {
+        let qmb = QualityModelBuilder::new(PathBuf::from("fezlfzej"));
+        assert!(qmb.build().is_err());
+    }
+
+    #[test]
+    fn test_perplexity() {
+        let qmb = QualityModelBuilder::new(PathBuf::from("res/kenlm/en.arpa"));
+        let model = qmb.build().unwrap();
+
+        assert_eq!(model.perplexity("\n  \n"), None);
+        assert_eq!(model.line_perplexities("foo bar\n\nbaz").len(), 2);
+        assert!(model.perplexity("foo bar\nbaz").is_some());
+    }
+}
@@ -0,0 +1,277 @@
+/*! Language splitting of mixed documents
+
+Documents that are not multilingual can still hold big blocks in another language.
+[LanguageSplit] uses line-level identifications to find contiguous runs of lines identified as another language,
+and builds child documents from them.
+
+Lines of child documents are removed from the parent document, that keeps its identification,
+so that no line ends up in two documents.
+!*/
+use std::ops::RangeInclusive;
+
+use oxilangtag::LanguageTag;
+
+use crate::pipelines::oscardoc::types::{Document, Metadata};
+use crate::transformers::Edits;
+use oscar_io::common::Identification;
+
+/// Splits blocks of lines in another language into separate documents.
+///
+/// A run is a sequence of lines identified in the same language (different from the document one),
+/// possibly containing unidentified lines, but starting and ending with identified ones.
+/// Runs smaller than `min_bytes` are ignored.
+pub struct LanguageSplit {
+    min_bytes: usize,
+}
+
+impl LanguageSplit {
+    /// Create a new [LanguageSplit], keeping runs that are at least `min_bytes` long.
+    pub fn new(min_bytes: usize) -> Self {
+        Self { min_bytes }
+    }
+
+    /// Find runs of lines identified in another language.
+    ///
+    /// Returns the language of each run along with its line range (inclusive).
+    fn runs(&self, doc: &Document) -> Vec<(LanguageTag<String>, RangeInclusive<usize>)> {
+        let doc_lang = doc.identification().label();
+        let ids = doc.metadata().sentence_identifications();
+        let line_sizes: Vec<usize> = doc.content().lines().map(str::len).collect();
+
+        let mut runs = Vec::new();
+
+        // current run: (language, start, last identified line)
+        let mut current: Option<(&LanguageTag<String>, usize, usize)> = None;
+
+        for (idx, id) in ids.iter().enumerate() {
+            let lang = id.as_ref().map(|id| id.label());
+            current = match (current, lang) {
+                // unidentified lines do not break runs
+                (current, None) => current,
+                (Some((run_lang, start, _)), Some(lang)) if run_lang == lang => {
+                    Some((run_lang, start, idx))
+                }
+                (previous, Some(lang)) => {
+                    if let Some((run_lang, start, end)) = previous {
+                        runs.push((run_lang, start..=end));
+                    }
+                    if lang == doc_lang {
+                        None
+                    } else {
+                        Some((lang, idx, idx))
+                    }
+                }
+            };
+        }
+
+        if let Some((run_lang, start, end)) = current {
+            runs.push((run_lang, start..=end));
+        }
+
+        runs.into_iter()
+            .filter(|(_, range)| {
+                line_sizes
+                    .get(range.clone())
+                    .map(|sizes| sizes.iter().sum::<usize>() >= self.min_bytes)
+                    .unwrap_or(false)
+            })
+            .map(|(lang, range)| (lang.clone(), range))
+            .collect()
+    }
+
+    /// Build child documents from runs of lines in another language, and remove their lines from `doc`.
+    ///
+    /// Returns the [Edits] made on `doc` ([None] if it is left untouched),
+    /// along with each child document and its line range (inclusive) in the original `doc`.
+    /// Multilingual documents are not split.
+    pub fn split(
+        &self,
+        doc: &mut Document,
+    ) -> (Option<Edits>, Vec<(Document, RangeInclusive<usize>)>) {
+        if doc.identification().label().as_str() == "multi" {
+            return (None, Vec::new());
+        }
+
+        let children = self.children(doc);
+        if children.is_empty() {
+            return (None, children);
+        }
+
+        // keep lines that are not in a child document
+        let nb_lines = doc.content().lines().count();
+        let mut kept = Vec::new();
+        let mut start = 0;
+        for (_, range) in &children {
+            if start < *range.start() {
+                kept.push(start..=range.start() - 1);
+            }
+            start = range.end() + 1;
+        }
+        if start < nb_lines {
+            kept.push(start..=nb_lines - 1);
+        }
+
+        let lines: Vec<&str> = doc.content().lines().collect();
+        let ids = doc.metadata().sentence_identifications();
+        let content = kept
+            .iter()
+            .flat_map(|range| lines[range.clone()].iter().copied())
+            .collect::<Vec<_>>()
+            .join("\n");
+        let kept_ids: Vec<_> = kept
+            .iter()
+            .flat_map(|range| ids[range.clone()].iter().cloned())
+            .collect();
+
+        let mut metadata = Metadata::new(doc.identification(), &kept_ids);
+        metadata.set_categories(doc.metadata().categories().cloned());
+        for annotation in doc.metadata().annotation().into_iter().flatten() {
+            metadata.add_annotation(annotation.clone());
+        }
+        doc.set_content(content);
+        *doc.metadata_mut() = metadata;
+
+        (Some(kept.into()), children)
+    }
+
+    /// Build child documents from runs of lines in another language.
+    fn children(&self, doc: &Document) -> Vec<(Document, RangeInclusive<usize>)> {
+        let lines: Vec<&str> = doc.content().lines().collect();
+        let ids = doc.metadata().sentence_identifications();
+
+        self.runs(doc)
+            .into_iter()
+            .map(|(lang, range)| {
+                let content = lines[range.clone()].join("\n");
+                let run_ids = &ids[range.clone()];
+
+                // confidence is the byte-weighted mean of identified lines confidences
+                let (bytes, bytes_times_prob) = lines[range.clone()]
+                    .iter()
+                    .zip(run_ids)
+                    .filter_map(|(line, id)| id.as_ref().map(|id| (line.len(), id.prob())))
+                    .fold((0, 0.0f32), |(bytes, acc), (len, prob)| {
+                        (bytes + len, acc + len as f32 * prob)
+                    });
+                let confidence = if bytes == 0 {
+                    0.0
+                } else {
+                    bytes_times_prob / bytes as f32
+                };
+
+                let metadata = Metadata::new(&Identification::new(lang, confidence), run_ids);
+                let mut child = Document::new(content, doc.warc_headers().clone(), metadata);
+                child.add_field("split_from", doc.identification().label().as_str());
+                (child, range)
+            })
+            .collect()
+    }
+}
+
+impl Default for LanguageSplit {
+    /// Runs must be at least 500 bytes long.
+    fn default() -> Self {
+        Self { min_bytes: 500 }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use oscar_io::common::Identification;
+    use oxilangtag::LanguageTag;
+
+    use crate::pipelines::oscardoc::types::{Document, Metadata};
+
+    use super::LanguageSplit;
+
+    fn id(lang: &str) -> Option<Identification<String>> {
+        Some(Identification::new(
+            LanguageTag::parse(lang.to_string()).unwrap(),
+            1.0,
+        ))
+    }
+
+    fn gen_doc(lines: &[(&str, Option<Identification<String>>)]) -> Document {
+        let content = lines
+            .iter()
+            .map(|(line, _)| *line)
+            .collect::<Vec<_>>()
+            .join("\n");
+        let ids: Vec<_> = lines.iter().map(|(_, id)| id.clone()).collect();
+        let metadata = Metadata::new(&id("en").unwrap(), &ids);
+        Document::new(content, HashMap::new(), metadata)
+    }
+
+    #[test]
+    fn test_split() {
+        let mut doc = gen_doc(&[
+            ("english line", id("en")),
+            ("english line", id("en")),
+            ("ligne française", id("fr")),
+            ("", None),
+            ("ligne française", id("fr")),
+            ("english line", id("en")),
+        ]);
+
+        let ls = LanguageSplit::new(10);
+        let (edits, children) = ls.split(&mut doc);
+        assert_eq!(children.len(), 1);
+
+        let (child, range) = &children[0];
+        assert_eq!(range, &(2..=4));
+        assert_eq!(child.content(), "ligne française\n\nligne française");
+        assert_eq!(child.identification().label().as_str(), "fr");
+        assert_eq!(child.identification().prob(), &1.0);
+        assert_eq!(child.field("split_from"), Some(&"en".into()));
+
+        // child lines are removed from the parent
+        assert_eq!(edits.unwrap().lines(), &[0..=1, 5..=5]);
+        assert_eq!(doc.content(), "english line\nenglish line\nenglish line");
+        assert_eq!(doc.identification().label().as_str(), "en");
+        assert_eq!(
+            doc.metadata().sentence_identifications(),
+            &[id("en"), id("en"), id("en")]
+        );
+    }
+
+    #[test]
+    fn test_split_at_end() {
+        let mut doc = gen_doc(&[
+            ("english line", id("en")),
+            ("ligne française", id("fr")),
+            ("linea italiana", id("it")),
+        ]);
+
+        let ls = LanguageSplit::new(10);
+        let (edits, children) = ls.split(&mut doc);
+        let ranges: Vec<_> = children.into_iter().map(|(_, r)| r).collect();
+        assert_eq!(ranges, vec![1..=1, 2..=2]);
+        assert_eq!(edits.unwrap().lines(), &[0..=0]);
+        assert_eq!(doc.content(), "english line");
+    }
+
+    #[test]
+    fn test_too_small() {
+        let mut doc = gen_doc(&[
+            ("english line", id("en")),
+            ("ligne française", id("fr")),
+            ("english line", id("en")),
+        ]);
+
+        let ls = LanguageSplit::default();
+        let (edits, children) = ls.split(&mut doc);
+        assert!(edits.is_none());
+        assert!(children.is_empty());
+        assert_eq!(doc.content().lines().count(), 3);
+    }
+
+    #[test]
+    fn test_monolingual() {
+        let mut doc = gen_doc(&[("english line", id("en")), ("", None)]);
+
+        let ls = LanguageSplit::new(0);
+        assert_eq!(ls.split(&mut doc), (None, Vec::new()));
+    }
+}
@@ -4,36 +4,72 @@ Transformers can either (or both) [Annotate] content or [Transform] it:
 
 - The [Annotate] trait only adds an annotation (see [crate::pipelines::oscardoc::types::Metadata]), without altering any content,
 - The [Transform] trait can change the content (and shouldn't add any annotation?).
-  It (for now) should only remove sentences without altering them.
+  It removes lines and/or replaces spans of lines, and reports those changes as [Edits]
+  so that they can be replayed at rebuild time.
 !*/
 
 mod annotate;
+mod boilerplate;
+mod content_classifier;
 mod content_detector;
+mod edit;
 mod header;
+mod language_split;
 
 mod lsh;
 mod noisy;
+mod pii;
+mod quality;
 
-#[cfg(feature = "kenlm")]
 mod kenlm;
 
 mod sentence_filter;
 mod tiny;
 mod transform;
+mod unicode;
+mod url_metadata;
 pub use annotate::Annotate;
 pub use annotate::Annotator;
+pub use boilerplate::Boilerplate;
+pub use boilerplate::BoilerplateMode;
+pub use content_classifier::ContentClassifier;
+pub use content_detector::Allowlist;
 pub use content_detector::ContentDetector;
+pub use edit::Edit;
+pub use edit::Edits;
 pub use header::Header;
-pub use lsh::LSH;
 #[cfg(feature = "kenlm")]
 pub use kenlm::AdultDetector;
 #[cfg(feature = "kenlm")]
 pub use kenlm::AdultDetectorBuilder;
-#[cfg(feature = "kenlm")]
+pub use kenlm::Capacity;
+pub use kenlm::ModelBuilder;
 pub use kenlm::Models;
+pub use kenlm::NgramAdultDetectorBuilder;
+pub use kenlm::NgramQualityModelBuilder;
+#[cfg(feature = "kenlm")]
+pub use kenlm::QualityModel;
+#[cfg(feature = "kenlm")]
+pub use kenlm::QualityModelBuilder;
+pub use language_split::LanguageSplit;
+pub use lsh::LSH;
 pub use noisy::Noisy;
+pub use pii::Pii;
+pub use pii::PiiMode;
+pub use quality::Bullets;
+pub use quality::Duplicates;
+pub use quality::Ellipsis;
+pub use quality::Markers;
+pub use quality::NgramRepetition;
+pub use quality::QualityThresholds;
+pub use quality::SymbolRatio;
+pub use quality::TerminalPunctuation;
 pub use sentence_filter::Conv;
+pub use sentence_filter::LengthOutliers;
 pub use sentence_filter::RemoveShortSentences;
 pub use sentence_filter::ShortSentences;
+pub use sentence_filter::TrimmingKind;
 pub use tiny::TinyDocument;
 pub use transform::Transform;
+pub use unicode::UnicodeRepair;
+pub use url_metadata::UrlMetadata;
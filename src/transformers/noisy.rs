@@ -9,11 +9,27 @@ use super::Annotate;
 use crate::pipelines::oscardoc::types::Document;
 pub struct Noisy {
     threshold: f64,
+    scores: bool,
+}
+
+impl Noisy {
+    /// Also record the letter ratio in the `score:letter_ratio` field.
+    pub fn set_scores(&mut self, scores: bool) {
+        self.scores = scores;
+    }
+
+    fn is_letter(c: char) -> bool {
+        let gc = GeneralCategory::of(c);
+        gc.is_letter() || gc.is_mark()
+    }
 }
 
 impl Default for Noisy {
     fn default() -> Self {
-        Self { threshold: 0.5 }
+        Self {
+            threshold: 0.5,
+            scores: false,
+        }
     }
 }
 impl Annotate<Document> for Noisy {
@@ -23,11 +39,27 @@ impl Annotate<Document> for Noisy {
         let nb_chars = doc.content().chars().count();
         let threshold = (nb_chars as f64 * self.threshold).floor() as usize;
 
-        let letters = doc.content().chars().map(|c| {
-            let gc = GeneralCategory::of(c);
+        // scores need a full count, so there's no early stop.
+        if self.scores {
+            let letter_count = doc
+                .content()
+                .chars()
+                .filter(|c| Self::is_letter(*c))
+                .count();
+            if nb_chars - letter_count > threshold {
+                doc.metadata_mut().add_annotation("noisy".to_string());
+            }
 
-            gc.is_letter() || gc.is_mark()
-        });
+            let letter_ratio = if nb_chars == 0 {
+                0.0
+            } else {
+                letter_count as f64 / nb_chars as f64
+            };
+            doc.add_score("letter_ratio", letter_ratio);
+            return;
+        }
+
+        let letters = doc.content().chars().map(Self::is_letter);
 
         let mut nonletter_count = 0;
         let mut letter_count = 0;
@@ -127,4 +159,27 @@ mod tests {
 
         assert!(d.metadata().annotation().is_none())
     }
+
+    #[test]
+    fn test_scores() {
+        let mut a = Noisy::default();
+        a.set_scores(true);
+
+        let content = "/a//a////a////a/a//a////a///a/a//a/".to_string();
+        let mut d = Document::new(content, HashMap::new(), Metadata::default());
+        a.annotate(&mut d);
+        assert!(d
+            .metadata()
+            .annotation()
+            .unwrap()
+            .contains(&"noisy".to_string()));
+        assert_eq!(d.score("letter_ratio"), Some(0.2857));
+
+        let content = "Lorem ipsum dolor sit amet".to_string();
+        let mut d = Document::new(content, HashMap::new(), Metadata::default());
+        a.annotate(&mut d);
+        assert_eq!(d.fields().len(), 1);
+        assert_eq!(d.score("letter_ratio"), Some(0.8462));
+        assert!(d.metadata().annotation().is_none());
+    }
 }
@@ -0,0 +1,692 @@
+/*! Heuristic quality annotators
+
+Annotators inspired by the quality filters of Gopher (Rae et al., 2021) and C4 (Raffel et al., 2020).
+Each of them adds a quality warning when its heuristic is triggered:
+
+- [TerminalPunctuation]: too few lines end in terminal punctuation (`no_terminal_punctuation`),
+- [Bullets]: too many lines start with a bullet (`bullets`),
+- [Ellipsis]: too many lines end with an ellipsis (`ellipsis`),
+- [SymbolRatio]: too many `#`/ellipsis symbols per word (`symbols`),
+- [Duplicates]: too many duplicate lines (`duplicate_lines`) or paragraphs (`duplicate_paragraphs`),
+- [NgramRepetition]: too much content in repeated word n-grams (`ngram_repetition`),
+- [Markers]: presence of boilerplate markers (`lorem_ipsum`, `javascript`, `cookie_banner`).
+
+Thresholds can be set from a JSON file (see [QualityThresholds]). Missing thresholds keep their default value:
+
+```json
+{
+  "terminal_punctuation": 0.1,
+  "duplicate_items": 0.5,
+  "ngram_top": [[2, 0.25], [3, 0.2]],
+  "markers": [["lorem_ipsum", ["lorem ipsum", "dolor sit amet"]]]
+}
+```
+!*/
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::path::Path;
+
+use log::debug;
+use serde::{Deserialize, Serialize};
+use unicode_segmentation::UnicodeSegmentation;
+
+use super::Annotate;
+use crate::error::Error;
+use crate::pipelines::oscardoc::types::Document;
+
+const TERMINAL_PUNCTUATION: [char; 11] =
+    ['.', '!', '?', '"', '\'', '。', '！', '？', '」', '”', '।'];
+const BULLETS: [char; 6] = ['•', '●', '○', '▪', '‣', '◦'];
+const ELLIPSIS: [&str; 2] = ["...", "…"];
+
+/// Get non-empty lines of a document, trimmed.
+fn non_empty_lines(doc: &Document) -> Vec<&str> {
+    doc.content()
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect()
+}
+
+/// Get the share of items that match `predicate`, or `None` if there are no items.
+fn share<T>(items: &[T], predicate: impl Fn(&T) -> bool) -> Option<f64> {
+    if items.is_empty() {
+        None
+    } else {
+        Some(items.iter().filter(|item| predicate(item)).count() as f64 / items.len() as f64)
+    }
+}
+
+/// Flags documents where less than `min_ratio` of the lines end in terminal punctuation.
+pub struct TerminalPunctuation {
+    min_ratio: f64,
+}
+
+impl TerminalPunctuation {
+    pub fn new(min_ratio: f64) -> Self {
+        Self { min_ratio }
+    }
+}
+
+impl Default for TerminalPunctuation {
+    /// At least 20% of lines have to end in terminal punctuation.
+    fn default() -> Self {
+        Self { min_ratio: 0.2 }
+    }
+}
+
+impl Annotate<Document> for TerminalPunctuation {
+    fn annotate(&self, doc: &mut Document) {
+        let lines = non_empty_lines(doc);
+        let ratio = share(&lines, |line| line.ends_with(&TERMINAL_PUNCTUATION[..]));
+
+        if matches!(ratio, Some(ratio) if ratio < self.min_ratio) {
+            debug!("record {} flagged for terminal punctuation", doc.warc_id());
+            doc.metadata_mut()
+                .add_annotation("no_terminal_punctuation".to_string());
+        }
+    }
+}
+
+/// Flags documents where more than `max_ratio` of the lines start with a bullet (`•`, `-`, `*`...).
+pub struct Bullets {
+    max_ratio: f64,
+}
+
+impl Bullets {
+    pub fn new(max_ratio: f64) -> Self {
+        Self { max_ratio }
+    }
+
+    fn is_bullet(line: &str) -> bool {
+        line.starts_with(&BULLETS[..])
+            || ["- ", "* ", "+ "]
+                .iter()
+                .any(|bullet| line.starts_with(bullet))
+    }
+}
+
+impl Default for Bullets {
+    /// At most 90% of lines can start with a bullet.
+    fn default() -> Self {
+        Self { max_ratio: 0.9 }
+    }
+}
+
+impl Annotate<Document> for Bullets {
+    fn annotate(&self, doc: &mut Document) {
+        let lines = non_empty_lines(doc);
+        let ratio = share(&lines, |line| Self::is_bullet(line));
+
+        if matches!(ratio, Some(ratio) if ratio > self.max_ratio) {
+            debug!("record {} flagged for bullets", doc.warc_id());
+            doc.metadata_mut().add_annotation("bullets".to_string());
+        }
+    }
+}
+
+/// Flags documents where more than `max_ratio` of the lines end with an ellipsis (`...` or `…`).
+pub struct Ellipsis {
+    max_ratio: f64,
+}
+
+impl Ellipsis {
+    pub fn new(max_ratio: f64) -> Self {
+        Self { max_ratio }
+    }
+}
+
+impl Default for Ellipsis {
+    /// At most 30% of lines can end with an ellipsis.
+    fn default() -> Self {
+        Self { max_ratio: 0.3 }
+    }
+}
+
+impl Annotate<Document> for Ellipsis {
+    fn annotate(&self, doc: &mut Document) {
+        let lines = non_empty_lines(doc);
+        let ratio = share(&lines, |line| {
+            ELLIPSIS.iter().any(|ellipsis| line.ends_with(ellipsis))
+        });
+
+        if matches!(ratio, Some(ratio) if ratio > self.max_ratio) {
+            debug!("record {} flagged for ellipsis", doc.warc_id());
+            doc.metadata_mut().add_annotation("ellipsis".to_string());
+        }
+    }
+}
+
+/// Flags documents where the number of symbols (`#` and ellipsis) per word is above `max_ratio`.
+pub struct SymbolRatio {
+    max_ratio: f64,
+}
+
+impl SymbolRatio {
+    pub fn new(max_ratio: f64) -> Self {
+        Self { max_ratio }
+    }
+}
+
+impl Default for SymbolRatio {
+    /// At most 0.1 symbol per word.
+    fn default() -> Self {
+        Self { max_ratio: 0.1 }
+    }
+}
+
+impl Annotate<Document> for SymbolRatio {
+    fn annotate(&self, doc: &mut Document) {
+        let content = doc.content();
+        let nb_words = content.unicode_words().count();
+        let nb_symbols = content.matches('#').count()
+            + ELLIPSIS
+                .iter()
+                .map(|ellipsis| content.matches(ellipsis).count())
+                .sum::<usize>();
+
+        let flagged = match nb_words {
+            0 => nb_symbols > 0,
+            _ => nb_symbols as f64 / nb_words as f64 > self.max_ratio,
+        };
+
+        if flagged {
+            debug!("record {} flagged for symbols", doc.warc_id());
+            doc.metadata_mut().add_annotation("symbols".to_string());
+        }
+    }
+}
+
+/// Flags documents with too many duplicate lines or paragraphs (blocks separated by empty lines).
+///
+/// Duplicates are measured both in number of items and in number of characters:
+/// a document is flagged if either the share of duplicate items is above `max_items_ratio`
+/// or the share of characters in duplicate items is above `max_chars_ratio`.
+pub struct Duplicates {
+    max_items_ratio: f64,
+    max_chars_ratio: f64,
+}
+
+impl Duplicates {
+    pub fn new(max_items_ratio: f64, max_chars_ratio: f64) -> Self {
+        Self {
+            max_items_ratio,
+            max_chars_ratio,
+        }
+    }
+
+    /// Check whether `items` exceed duplicate thresholds.
+    /// The first occurrence of an item isn't counted as a duplicate.
+    fn is_duplicated(&self, items: &[&str]) -> bool {
+        if items.is_empty() {
+            return false;
+        }
+
+        let mut seen = HashSet::new();
+        let (mut nb_dup, mut dup_chars, mut total_chars) = (0, 0, 0);
+        for item in items {
+            let nb_chars = item.chars().count();
+            total_chars += nb_chars;
+            if !seen.insert(*item) {
+                nb_dup += 1;
+                dup_chars += nb_chars;
+            }
+        }
+
+        nb_dup as f64 / items.len() as f64 > self.max_items_ratio
+            || (total_chars > 0 && dup_chars as f64 / total_chars as f64 > self.max_chars_ratio)
+    }
+
+    /// Split content into paragraphs, that is blocks separated by empty lines.
+    fn paragraphs(content: &str) -> Vec<String> {
+        let mut paragraphs = Vec::new();
+        let mut current: Vec<&str> = Vec::new();
+        for line in content.lines().map(str::trim) {
+            if line.is_empty() {
+                if !current.is_empty() {
+                    paragraphs.push(current.join("\n"));
+                    current.clear();
+                }
+            } else {
+                current.push(line);
+            }
+        }
+        if !current.is_empty() {
+            paragraphs.push(current.join("\n"));
+        }
+        paragraphs
+    }
+}
+
+impl Default for Duplicates {
+    /// At most 30% of duplicate items, or 20% of characters in duplicate items.
+    fn default() -> Self {
+        Self {
+            max_items_ratio: 0.3,
+            max_chars_ratio: 0.2,
+        }
+    }
+}
+
+impl Annotate<Document> for Duplicates {
+    fn annotate(&self, doc: &mut Document) {
+        let duplicate_lines = self.is_duplicated(&non_empty_lines(doc));
+
+        let paragraphs = Self::paragraphs(doc.content());
+        let paragraphs: Vec<&str> = paragraphs.iter().map(String::as_str).collect();
+        let duplicate_paragraphs = self.is_duplicated(&paragraphs);
+
+        if duplicate_lines {
+            debug!("record {} flagged for duplicate lines", doc.warc_id());
+            doc.metadata_mut()
+                .add_annotation("duplicate_lines".to_string());
+        }
+        if duplicate_paragraphs {
+            debug!("record {} flagged for duplicate paragraphs", doc.warc_id());
+            doc.metadata_mut()
+                .add_annotation("duplicate_paragraphs".to_string());
+        }
+    }
+}
+
+/// Flags documents with too much content in repeated word n-grams.
+///
+/// Two measures are used (characters are counted in words):
+/// - for short n-grams, the share of characters in the most frequent n-gram (`top_thresholds`),
+/// - for long n-grams, the share of characters in n-grams that appear more than once (`dup_thresholds`).
+pub struct NgramRepetition {
+    top_thresholds: Vec<(usize, f64)>,
+    dup_thresholds: Vec<(usize, f64)>,
+}
+
+impl NgramRepetition {
+    /// `top_thresholds` and `dup_thresholds` are `(n, max ratio)` pairs.
+    pub fn new(top_thresholds: Vec<(usize, f64)>, dup_thresholds: Vec<(usize, f64)>) -> Self {
+        Self {
+            top_thresholds,
+            dup_thresholds,
+        }
+    }
+
+    /// Share of characters in the most frequent n-gram.
+    fn top_ngram_ratio(words: &[String], n: usize, total_chars: usize) -> f64 {
+        let mut counts: HashMap<&[String], usize> = HashMap::new();
+        for ngram in words.windows(n) {
+            *counts.entry(ngram).or_insert(0) += 1;
+        }
+
+        counts
+            .into_iter()
+            .filter(|(_, count)| *count > 1)
+            .map(|(ngram, count)| count * ngram.iter().map(|w| w.chars().count()).sum::<usize>())
+            .max()
+            .map(|chars| chars as f64 / total_chars as f64)
+            .unwrap_or(0.0)
+    }
+
+    /// Share of characters in words that are part of an n-gram appearing more than once.
+    fn dup_ngram_ratio(words: &[String], n: usize, total_chars: usize) -> f64 {
+        let mut positions: HashMap<&[String], Vec<usize>> = HashMap::new();
+        for (idx, ngram) in words.windows(n).enumerate() {
+            positions.entry(ngram).or_default().push(idx);
+        }
+
+        let mut covered = vec![false; words.len()];
+        for starts in positions.values().filter(|starts| starts.len() > 1) {
+            for start in starts {
+                covered[*start..*start + n]
+                    .iter_mut()
+                    .for_each(|c| *c = true);
+            }
+        }
+
+        let dup_chars: usize = words
+            .iter()
+            .zip(covered)
+            .filter(|(_, covered)| *covered)
+            .map(|(word, _)| word.chars().count())
+            .sum();
+
+        dup_chars as f64 / total_chars as f64
+    }
+}
+
+impl Default for NgramRepetition {
+    /// Gopher thresholds:
+    /// - top 2/3/4-grams: 20%/18%/16% of characters,
+    /// - duplicate 5 to 10-grams: from 15% to 10% of characters.
+    fn default() -> Self {
+        Self {
+            top_thresholds: vec![(2, 0.20), (3, 0.18), (4, 0.16)],
+            dup_thresholds: vec![
+                (5, 0.15),
+                (6, 0.14),
+                (7, 0.13),
+                (8, 0.12),
+                (9, 0.11),
+                (10, 0.10),
+            ],
+        }
+    }
+}
+
+impl Annotate<Document> for NgramRepetition {
+    fn annotate(&self, doc: &mut Document) {
+        let words: Vec<String> = doc
+            .content()
+            .unicode_words()
+            .map(str::to_lowercase)
+            .collect();
+        let total_chars: usize = words.iter().map(|w| w.chars().count()).sum();
+        if total_chars == 0 {
+            return;
+        }
+
+        let flagged = self
+            .top_thresholds
+            .iter()
+            .any(|(n, max)| Self::top_ngram_ratio(&words, *n, total_chars) > *max)
+            || self
+                .dup_thresholds
+                .iter()
+                .any(|(n, max)| Self::dup_ngram_ratio(&words, *n, total_chars) > *max);
+
+        if flagged {
+            debug!("record {} flagged for ngram repetition", doc.warc_id());
+            doc.metadata_mut()
+                .add_annotation("ngram_repetition".to_string());
+        }
+    }
+}
+
+/// Flags documents containing boilerplate markers.
+///
+/// Markers are lowercase patterns, grouped by annotation.
+/// Matching is case-insensitive.
+pub struct Markers {
+    markers: Vec<(String, Vec<String>)>,
+}
+
+impl Markers {
+    /// `markers` are `(annotation, patterns)` pairs. Patterns are lowercased.
+    pub fn new(markers: Vec<(String, Vec<String>)>) -> Self {
+        Self {
+            markers: markers
+                .into_iter()
+                .map(|(annotation, patterns)| {
+                    let patterns = patterns.iter().map(|p| p.to_lowercase()).collect();
+                    (annotation, patterns)
+                })
+                .collect(),
+        }
+    }
+}
+
+impl Default for Markers {
+    /// C4 markers: `lorem ipsum`, `javascript`, and cookie banners.
+    fn default() -> Self {
+        let markers = [
+            ("lorem_ipsum", vec!["lorem ipsum"]),
+            ("javascript", vec!["javascript"]),
+            (
+                "cookie_banner",
+                vec![
+                    "use cookies",
+                    "use of cookies",
+                    "uses cookies",
+                    "accept cookies",
+                    "cookie policy",
+                ],
+            ),
+        ];
+
+        Self {
+            markers: markers
+                .into_iter()
+                .map(|(annotation, patterns)| {
+                    (
+                        annotation.to_string(),
+                        patterns.into_iter().map(String::from).collect(),
+                    )
+                })
+                .collect(),
+        }
+    }
+}
+
+impl Annotate<Document> for Markers {
+    fn annotate(&self, doc: &mut Document) {
+        let content = doc.content().to_lowercase();
+        let annotations: Vec<String> = self
+            .markers
+            .iter()
+            .filter(|(_, patterns)| patterns.iter().any(|p| content.contains(p.as_str())))
+            .map(|(annotation, _)| annotation.clone())
+            .collect();
+
+        for annotation in annotations {
+            debug!("record {} flagged for {}", doc.warc_id(), annotation);
+            doc.metadata_mut().add_annotation(annotation);
+        }
+    }
+}
+
+/// Thresholds of the heuristic quality annotators (see [module](self) documentation).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QualityThresholds {
+    /// minimum share of lines ending in terminal punctuation (see [TerminalPunctuation])
+    pub terminal_punctuation: f64,
+    /// maximum share of lines starting with a bullet (see [Bullets])
+    pub bullets: f64,
+    /// maximum share of lines ending with an ellipsis (see [Ellipsis])
+    pub ellipsis: f64,
+    /// maximum number of symbols per word (see [SymbolRatio])
+    pub symbols: f64,
+    /// maximum share of duplicate lines/paragraphs (see [Duplicates])
+    pub duplicate_items: f64,
+    /// maximum share of characters in duplicate lines/paragraphs (see [Duplicates])
+    pub duplicate_chars: f64,
+    /// `(n, max ratio)` pairs for the most frequent n-gram (see [NgramRepetition])
+    pub ngram_top: Vec<(usize, f64)>,
+    /// `(n, max ratio)` pairs for duplicate n-grams (see [NgramRepetition])
+    pub ngram_dup: Vec<(usize, f64)>,
+    /// `(annotation, patterns)` pairs (see [Markers])
+    pub markers: Vec<(String, Vec<String>)>,
+}
+
+impl QualityThresholds {
+    /// Load thresholds from a JSON file.
+    pub fn from_path(path: &Path) -> Result<Self, Error> {
+        let f = File::open(path)?;
+        Ok(serde_json::from_reader(f)?)
+    }
+}
+
+impl Default for QualityThresholds {
+    /// Default thresholds of each annotator.
+    fn default() -> Self {
+        let duplicates = Duplicates::default();
+        let ngram_repetition = NgramRepetition::default();
+        Self {
+            terminal_punctuation: TerminalPunctuation::default().min_ratio,
+            bullets: Bullets::default().max_ratio,
+            ellipsis: Ellipsis::default().max_ratio,
+            symbols: SymbolRatio::default().max_ratio,
+            duplicate_items: duplicates.max_items_ratio,
+            duplicate_chars: duplicates.max_chars_ratio,
+            ngram_top: ngram_repetition.top_thresholds,
+            ngram_dup: ngram_repetition.dup_thresholds,
+            markers: Markers::default().markers,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::{
+        pipelines::oscardoc::types::{Document, Metadata},
+        transformers::Annotate,
+    };
+
+    use super::{
+        Bullets, Duplicates, Ellipsis, Markers, NgramRepetition, QualityThresholds, SymbolRatio,
+        TerminalPunctuation,
+    };
+
+    fn annotate(annotator: &impl Annotate<Document>, content: &str) -> Option<Vec<String>> {
+        let mut d = Document::new(content.to_string(), HashMap::new(), Metadata::default());
+        annotator.annotate(&mut d);
+        d.metadata().annotation().cloned()
+    }
+
+    const CLEAN: &str = "Lorem dolor sit amet, consectetur adipiscing elit.
+Nam tempor magna ac justo sollicitudin, eu posuere purus sollicitudin.
+Aliquam erat volutpat.
+Duis dui ipsum, lacinia at ornare vitae, fringilla eu lorem.
+Aenean nec justo neque.";
+
+    #[test]
+    fn test_terminal_punctuation() {
+        let a = TerminalPunctuation::default();
+        assert_eq!(annotate(&a, CLEAN), None);
+
+        let content = "Home\nAbout us\nContact\nLogin\nThis one ends well.\nFAQ";
+        assert_eq!(
+            annotate(&a, content),
+            Some(vec!["no_terminal_punctuation".to_string()])
+        );
+
+        // threshold is configurable
+        let a = TerminalPunctuation::new(0.1);
+        assert_eq!(annotate(&a, content), None);
+    }
+
+    #[test]
+    fn test_bullets() {
+        let a = Bullets::default();
+        assert_eq!(annotate(&a, CLEAN), None);
+
+        let content = "• first\n• second\n- third\n* fourth";
+        assert_eq!(annotate(&a, content), Some(vec!["bullets".to_string()]));
+
+        let content = "Intro line\n• first\n• second\n• third";
+        assert_eq!(annotate(&a, content), None);
+        assert_eq!(
+            annotate(&Bullets::new(0.5), content),
+            Some(vec!["bullets".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_ellipsis() {
+        let a = Ellipsis::default();
+        assert_eq!(annotate(&a, CLEAN), None);
+
+        let content = "Read more...\nContinue reading…\nA full sentence.";
+        assert_eq!(annotate(&a, content), Some(vec!["ellipsis".to_string()]));
+    }
+
+    #[test]
+    fn test_symbol_ratio() {
+        let a = SymbolRatio::default();
+        assert_eq!(annotate(&a, CLEAN), None);
+
+        let content = "#love #summer #beach #sun and more";
+        assert_eq!(annotate(&a, content), Some(vec!["symbols".to_string()]));
+        assert_eq!(annotate(&a, "###"), Some(vec!["symbols".to_string()]));
+        assert_eq!(annotate(&a, ""), None);
+    }
+
+    #[test]
+    fn test_duplicate_lines() {
+        let a = Duplicates::default();
+        assert_eq!(annotate(&a, CLEAN), None);
+
+        let content = "Share this\nA real line of content here.\nShare this\nShare this";
+        assert_eq!(
+            annotate(&a, content),
+            Some(vec!["duplicate_lines".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_duplicate_paragraphs() {
+        let a = Duplicates::new(0.9, 0.9);
+        let content = "First paragraph\nsecond line\n\nFirst paragraph\nsecond line\n\nOther paragraph\nwith other lines\nthat are different";
+        // 2/7 lines and 1/3 paragraphs are duplicates, but thresholds are high
+        assert_eq!(annotate(&a, content), None);
+
+        let a = Duplicates::new(0.3, 0.9);
+        assert_eq!(
+            annotate(&a, content),
+            Some(vec!["duplicate_paragraphs".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_ngram_repetition() {
+        let a = NgramRepetition::default();
+        assert_eq!(annotate(&a, CLEAN), None);
+
+        let content = "buy now buy now buy now buy now and get a free gift with your order today";
+        assert_eq!(
+            annotate(&a, content),
+            Some(vec!["ngram_repetition".to_string()])
+        );
+
+        let content = "the quick brown fox jumps over the lazy dog. Then the quick brown fox jumps over the lazy dog again";
+        assert_eq!(
+            annotate(&a, content),
+            Some(vec!["ngram_repetition".to_string()])
+        );
+        assert_eq!(
+            annotate(&NgramRepetition::new(vec![], vec![(10, 0.9)]), content),
+            None
+        );
+    }
+
+    #[test]
+    fn test_markers() {
+        let a = Markers::default();
+        assert_eq!(annotate(&a, CLEAN), None);
+
+        let content =
+            "Lorem ipsum dolor sit amet.\nThis website uses cookies.\nPlease enable JavaScript.";
+        assert_eq!(
+            annotate(&a, content),
+            Some(vec![
+                "lorem_ipsum".to_string(),
+                "javascript".to_string(),
+                "cookie_banner".to_string()
+            ])
+        );
+
+        let a = Markers::new(vec![("foo".to_string(), vec!["Bar".to_string()])]);
+        assert_eq!(annotate(&a, "Foo BAR baz"), Some(vec!["foo".to_string()]));
+    }
+
+    #[test]
+    fn test_thresholds() {
+        let thresholds: QualityThresholds =
+            serde_json::from_str(r#"{"bullets": 0.4, "markers": [["foo", ["bar"]]]}"#).unwrap();
+        assert_eq!(thresholds.bullets, 0.4);
+        assert_eq!(
+            thresholds.terminal_punctuation,
+            QualityThresholds::default().terminal_punctuation
+        );
+        assert_eq!(
+            thresholds.markers,
+            vec![("foo".to_string(), vec!["bar".to_string()])]
+        );
+
+        let content = "- Lorem dolor sit amet.\n- Aliquam erat volutpat.\nAenean nec justo neque.";
+        assert_eq!(annotate(&Bullets::default(), content), None);
+        assert_eq!(
+            annotate(&Bullets::new(thresholds.bullets), content),
+            Some(vec!["bullets".to_string()])
+        );
+    }
+}
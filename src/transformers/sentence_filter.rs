@@ -1,6 +1,7 @@
 //! Sentence transformers
 //!
 use std::ops::RangeInclusive;
+use std::str::FromStr;
 
 use itertools::Itertools;
 use log::debug;
@@ -8,20 +9,33 @@ use warc::BufferedBody;
 use warc::Record;
 
 use crate::{
-    filtering::{sentence::Length, Filter},
+    filtering::{
+        sentence::{Length, MeanLengths},
+        Filter,
+    },
     pipelines::oscardoc::types::Document,
 };
 
-use super::{Annotate, Transform};
+use super::{Annotate, Edits, Transform};
 
 pub struct ShortSentences {
     filter: Length,
     threshold: f32,
+    scores: bool,
 }
 
 impl ShortSentences {
     pub fn new(filter: Length, threshold: f32) -> Self {
-        Self { filter, threshold }
+        Self {
+            filter,
+            threshold,
+            scores: false,
+        }
+    }
+
+    /// Also record the short lines fraction in the `score:short_lines_ratio` field.
+    pub fn set_scores(&mut self, scores: bool) {
+        self.scores = scores;
     }
 }
 
@@ -44,6 +58,15 @@ impl Annotate<Document> for ShortSentences {
             doc.metadata_mut()
                 .add_annotation("short_sentences".to_string());
         }
+
+        if self.scores {
+            let ratio = if nb_lines == 0 {
+                0.0
+            } else {
+                nb_short_lines as f32 / nb_lines as f32
+            };
+            doc.add_score("short_lines_ratio", ratio as f64);
+        }
     }
 }
 impl Default for ShortSentences {
@@ -51,6 +74,59 @@ impl Default for ShortSentences {
         Self {
             filter: Default::default(),
             threshold: 0.5,
+            scores: false,
+        }
+    }
+}
+
+/// Flags lines whose length is an outlier for their language, using calibrated [MeanLengths].
+///
+/// Each line is checked against the distribution of its own identified language,
+/// falling back to the document language for unidentified lines.
+/// Distributions learnt on less than `min_measures` lines are not used.
+///
+/// The number of outlier lines is stored in the `length_outliers` field.
+pub struct LengthOutliers {
+    lengths: MeanLengths,
+    min_measures: u32,
+}
+
+impl LengthOutliers {
+    pub fn new(lengths: MeanLengths, min_measures: u32) -> Self {
+        Self {
+            lengths,
+            min_measures,
+        }
+    }
+}
+
+impl Annotate<Document> for LengthOutliers {
+    fn annotate(&self, doc: &mut Document) {
+        let doc_lang = doc.identification().label().as_str();
+        let ids = doc.metadata().sentence_identifications();
+
+        let outliers = doc
+            .content()
+            .lines()
+            .enumerate()
+            .filter(|(idx, line)| {
+                let lang = ids
+                    .get(*idx)
+                    .and_then(|id| id.as_ref())
+                    .map(|id| id.label().as_str())
+                    .unwrap_or(doc_lang);
+
+                self.lengths
+                    .get(lang)
+                    .filter(|length| length.nb_measures() >= self.min_measures)
+                    .map(|length| !length.detect(line))
+                    .unwrap_or(false)
+            })
+            .count();
+
+        if outliers > 0 {
+            debug!("record {} has length outliers", doc.warc_id());
+            doc.add_field("length_outliers", outliers);
         }
     }
 }
@@ -67,24 +143,46 @@ impl Default for ShortSentences {
 /// and then filter out.
 /// Basically, we convolve with a `[... 1/3 1/3 1/3 ...]` filter.
 /// We could try having filters that are more sensible at the start?
+///
+/// Line lengths are divided by their minimum length (see [Length::min_size_for]) before being convolved,
+/// so that script-aware thresholds are honored. Lines are then kept if their convolved length is above `1`.
+#[derive(Debug)]
 pub struct Conv {
     conv_size: usize,
     rss: RemoveShortSentences,
 }
 
 impl Conv {
-    pub fn new(conv_size: usize, rss: RemoveShortSentences) -> Self {
-        Self { conv_size, rss }
+    /// Create a new convolution-based remover.
+    ///
+    /// Windows are centred on each line, so `conv_size` must be odd (`1` meaning no convolution).
+    pub fn new(conv_size: usize, rss: RemoveShortSentences) -> Result<Self, String> {
+        if conv_size % 2 == 1 {
+            Ok(Self { conv_size, rss })
+        } else {
+            Err(format!(
+                "invalid convolution window size {conv_size}: windows are centred, so sizes must be odd"
+            ))
+        }
     }
 
-    /// transform document and return it, along with a vector containing the indices of kept sentences.
-    pub fn transform_idx(&self, mut doc: Document) -> (Document, Vec<RangeInclusive<usize>>) {
-        let lines: Vec<&str> = doc.content().lines().collect();
-        let line_lengths: Vec<f32> = lines.iter().map(|line| line.len() as f32).collect();
+    /// Get convolved line lengths, normalized by the minimum length of each line.
+    fn convolved_lengths(&self, lines: &[&str]) -> Vec<f32> {
+        let line_lengths: Vec<f32> = lines
+            .iter()
+            .map(|line| {
+                let min_size = self.rss.filter.min_size_for(line).max(1);
+                self.rss.filter.length(line) as f32 / min_size as f32
+            })
+            .collect();
+
         //add padding
+        let (padding_val_start, padding_val_end) = match (line_lengths.first(), line_lengths.last())
+        {
+            (Some(start), Some(end)) => (*start, *end),
+            _ => return Vec::new(),
+        };
         let padding_size = self.conv_size.div_euclid(2);
-        let padding_val_start = *line_lengths.first().unwrap();
-        let padding_val_end = *line_lengths.last().unwrap();
         let line_lengths = [
             vec![padding_val_start; padding_size],
             line_lengths,
@@ -94,47 +192,55 @@ impl Conv {
         //end add padding
 
         // convolve
-        let convolved_lengths: Vec<f32> = line_lengths
+        line_lengths
             .windows(self.conv_size)
             .map(|lengths| lengths.iter().sum::<f32>() / self.conv_size as f32)
-            .collect();
+            .collect()
+    }
 
-        // filter/remove lines
-        // this iterator contains (line_index, (line, convolved_length)).
-        // let i: Vec<(usize, (&&str, &f32))> = lines
-        let i = lines.iter().zip(convolved_lengths.iter()).enumerate();
+    /// extracts indices of the document content, ignoring lines at start/end whose convolved length is too short.
+    ///
+    /// Same output as [RemoveShortSentences::extract_indices] (kept lines, in reverse order).
+    fn extract_indices<'a>(&self, lines: std::str::Lines<'a>) -> Vec<(usize, &'a str)> {
+        let lines: Vec<&str> = lines.collect();
+        let convolved_lengths = self.convolved_lengths(&lines);
 
-        let min_length = *self.rss.filter_min_length() as f32;
+        // this iterator contains (line_index, (line, convolved_length)).
+        let i = lines.into_iter().zip(convolved_lengths).enumerate();
 
         // skip beginning sentences
         let i: Vec<_> = i
-            .skip_while(|(_, (_, convolved_length))| convolved_length < &&min_length)
+            .skip_while(|(_, (_, convolved_length))| *convolved_length <= 1.0)
             .collect();
 
         // skip ending sentences
-        let i: Vec<_> = i
-            .into_iter()
+        i.into_iter()
             .rev()
-            .skip_while(|(_, (_, convolved_length))| convolved_length < &&min_length)
-            .collect();
+            .skip_while(|(_, (_, convolved_length))| *convolved_length <= 1.0)
+            .map(|(idx, (line, _))| (idx, line))
+            .collect()
+    }
+}
 
-        // ensure that there's enough to keep
-        // if not, return the intact document and empty ranges.
-        // TODO: ensure that it can't be processed further down?
-        match (i.first(), i.last()) {
-            (Some(end), Some(start)) => {
-                let ranges = vec![start.0..=end.0];
-                let sentences = i
-                    .into_iter()
-                    .rev()
-                    .map(|(_, (sentence, _))| sentence)
-                    .join("\n");
-
-                doc.set_content(sentences);
-                (doc, ranges)
-            }
-            _ => (doc, Vec::new()),
-        }
+impl Transform<Document> for Conv {
+    fn transform(&self, doc: &mut Document) -> Edits {
+        let s = self.extract_indices(doc.content().lines());
+        let (content, ranges) = RemoveShortSentences::build_content(s);
+
+        doc.set_content(content);
+
+        ranges.into()
+    }
+}
+
+impl Transform<Record<BufferedBody>> for Conv {
+    fn transform(&self, doc: &mut Record<BufferedBody>) -> Edits {
+        let stringified = String::from_utf8_lossy(doc.body());
+        let s = self.extract_indices(stringified.lines());
+
+        let (content, ranges) = RemoveShortSentences::build_content(s);
+        doc.replace_body(content);
+        ranges.into()
     }
 }
 
@@ -148,6 +254,50 @@ impl Default for Conv {
     }
 }
 
+/// Head/foot trimming strategy selection.
+///
+/// Enables choosing between [RemoveShortSentences] and [Conv] at runtime.
+/// Can be parsed from `short_sentences`, `conv` or `conv=<window size>` (window sizes must be odd).
+#[derive(Debug)]
+pub enum TrimmingKind {
+    ShortSentences(RemoveShortSentences),
+    Conv(Conv),
+}
+
+impl Default for TrimmingKind {
+    fn default() -> Self {
+        Self::ShortSentences(RemoveShortSentences::default())
+    }
+}
+
+impl FromStr for TrimmingKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('=') {
+            None if s == "short_sentences" => Ok(Self::default()),
+            None if s == "conv" => Ok(Self::Conv(Conv::default())),
+            Some(("conv", conv_size)) => conv_size
+                .parse()
+                .map_err(|e| format!("invalid convolution window size {conv_size}: {e}"))
+                .and_then(|conv_size| Conv::new(conv_size, RemoveShortSentences::default()))
+                .map(Self::Conv),
+            _ => Err(format!(
+                "unknown trimming strategy {s} (expected short_sentences, conv or conv=<odd window size>)"
+            )),
+        }
+    }
+}
+
+impl Transform<Record<BufferedBody>> for TrimmingKind {
+    fn transform(&self, doc: &mut Record<BufferedBody>) -> Edits {
+        match self {
+            Self::ShortSentences(rss) => rss.transform(doc),
+            Self::Conv(c) => c.transform(doc),
+        }
+    }
+}
+
 /// Removes short sentences that are before/after a contiguous chunk of the file.
 ///
 /// The idea is to remove contiguous short sentences that are located before and after a main body.
@@ -175,6 +325,7 @@ impl Default for Conv {
 /// xxxxxxxxxxxxxxxxxxxxxxxxxxxxxx
 /// xxxxxxxxxxxxxxxxxxxxxxxxxxxxxx
 /// ```
+#[derive(Debug)]
 pub struct RemoveShortSentences {
     filter: Length,
 }
@@ -217,14 +368,10 @@ impl RemoveShortSentences {
             }
         }
     }
-    /// get filter detection threshold
-    fn filter_min_length(&self) -> &usize {
-        self.filter.min_size()
-    }
 }
 
 impl Transform<Document> for RemoveShortSentences {
-    fn transform(&self, doc: &mut Document) -> Vec<RangeInclusive<usize>> {
+    fn transform(&self, doc: &mut Document) -> Edits {
         let lines = doc.content().lines();
 
         // TODO: fuse those two methods?
@@ -233,12 +380,12 @@ impl Transform<Document> for RemoveShortSentences {
 
         doc.set_content(content);
 
-        ranges
+        ranges.into()
     }
 }
 
 impl Transform<Record<BufferedBody>> for RemoveShortSentences {
-    fn transform(&self, doc: &mut Record<BufferedBody>) -> Vec<RangeInclusive<usize>> {
+    fn transform(&self, doc: &mut Record<BufferedBody>) -> Edits {
         let stringified = String::from_utf8_lossy(doc.body());
         let lines = stringified.lines();
         let s = self.extract_indices(lines);
@@ -247,7 +394,7 @@ impl Transform<Record<BufferedBody>> for RemoveShortSentences {
         // meaning that we keed nothing. (analogous to transform_own)
         let (content, ranges) = Self::build_content(s);
         doc.replace_body(content);
-        ranges
+        ranges.into()
     }
 }
 
@@ -263,11 +410,15 @@ impl Default for RemoveShortSentences {
 mod tests {
     use std::collections::HashMap;
 
-    use crate::filtering::sentence::Length;
+    use oscar_io::common::Identification;
+    use oxilangtag::LanguageTag;
+    use warc::{BufferedBody, Record};
+
+    use crate::filtering::sentence::{Length, MeanLengths};
     use crate::pipelines::oscardoc::types::{Document, Metadata};
     use crate::transformers::{Annotate, Transform};
 
-    use super::{RemoveShortSentences, ShortSentences};
+    use super::{Conv, LengthOutliers, RemoveShortSentences, ShortSentences, TrimmingKind};
 
     fn gen_valid() -> (Document, String) {
         let content = r"foo
@@ -390,7 +541,7 @@ baz
     #[test]
     fn test_rss_default() {
         let rss = RemoveShortSentences::default();
-        assert_eq!(rss.filter_min_length(), &100);
+        assert_eq!(rss.filter.min_size(), &100);
     }
     #[test]
     fn test_rss() {
@@ -440,34 +591,98 @@ baz
 
         println!("{:#?}", range_transformed);
 
-        assert_eq!(range_transformed, expected_range);
+        assert_eq!(range_transformed.lines(), expected_range);
     }
 
     #[test]
     fn test_rss_idx_invalid() {
         let mut doc = gen_invalid();
-        let expected_ranges = Vec::new();
-
         let rss = RemoveShortSentences::new(10);
 
         let range_transformed = rss.transform(&mut doc);
 
-        assert_eq!(range_transformed, expected_ranges);
+        assert!(range_transformed.is_empty());
+    }
+
+    #[test]
+    fn test_conv() {
+        let (doc, expected) = gen_valid_long();
+
+        // short sentences removal keeps the isolated long lines at start/end
+        let mut rss_doc = doc.clone();
+        let ranges = RemoveShortSentences::new(40).transform(&mut rss_doc);
+        assert_eq!(ranges.lines(), &[3..=37]);
+
+        let mut doc = doc;
+        let c = Conv::new(3, RemoveShortSentences::new(40)).unwrap();
+        let ranges = c.transform(&mut doc);
+        assert_eq!(ranges.lines(), &[8..=30]);
+        // fixture's expected content has no trailing whitespace
+        assert_eq!(doc.content().trim_end(), expected);
     }
 
-    // #[test]
-    // fn test_conv() {
-    //     let (doc, expected) = gen_valid_long();
-    //     let c = Conv::new(3, RemoveShortSentences::new(60));
-    //     println!(
-    //         "{:?}",
-    //         doc.content()
-    //             .lines()
-    //             .map(|line| line.len())
-    //             .collect::<Vec<usize>>()
-    //     );
-    //     println!("{:#?}", c.transform_idx(doc));
-    // }
+    #[test]
+    fn test_conv_tiny() {
+        let c = Conv::default();
+        let gen_doc =
+            |content: &str| Document::new(content.to_string(), HashMap::new(), Metadata::default());
+
+        let mut doc = gen_doc("");
+        assert!(c.transform(&mut doc).is_empty());
+        assert_eq!(doc.content(), "");
+
+        let mut doc = gen_doc("foo");
+        assert!(c.transform(&mut doc).is_empty());
+        assert_eq!(doc.content(), "");
+
+        let long = "x".repeat(150);
+        let mut doc = gen_doc(&long);
+        assert_eq!(c.transform(&mut doc).lines(), &[0..=0]);
+        assert_eq!(doc.content(), &long);
+
+        // single-line window: no convolution
+        let c = Conv::new(1, RemoveShortSentences::new(10)).unwrap();
+        let mut doc = gen_doc("foo\nxxxxxxxxxxxxxxx\nbar");
+        assert_eq!(c.transform(&mut doc).lines(), &[1..=1]);
+    }
+
+    #[test]
+    fn test_conv_size() {
+        // windows are centred, so even (and zero) sizes are rejected
+        for conv_size in [0, 2, 4] {
+            assert!(Conv::new(conv_size, RemoveShortSentences::default()).is_err());
+            assert!(format!("conv={conv_size}").parse::<TrimmingKind>().is_err());
+        }
+        for conv_size in [1, 3, 5] {
+            assert!(Conv::new(conv_size, RemoveShortSentences::default()).is_ok());
+            assert!(format!("conv={conv_size}").parse::<TrimmingKind>().is_ok());
+        }
+    }
+
+    #[test]
+    fn test_trimming_record() {
+        let (doc, expected) = gen_valid_long();
+        let mut record = Record::default().add_body(doc.content().clone());
+        let mut empty: Record<BufferedBody> = Record::default();
+
+        let trimming: TrimmingKind = "conv=3".parse().unwrap();
+        assert!(matches!(trimming, TrimmingKind::Conv(_)));
+        assert!("conv=x".parse::<TrimmingKind>().is_err());
+        assert!("conv=4".parse::<TrimmingKind>().is_err());
+        assert!("conv=0".parse::<TrimmingKind>().is_err());
+        assert!("foo".parse::<TrimmingKind>().is_err());
+
+        // default window size of 5 with a minimum length of 100 trims everything here
+        let ranges = trimming.transform(&mut record);
+        assert!(ranges.is_empty());
+        assert!(trimming.transform(&mut empty).is_empty());
+
+        let c = Conv::new(3, RemoveShortSentences::new(40)).unwrap();
+        let mut record = Record::default().add_body(doc.content().clone());
+        let ranges = c.transform(&mut record);
+        assert_eq!(ranges.lines(), &[8..=30]);
+        assert_eq!(String::from_utf8_lossy(record.body()).trim_end(), expected);
+    }
 
     //     #[test]
     //     fn test_annotate_short() {
@@ -518,4 +733,65 @@ Long enough sentence here :)"#;
         // this fails if doc is annotated with something else
         assert!(doc.metadata().annotation().is_none())
     }
+
+    #[test]
+    fn test_length_outliers() {
+        let mut lengths = MeanLengths::default();
+        for length in [8, 10, 12] {
+            lengths.detect_mut("en", &"a".repeat(length));
+            lengths.detect_mut("zh", &"字".repeat(length / 4));
+        }
+        lengths.set_nb_std(2.0);
+
+        let id = |lang: &str| {
+            Some(Identification::new(
+                LanguageTag::parse(lang.to_string()).unwrap(),
+                1.0,
+            ))
+        };
+        let content = [
+            "a".repeat(10),
+            "字".repeat(2),
+            "a".repeat(30),
+            "字".repeat(10),
+        ]
+        .join("\n");
+        let ids = vec![id("en"), id("zh"), None, id("zh")];
+        let metadata = Metadata::new(&id("en").unwrap(), &ids);
+        let mut doc = Document::new(content, HashMap::new(), metadata);
+
+        let a = LengthOutliers::new(lengths, 3);
+        a.annotate(&mut doc);
+        // the 30 "a" and 10 "字" lines
+        assert_eq!(doc.field("length_outliers"), Some(&serde_json::json!(2)));
+    }
+
+    #[test]
+    fn test_length_outliers_not_enough_measures() {
+        let mut lengths = MeanLengths::default();
+        lengths.detect_mut("en", "foo");
+        lengths.detect_mut("en", "foobar");
+
+        let (mut doc, _) = gen_valid();
+        let a = LengthOutliers::new(lengths, 100);
+        a.annotate(&mut doc);
+        assert!(doc.metadata().annotation().is_none());
+    }
+
+    #[test]
+    fn test_short_sentences_scores() {
+        let (mut doc, _) = gen_valid_long();
+        let content = r#"Long enough sentence here :)
+tiny one
+Long enough sentence here :)
+tiny one"#;
+        doc.set_content(content.to_string());
+        let mut a = ShortSentences::new(Length::with_min_size(10), 0.5);
+        a.set_scores(true);
+        a.annotate(&mut doc);
+
+        assert_eq!(doc.fields().len(), 1);
+        assert_eq!(doc.score("short_lines_ratio"), Some(0.5));
+        assert!(doc.metadata().annotation().is_none());
+    }
 }
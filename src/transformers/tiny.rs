@@ -4,18 +4,35 @@ use super::Annotate;
 
 pub struct TinyDocument {
     threshold: usize,
+    scores: bool,
 }
+
+impl TinyDocument {
+    /// Also record the line count in the `score:nb_lines` field.
+    pub fn set_scores(&mut self, scores: bool) {
+        self.scores = scores;
+    }
+}
+
 impl Annotate<Document> for TinyDocument {
     fn annotate(&self, doc: &mut Document) {
-        if doc.content().lines().count() < self.threshold {
+        let nb_lines = doc.content().lines().count();
+        if nb_lines < self.threshold {
             doc.metadata_mut().add_annotation("tiny".to_string())
         }
+
+        if self.scores {
+            doc.add_score("nb_lines", nb_lines as f64);
+        }
     }
 }
 
 impl Default for TinyDocument {
     fn default() -> Self {
-        Self { threshold: 5 }
+        Self {
+            threshold: 5,
+            scores: false,
+        }
     }
 }
 
@@ -59,4 +76,21 @@ mod tests {
 
         assert_eq!(d.metadata().annotation(), None);
     }
+
+    #[test]
+    fn test_scores() {
+        let b = "this is a short
+        short document";
+        let mut d = Document::new(b.to_string(), HashMap::new(), Metadata::default());
+
+        let mut annotator = TinyDocument::default();
+        annotator.set_scores(true);
+        annotator.annotate(&mut d);
+
+        assert_eq!(
+            d.metadata().annotation(),
+            Some(vec!["tiny".to_string()]).as_ref()
+        );
+        assert_eq!(d.score("nb_lines"), Some(2.0));
+    }
 }
@@ -1,8 +1,8 @@
 //! Transform trait.
 
-use std::ops::RangeInclusive;
+use super::Edits;
 
 pub trait Transform<T> {
-    /// Takes ownership of [Document] and returns it.
-    fn transform(&self, doc: &mut T) -> Vec<RangeInclusive<usize>>;
+    /// Transforms the document in place, returning the [Edits] that have been made on its content.
+    fn transform(&self, doc: &mut T) -> Edits;
 }
@@ -0,0 +1,285 @@
+//! Unicode repair.
+//!
+//! WET content often contains UTF-8 that has been decoded as Windows-1252/Latin-1 and re-encoded
+//! (ex. `Ã©` instead of `é`), stray control characters, and `U+FFFD` replacement characters coming from
+//! lossy decoding of invalid UTF-8.
+//!
+//! [UnicodeRepair] fixes the first two, normalizes content to NFC and counts the latter,
+//! so that identification works on clean text.
+//! Repairs are done line by line, so that they can be replayed at rebuild time (see [Edits]).
+use std::borrow::Cow;
+
+use log::debug;
+use unicode_normalization::{is_nfc, UnicodeNormalization};
+use warc::{BufferedBody, Record};
+
+use crate::pipelines::oscardoc::types::Document;
+
+use super::{Edits, Transform};
+
+/// Maximum number of mojibake repair passes, for text that has been double-encoded several times.
+const MAX_PASSES: usize = 3;
+
+/// Characters of Windows-1252 that are not in Latin-1, along with their byte.
+const CP1252: [(char, u8); 27] = [
+    ('€', 0x80),
+    ('‚', 0x82),
+    ('ƒ', 0x83),
+    ('„', 0x84),
+    ('…', 0x85),
+    ('†', 0x86),
+    ('‡', 0x87),
+    ('ˆ', 0x88),
+    ('‰', 0x89),
+    ('Š', 0x8A),
+    ('‹', 0x8B),
+    ('Œ', 0x8C),
+    ('Ž', 0x8E),
+    ('‘', 0x91),
+    ('’', 0x92),
+    ('“', 0x93),
+    ('”', 0x94),
+    ('•', 0x95),
+    ('–', 0x96),
+    ('—', 0x97),
+    ('˜', 0x98),
+    ('™', 0x99),
+    ('š', 0x9A),
+    ('›', 0x9B),
+    ('œ', 0x9C),
+    ('ž', 0x9E),
+    ('Ÿ', 0x9F),
+];
+
+/// Get the Windows-1252 (or Latin-1, for undefined Windows-1252 bytes) byte of a character, if any.
+fn cp1252_byte(c: char) -> Option<u8> {
+    match c as u32 {
+        0..=0xFF => Some(c as u8),
+        _ => CP1252
+            .iter()
+            .find(|(cp_char, _)| *cp_char == c)
+            .map(|(_, byte)| *byte),
+    }
+}
+
+/// Get the length of a UTF-8 sequence from its leading byte, for multibyte sequences.
+fn utf8_len(lead: u8) -> Option<usize> {
+    match lead {
+        0xC2..=0xDF => Some(2),
+        0xE0..=0xEF => Some(3),
+        0xF0..=0xF4 => Some(4),
+        _ => None,
+    }
+}
+
+/// Repair UTF-8 sequences that have been decoded as Windows-1252/Latin-1, returning the number of repairs.
+///
+/// A sequence is only repaired if it decodes to valid UTF-8, which rarely happens on legitimate text.
+pub fn repair_mojibake(line: &str) -> (String, usize) {
+    let chars: Vec<char> = line.chars().collect();
+    let mut repaired = String::with_capacity(line.len());
+    let mut nb_repairs = 0;
+
+    let mut idx = 0;
+    while idx < chars.len() {
+        let len = cp1252_byte(chars[idx]).and_then(utf8_len);
+        if let Some(len) = len.filter(|len| idx + len <= chars.len()) {
+            let bytes: Option<Vec<u8>> = chars[idx..idx + len]
+                .iter()
+                .enumerate()
+                .map(|(pos, c)| {
+                    // continuation bytes are in 0x80..=0xBF
+                    cp1252_byte(*c).filter(|byte| pos == 0 || (0x80..=0xBF).contains(byte))
+                })
+                .collect();
+
+            if let Some(decoded) = bytes.and_then(|bytes| String::from_utf8(bytes).ok()) {
+                repaired.push_str(&decoded);
+                nb_repairs += 1;
+                idx += len;
+                continue;
+            }
+        }
+
+        repaired.push(chars[idx]);
+        idx += 1;
+    }
+
+    (repaired, nb_repairs)
+}
+
+/// Check if a character should be removed: control characters (except tabs) and invisible characters
+/// that are not needed for shaping (zero-width space, word joiner, byte order mark).
+///
+/// Zero-width (non) joiners are kept since they are meaningful in some scripts.
+fn is_removable(c: char) -> bool {
+    (c.is_control() && c != '\t') || matches!(c, '\u{200B}' | '\u{2060}' | '\u{FEFF}')
+}
+
+/// Counts of repairs made on a content.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Repairs {
+    /// Number of repaired mojibake sequences.
+    pub mojibake: usize,
+    /// Number of removed control/invisible characters.
+    pub controls: usize,
+    /// Number of lines that were not in NFC.
+    pub nfc: usize,
+    /// Number of `U+FFFD` characters, usually coming from lossy decoding.
+    pub replacement_chars: usize,
+}
+
+impl Repairs {
+    /// Add non-zero counts as `unicode:<repair>` fields.
+    pub fn add_to(&self, doc: &mut Document) {
+        let counts = [
+            ("mojibake", self.mojibake),
+            ("controls", self.controls),
+            ("nfc", self.nfc),
+            ("replacement_chars", self.replacement_chars),
+        ];
+        for (name, count) in counts.into_iter().filter(|(_, count)| *count > 0) {
+            doc.add_field(&format!("unicode:{name}"), count);
+        }
+    }
+}
+
+/// Repairs mojibake, removes control characters and normalizes to NFC.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct UnicodeRepair;
+
+impl UnicodeRepair {
+    /// Repair a single line.
+    fn repair_line<'a>(&self, line: &'a str, repairs: &mut Repairs) -> Cow<'a, str> {
+        repairs.replacement_chars += line.matches('\u{FFFD}').count();
+        let mut line = Cow::Borrowed(line);
+
+        // only non-ASCII content can be mojibake
+        if !line.is_ascii() {
+            for _ in 0..MAX_PASSES {
+                let (repaired, nb_repairs) = repair_mojibake(&line);
+                if nb_repairs == 0 {
+                    break;
+                }
+                repairs.mojibake += nb_repairs;
+                line = Cow::Owned(repaired);
+            }
+        }
+
+        let nb_removable = line.chars().filter(|c| is_removable(*c)).count();
+        if nb_removable > 0 {
+            repairs.controls += nb_removable;
+            line = Cow::Owned(line.chars().filter(|c| !is_removable(*c)).collect());
+        }
+
+        if !is_nfc(&line) {
+            repairs.nfc += 1;
+            line = Cow::Owned(line.nfc().collect());
+        }
+
+        line
+    }
+
+    /// Repair content, returning the repaired content along with repair counts.
+    ///
+    /// Lines are repaired independently, and the repaired content has the same number of lines.
+    pub fn repair(&self, content: &str) -> (String, Repairs) {
+        let mut repairs = Repairs::default();
+        let repaired = content
+            .lines()
+            .map(|line| self.repair_line(line, &mut repairs))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        (repaired, repairs)
+    }
+
+    /// Repair the record body, returning the edits made along with repair counts.
+    pub fn repair_record(&self, doc: &mut Record<BufferedBody>) -> (Edits, Repairs) {
+        let body = String::from_utf8_lossy(doc.body()).into_owned();
+        let (content, repairs) = self.repair(&body);
+        let edits = Edits::replacing(&body, &content);
+
+        if !edits.replacements().is_empty() {
+            debug!("record {} repaired: {:?}", doc.warc_id(), repairs);
+            doc.replace_body(content);
+        }
+
+        (edits, repairs)
+    }
+}
+
+impl Transform<Record<BufferedBody>> for UnicodeRepair {
+    fn transform(&self, doc: &mut Record<BufferedBody>) -> Edits {
+        self.repair_record(doc).0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use warc::{BufferedBody, Record};
+
+    use crate::pipelines::oscardoc::types::{Document, Metadata};
+    use crate::transformers::Transform;
+
+    use super::{repair_mojibake, Repairs, UnicodeRepair};
+
+    #[test]
+    fn test_mojibake() {
+        assert_eq!(
+            repair_mojibake("cafÃ© dÃ©jÃ\u{a0}vu"),
+            ("café déjàvu".to_string(), 3)
+        );
+        assert_eq!(repair_mojibake("itâ€™s"), ("it’s".to_string(), 1));
+
+        // legitimate text is left untouched
+        assert_eq!(
+            repair_mojibake("Ã la carte © 2021"),
+            ("Ã la carte © 2021".to_string(), 0)
+        );
+        assert_eq!(repair_mojibake("日本語"), ("日本語".to_string(), 0));
+    }
+
+    #[test]
+    fn test_repair() {
+        let ur = UnicodeRepair;
+        // encoded twice, C1 control, zero-width space, decomposed é, replacement char
+        let content = "cafÃƒÂ©\u{0085}\nfoo\u{200B}bar\ne\u{0301}t\u{00e9}\n\u{FFFD}";
+        let (repaired, repairs) = ur.repair(content);
+
+        assert_eq!(repaired, "café\nfoobar\n\u{00e9}t\u{00e9}\n\u{FFFD}");
+        assert_eq!(
+            repairs,
+            Repairs {
+                mojibake: 3,
+                controls: 2,
+                nfc: 1,
+                replacement_chars: 1,
+            }
+        );
+
+        let mut doc = Document::new(String::new(), HashMap::new(), Metadata::default());
+        repairs.add_to(&mut doc);
+        assert_eq!(doc.field("unicode:mojibake"), Some(&3.into()));
+        assert_eq!(doc.field("unicode:replacement_chars"), Some(&1.into()));
+    }
+
+    #[test]
+    fn test_transform_record() {
+        let ur = UnicodeRepair;
+        let mut record: Record<BufferedBody> = Record::default().add_body("foo\nGrÃ¼ÃŸe\nbar");
+        let edits = ur.transform(&mut record);
+
+        assert_eq!(record.body(), "foo\nGrüße\nbar".as_bytes());
+        assert_eq!(edits.lines(), &[0..=2]);
+        assert_eq!(edits.replacements().len(), 1);
+
+        // clean records are not modified
+        let mut record: Record<BufferedBody> = Record::default().add_body("foo\nbar\n");
+        let edits = ur.transform(&mut record);
+        assert_eq!(record.body(), b"foo\nbar\n");
+        assert!(edits.replacements().is_empty());
+    }
+}
@@ -0,0 +1,355 @@
+/*! URL metadata.
+
+[UrlMetadata] parses the `WARC-Target-URI` of documents and adds the following fields:
+
+- `url:domain:<domain>`: registered domain (ex. `example.co.uk` for `https://www.example.co.uk/foo`),
+  using the embedded [Public Suffix List](https://publicsuffix.org/) (see [PublicSuffixList]),
+- `url:tld:<tld>`: top-level domain (ex. `uk`),
+- `url:cctld:<cctld>`: country-code top-level domain, if the top-level domain is one (ex. `uk`),
+- `url:depth:<depth>`: number of non-empty path segments (ex. `2` for `/foo/bar/`),
+- `url:listing:<kind>`: if the URL looks like a listing page rather than content (see [ListingKind]).
+
+Domain fields are not added for IP addresses.
+These enable per-domain statistics and capping, and ccTLD-based sanity checks of language identification.
+!*/
+use std::collections::HashSet;
+
+use lazy_static::lazy_static;
+use serde_json::Value;
+use url::{Host, Url};
+
+use crate::pipelines::oscardoc::types::Document;
+
+use super::Annotate;
+
+lazy_static! {
+    static ref PUBLIC_SUFFIXES: PublicSuffixList =
+        PublicSuffixList::from_list(include_str!("../../res/public_suffix_list.dat"));
+}
+
+/// Query keys of search pages.
+const SEARCH_KEYS: [&str; 6] = ["q", "s", "query", "search", "keyword", "keywords"];
+/// Query keys of paginated pages.
+const PAGE_KEYS: [&str; 2] = ["page", "p"];
+
+/// Public suffixes (ex. `com`, `co.uk`, `github.io`), against which registered domains are found.
+///
+/// Follows the [Public Suffix List algorithm](https://publicsuffix.org/list/), with wildcard (`*.ck`)
+/// and exception (`!www.ck`) rules. Internationalized rules are stored as punycode,
+/// to match hosts as returned by [Url::host_str].
+#[derive(Debug, Default)]
+pub struct PublicSuffixList {
+    rules: HashSet<String>,
+    /// parents of wildcard rules (`ck` for `*.ck`)
+    wildcards: HashSet<String>,
+    exceptions: HashSet<String>,
+}
+
+impl PublicSuffixList {
+    /// Parse a list in the Public Suffix List format.
+    pub fn from_list(list: &str) -> Self {
+        let mut psl = Self::default();
+        for line in list.lines() {
+            let rule = match line.split_whitespace().next() {
+                Some(rule) if !rule.starts_with("//") => rule,
+                _ => continue,
+            };
+            let (set, rule) = if let Some(rule) = rule.strip_prefix('!') {
+                (&mut psl.exceptions, rule)
+            } else if let Some(rule) = rule.strip_prefix("*.") {
+                (&mut psl.wildcards, rule)
+            } else {
+                (&mut psl.rules, rule)
+            };
+
+            if rule.is_ascii() {
+                set.insert(rule.to_lowercase());
+            } else if let Some(rule) = Url::parse(&format!("http://{rule}"))
+                .ok()
+                .and_then(|url| url.host_str().map(String::from))
+            {
+                set.insert(rule);
+            }
+        }
+        psl
+    }
+
+    /// Get the embedded list.
+    pub fn embedded() -> &'static Self {
+        &PUBLIC_SUFFIXES
+    }
+
+    /// Get the public suffix of a (lowercase) domain. Unlisted top-level domains are public suffixes.
+    pub fn public_suffix<'a>(&self, domain: &'a str) -> &'a str {
+        let domain = domain.trim_end_matches('.');
+        let mut suffix = domain.rsplit('.').next().unwrap_or(domain);
+
+        // candidates from the longest to the shortest
+        let mut candidate = Some(domain);
+        while let Some(current) = candidate {
+            let parent = current.split_once('.').map(|(_, parent)| parent);
+            if self.exceptions.contains(current) {
+                suffix = parent.unwrap_or(current);
+                break;
+            }
+            if self.rules.contains(current) || parent.is_some_and(|p| self.wildcards.contains(p)) {
+                suffix = current;
+                break;
+            }
+            candidate = parent;
+        }
+        suffix
+    }
+
+    /// Get the registered domain (public suffix and one more label) of a (lowercase) domain.
+    /// Returns [None] if the domain is a public suffix.
+    pub fn registered_domain<'a>(&self, domain: &'a str) -> Option<&'a str> {
+        let domain = domain.trim_end_matches('.');
+        let suffix = self.public_suffix(domain);
+        let prefix = domain.strip_suffix(suffix)?.strip_suffix('.')?;
+        let start = prefix.rfind('.').map_or(0, |idx| idx + 1);
+        Some(&domain[start..])
+    }
+}
+
+/// Kinds of listing pages, in detection priority order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListingKind {
+    /// `/search/...`, or a search query (`?q=`, `?s=`...).
+    Search,
+    /// `/tag/...`, `/tags/...`, `/label/...`.
+    Tag,
+    /// `/category/...`, `/topic/...`.
+    Category,
+    /// `/archive/...`, or a date archive (`/2021/`, `/2021/05/`).
+    Archive,
+    /// `/page/2`, or a page query (`?page=2`).
+    Page,
+}
+
+impl ListingKind {
+    /// Get the name of the kind, used in metadata fields.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Search => "search",
+            Self::Tag => "tag",
+            Self::Category => "category",
+            Self::Archive => "archive",
+            Self::Page => "page",
+        }
+    }
+
+    /// Get the kind of listing the URL looks like, if any.
+    pub fn detect(url: &Url) -> Option<Self> {
+        let segments: Vec<String> = url
+            .path_segments()
+            .map(|segments| {
+                segments
+                    .filter(|segment| !segment.is_empty())
+                    .map(str::to_lowercase)
+                    .collect()
+            })
+            .unwrap_or_default();
+        let has_segment = |names: &[&str]| segments.iter().any(|s| names.contains(&s.as_str()));
+        let has_query = |keys: &[&str], numeric: bool| {
+            url.query_pairs().any(|(key, value)| {
+                keys.contains(&key.as_ref())
+                    && !value.is_empty()
+                    && (!numeric || value.chars().all(|c| c.is_ascii_digit()))
+            })
+        };
+        let is_number = |segment: &String| segment.chars().all(|c| c.is_ascii_digit());
+
+        if has_segment(&["search"]) || has_query(&SEARCH_KEYS, false) {
+            Some(Self::Search)
+        } else if has_segment(&["tag", "tags", "label", "labels", "hashtag"]) {
+            Some(Self::Tag)
+        } else if has_segment(&["category", "categories", "topic", "topics"]) {
+            Some(Self::Category)
+        } else if has_segment(&["archive", "archives"])
+            || (!segments.is_empty()
+                && segments.len() <= 2
+                && segments[0].len() == 4
+                && segments.iter().all(is_number))
+        {
+            Some(Self::Archive)
+        } else if segments
+            .windows(2)
+            .any(|window| window[0] == "page" && is_number(&window[1]))
+            || has_query(&PAGE_KEYS, true)
+        {
+            Some(Self::Page)
+        } else {
+            None
+        }
+    }
+}
+
+/// Adds domain, path depth and listing fields from the document URL (see [module](self) documentation).
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UrlMetadata;
+
+impl UrlMetadata {
+    /// Get the `(key, value)` fields of a URL.
+    pub fn fields(url: &Url) -> Vec<(&'static str, Value)> {
+        let mut fields = Vec::new();
+
+        if let Some(Host::Domain(domain)) = url.host() {
+            let psl = PublicSuffixList::embedded();
+            let domain = domain.trim_end_matches('.');
+            if let Some(registered) = psl.registered_domain(domain) {
+                fields.push(("url:domain", registered.into()));
+            }
+            if let Some(tld) = domain.rsplit('.').next().filter(|tld| !tld.is_empty()) {
+                fields.push(("url:tld", tld.into()));
+                if tld.len() == 2 && tld.chars().all(|c| c.is_ascii_alphabetic()) {
+                    fields.push(("url:cctld", tld.into()));
+                }
+            }
+        }
+
+        let depth = url
+            .path_segments()
+            .map_or(0, |segments| segments.filter(|s| !s.is_empty()).count());
+        fields.push(("url:depth", depth.into()));
+
+        if let Some(listing) = ListingKind::detect(url) {
+            fields.push(("url:listing", listing.name().into()));
+        }
+
+        fields
+    }
+}
+
+impl Annotate<Document> for UrlMetadata {
+    fn annotate(&self, doc: &mut Document) {
+        let url = match doc.url().and_then(|url| Url::parse(&url).ok()) {
+            Some(url) => url,
+            None => return,
+        };
+        for (key, value) in Self::fields(&url) {
+            doc.add_field(key, value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde_json::json;
+    use url::Url;
+    use warc::WarcHeader;
+
+    use crate::pipelines::oscardoc::types::{Document, Metadata};
+    use crate::transformers::Annotate;
+
+    use super::{ListingKind, PublicSuffixList, UrlMetadata};
+
+    fn listing(url: &str) -> Option<ListingKind> {
+        ListingKind::detect(&Url::parse(url).unwrap())
+    }
+
+    #[test]
+    fn test_public_suffix() {
+        let psl = PublicSuffixList::from_list(
+            "// comment\ncom\nuk\nco.uk\n*.ck\n!www.ck\ngithub.io\n公司.cn\n",
+        );
+
+        assert_eq!(psl.public_suffix("www.example.com"), "com");
+        assert_eq!(
+            psl.registered_domain("www.example.com"),
+            Some("example.com")
+        );
+        assert_eq!(
+            psl.registered_domain("a.b.example.co.uk"),
+            Some("example.co.uk")
+        );
+        assert_eq!(
+            psl.registered_domain("foo.github.io"),
+            Some("foo.github.io")
+        );
+        // unlisted top-level domain
+        assert_eq!(
+            psl.registered_domain("foo.bar.example"),
+            Some("bar.example")
+        );
+        // wildcard and exception
+        assert_eq!(psl.registered_domain("a.foo.ck"), Some("a.foo.ck"));
+        assert_eq!(psl.registered_domain("a.www.ck"), Some("www.ck"));
+        // internationalized rules are stored as punycode
+        assert_eq!(psl.public_suffix("foo.xn--55qx5d.cn"), "xn--55qx5d.cn");
+        // public suffixes have no registered domain
+        assert_eq!(psl.registered_domain("co.uk"), None);
+        assert_eq!(psl.registered_domain("foo.ck"), None);
+        assert_eq!(psl.registered_domain("localhost"), None);
+    }
+
+    #[test]
+    fn test_embedded() {
+        let psl = PublicSuffixList::embedded();
+        assert_eq!(psl.registered_domain("www.bbc.co.uk"), Some("bbc.co.uk"));
+        assert_eq!(
+            psl.registered_domain("foo.blogspot.com"),
+            Some("foo.blogspot.com")
+        );
+    }
+
+    #[test]
+    fn test_listing() {
+        assert_eq!(
+            listing("https://foo.bar/search/baz"),
+            Some(ListingKind::Search)
+        );
+        assert_eq!(listing("https://foo.bar/?s=baz"), Some(ListingKind::Search));
+        assert_eq!(
+            listing("https://foo.bar/blog/tag/rust"),
+            Some(ListingKind::Tag)
+        );
+        assert_eq!(
+            listing("https://foo.bar/category/news/"),
+            Some(ListingKind::Category)
+        );
+        assert_eq!(
+            listing("https://foo.bar/2021/05/"),
+            Some(ListingKind::Archive)
+        );
+        assert_eq!(
+            listing("https://foo.bar/news/page/2"),
+            Some(ListingKind::Page)
+        );
+        assert_eq!(
+            listing("https://foo.bar/news?page=3"),
+            Some(ListingKind::Page)
+        );
+
+        assert_eq!(listing("https://foo.bar/2021/05/some-article"), None);
+        assert_eq!(listing("https://foo.bar/news?page=about"), None);
+        assert_eq!(listing("https://foo.bar/"), None);
+    }
+
+    #[test]
+    fn test_annotate() {
+        let mut headers = HashMap::new();
+        headers.insert(
+            WarcHeader::TargetURI,
+            b"https://www.example.co.uk/news/tag/foo/".to_vec(),
+        );
+        let mut doc = Document::new(String::new(), headers, Metadata::default());
+        UrlMetadata.annotate(&mut doc);
+
+        assert_eq!(doc.field("url:domain"), Some(&json!("example.co.uk")));
+        assert_eq!(doc.field("url:tld"), Some(&json!("uk")));
+        assert_eq!(doc.field("url:cctld"), Some(&json!("uk")));
+        assert_eq!(doc.field("url:depth"), Some(&json!(3)));
+        assert_eq!(doc.field("url:listing"), Some(&json!("tag")));
+
+        // no domain fields for IP addresses, no ccTLD for generic TLDs
+        let url = Url::parse("http://127.0.0.1/foo").unwrap();
+        assert_eq!(UrlMetadata::fields(&url), vec![("url:depth", json!(1))]);
+        let url = Url::parse("https://example.com").unwrap();
+        let fields = UrlMetadata::fields(&url);
+        assert!(fields.iter().all(|(key, _)| *key != "url:cctld"));
+        assert!(fields.contains(&("url:depth", json!(0))));
+    }
+}